@@ -1,24 +1,26 @@
 use std::net::SocketAddr;
 
-use axum::{
-    http::Method, Router,
-};
-use tower_http::cors::{Any, CorsLayer};
+use server::{build_app, config::Config, erasure, mail, migrations, pool, state::AppState};
 
+// run the server embedded in the desktop app on the given port
 pub async fn app(port: u16) {
-    let app = Router::new()
-        .layer(CorsLayer::new().allow_origin(Any).allow_methods(vec![
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::PATCH,
-            Method::DELETE,
-        ]));
+    let config = Config::load()
+        .unwrap_or_else(|error| panic!("Invalid configuration: {}", error));
+    let pool = pool::create_pool(&config.database).await
+        .unwrap_or_else(|error| panic!("Could not create pool: {}", error));
+    // desktop users have no sqlx-cli, so the embedded migrations are the only way to set up the database
+    if config.database.run_migrations {
+        migrations::run(&pool).await
+            .unwrap_or_else(|error| panic!("Could not run migrations: {}", error));
+    }
+    let mailer = mail::from_config(&config)
+        .unwrap_or_else(|error| panic!("Could not create mailer: {}", error));
+    let state = AppState::new(pool, config, mailer);
+    erasure::spawn(state.users.clone(), state.config.accounts.clone());
+    let app = build_app(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     println!("Backend is listening on http://{}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
 }
@@ -75,7 +75,7 @@ pub fn login_form() -> Html {
     html! {
         <form class="flex flex-col w-64 space-y-2" onsubmit={login_onsubmit}>
             if let Some(error) = (*error_state).to_owned() {
-                <ErrorMessage message={error.body().message} />
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
             }
             <Input input_type="text" placeholder="Username" oninput={oninput.clone()("username", &error_state)} value={login_user.username.to_owned()} />
             <Input input_type="password" placeholder="Password" oninput={oninput.clone()("pass", &error_state)} value={login_user.pass.to_owned()} />
@@ -1,4 +1,4 @@
-use types::user::RegisterUser;
+use types::{auth::AuthErrorType, user::RegisterUser, username::{validate_username, DEFAULT_RESERVED_USERNAMES}};
 use web_sys::HtmlInputElement;
 use yew::{function_component, html, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast, UseStateHandle};
 use yew_hooks::use_async;
@@ -35,6 +35,12 @@ pub fn register_form() -> Html {
         let register_user = register_user.clone();
         let error_state = error_state.clone();
         use_async(async move {
+            // check username rules before sending to server
+            if let Err(error) = validate_username(&register_user.username, DEFAULT_RESERVED_USERNAMES) {
+                let error = AuthError::from_error_type(AuthErrorType::InvalidUsername).with_message(error.to_string());
+                error_state.set(Some(error.to_owned()));
+                return Err(error);
+            }
             let response = services::auth::register_user((*register_user).clone()).await;
             match response {
                 Ok(user_info) => {
@@ -69,7 +75,7 @@ pub fn register_form() -> Html {
     html! {
         <form class="flex flex-col w-64 space-y-2" onsubmit={register_onsubmit}>
             if let Some(error) = (*error_state).to_owned() {
-                <ErrorMessage message={error.body().message} />
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
             }
             <Input input_type="text" placeholder="Username" oninput={oninput("username", &error_state)} value={register_user.username.to_owned()} />
             <Input input_type="password" placeholder="Password" oninput={oninput("pass", &error_state)} value={register_user.pass.to_owned()} />
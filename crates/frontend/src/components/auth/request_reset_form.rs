@@ -63,7 +63,7 @@ pub fn request_reset_form() -> Html {
             text-slate-800 dark:text-slate-100" onsubmit={reset_onsubmit}>
             <p>{"Enter your email to reset your password"}</p>
             if let Some(error) = (*error_state).to_owned() {
-                <ErrorMessage message={error.body().message} />
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
             }
             <Input input_type="email" placeholder="Email" oninput={oninput(&error_state)} value={(*reset_email).to_owned()} />
             <Button onclick={reset_onclick} label="Confirm" />
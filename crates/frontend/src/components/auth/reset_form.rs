@@ -108,7 +108,7 @@ pub fn reset_form() -> Html {
             text-slate-800 dark:text-slate-100" onsubmit={reset_onsubmit}>
             <p>{"Enter your new password"}</p>
             if let Some(error) = (*error_state).to_owned() {
-                <ErrorMessage message={error.body().message} />
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
             }
             <Input input_type="password" placeholder="Password" oninput={oninput("pass", &error_state)} value={reset_user.pass.to_owned()} />
             <Input input_type="password" placeholder="Confirm password" oninput={on_confirm_input(&error_state)} value={(*confirm_pass).to_owned()} />
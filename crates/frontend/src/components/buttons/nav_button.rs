@@ -8,7 +8,10 @@ pub struct Props {
     pub label: String,
     pub destination: AppRoute,
     #[prop_or(false)]
-    pub disabled: bool
+    pub disabled: bool,
+    // count shown next to the label when above zero
+    #[prop_or(0)]
+    pub badge: i64
 }
 
 #[function_component(NavButton)]
@@ -26,6 +29,9 @@ pub fn navbutton(props: &Props) -> Html {
                     dark:bg-slate-900 dark:text-slate-100 dark:hover:bg-slate-800"
                     disabled={props.disabled}>
                 {props.label}
+                if props.badge > 0 {
+                    <span class="ml-2 px-2 rounded-full text-xs bg-red-500 text-white">{props.badge}</span>
+                }
             </div>
         </Link<AppRoute>>
     }
@@ -0,0 +1,217 @@
+use types::chat::{ChatContact, CreateRoom, Room, DEFAULT_ROOM};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_hooks::prelude::*;
+
+use crate::{services::{self, AuthError}, components::{buttons::button::Button, error_message::ErrorMessage, input::Input}};
+
+const ACTIVE_COLOR: &str = "bg-slate-300 text-slate-800 dark:bg-slate-700 dark:text-slate-100";
+
+// Conversation shown in the chat window
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatTarget {
+    Room(String),
+    Direct(ChatContact)
+}
+
+impl Default for ChatTarget {
+    fn default() -> Self {
+        ChatTarget::Room(DEFAULT_ROOM.to_string())
+    }
+}
+
+impl ChatTarget {
+    // key the lines of the conversation are stored under
+    pub fn key(&self, own_uuid: &str) -> String {
+        match self {
+            ChatTarget::Room(room) => room_key(room),
+            ChatTarget::Direct(contact) => direct_key(own_uuid, &contact.uuid)
+        }
+    }
+}
+
+pub fn room_key(room: &str) -> String {
+    format!("#{}", room)
+}
+
+// both participants share the key of a direct conversation
+pub fn direct_key(uuid: &str, other_uuid: &str) -> String {
+    if uuid < other_uuid {
+        format!("@{}:{}", uuid, other_uuid)
+    } else {
+        format!("@{}:{}", other_uuid, uuid)
+    }
+}
+
+#[derive(Clone, Properties, PartialEq)]
+pub struct Props {
+    pub active: ChatTarget,
+    pub onselect: Callback<ChatTarget>,
+    #[prop_or(String::new())]
+    pub class: String
+}
+
+#[function_component(ChatSidebar)]
+pub fn chat_sidebar(props: &Props) -> Html {
+    let props = props.clone();
+    let error_state = use_state(|| None::<AuthError>);
+    let room_name = use_state(|| String::new());
+    let room_is_private = use_state(|| false);
+    let member_uuid = use_state(|| String::new());
+    let direct_uuid = use_state(|| String::new());
+
+    let rooms = {
+        let error_state = error_state.clone();
+        use_async_with_options(async move {
+            services::chat::get_rooms().await.map_err(|error| {
+                error_state.set(Some(error));
+            })
+        }, UseAsyncOptions::enable_auto())
+    };
+
+    let contacts = {
+        let error_state = error_state.clone();
+        use_async_with_options(async move {
+            services::chat::get_contacts().await.map_err(|error| {
+                error_state.set(Some(error));
+            })
+        }, UseAsyncOptions::enable_auto())
+    };
+
+    let handle_create = {
+        let error_state = error_state.clone();
+        let room_name = room_name.clone();
+        let is_private = *room_is_private;
+        let rooms = rooms.clone();
+        let onselect = props.onselect.clone();
+        use_async(async move {
+            let room = CreateRoom { name: (*room_name).clone(), is_private };
+            match services::chat::create_room(&room).await {
+                Ok(room) => {
+                    error_state.set(None);
+                    room_name.set(String::new());
+                    rooms.run();
+                    onselect.emit(ChatTarget::Room(room.name));
+                    Ok(())
+                },
+                Err(error) => {
+                    error_state.set(Some(error));
+                    Err(())
+                }
+            }
+        })
+    };
+
+    let handle_add_member = {
+        let error_state = error_state.clone();
+        let member_uuid = member_uuid.clone();
+        let active = props.active.clone();
+        use_async(async move {
+            let ChatTarget::Room(room) = active else {
+                return Err(());
+            };
+            match services::chat::add_room_member(&room, (*member_uuid).clone()).await {
+                Ok(()) => {
+                    error_state.set(None);
+                    member_uuid.set(String::new());
+                    Ok(())
+                },
+                Err(error) => {
+                    error_state.set(Some(error));
+                    Err(())
+                }
+            }
+        })
+    };
+
+    let input_callback = |state: &UseStateHandle<String>| {
+        let state = state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            state.set(input.value());
+        })
+    };
+
+    let create_onclick = {
+        let handle_create = handle_create.clone();
+        Callback::from(move |_| handle_create.run())
+    };
+    let private_onclick = {
+        let room_is_private = room_is_private.clone();
+        Callback::from(move |_| room_is_private.set(!*room_is_private))
+    };
+    let add_member_onclick = {
+        let handle_add_member = handle_add_member.clone();
+        Callback::from(move |_| handle_add_member.run())
+    };
+    // a new conversation is named after the uuid until its first message arrives
+    let direct_onclick = {
+        let direct_uuid = direct_uuid.clone();
+        let onselect = props.onselect.clone();
+        Callback::from(move |_| {
+            let uuid = (*direct_uuid).trim().to_string();
+            if uuid.is_empty() {
+                return;
+            }
+            onselect.emit(ChatTarget::Direct(ChatContact { uuid: uuid.clone(), username: uuid }));
+            direct_uuid.set(String::new());
+        })
+    };
+
+    let is_private_room = match &props.active {
+        ChatTarget::Room(name) => rooms.data.iter().flatten().any(|room: &Room| &room.name == name && room.is_private),
+        ChatTarget::Direct(_) => false
+    };
+
+    html! {
+        <div class={props.class}>
+            <h3 class="font-bold">{"Rooms"}</h3>
+            {
+                for rooms.data.iter().flatten().map(|room| {
+                    let target = ChatTarget::Room(room.name.clone());
+                    let onselect = props.onselect.clone();
+                    let onclick = {
+                        let target = target.clone();
+                        Callback::from(move |_| onselect.emit(target.clone()))
+                    };
+                    let label = if room.is_private { format!("🔒 {}", room.name) } else { format!("# {}", room.name) };
+                    if target == props.active {
+                        html! { <Button onclick={onclick} label={label} color={ACTIVE_COLOR} /> }
+                    } else {
+                        html! { <Button onclick={onclick} label={label} /> }
+                    }
+                })
+            }
+            <Input input_type="text" placeholder="New room..." oninput={input_callback(&room_name)} value={(*room_name).to_owned()} />
+            <div class="flex flex-row space-x-2">
+                <Button onclick={private_onclick} label={if *room_is_private { "Private: yes" } else { "Private: no" }} />
+                <Button onclick={create_onclick} label={"Create"} disabled={handle_create.loading || room_name.is_empty()} />
+            </div>
+            if is_private_room {
+                <Input input_type="text" placeholder="User UUID..." oninput={input_callback(&member_uuid)} value={(*member_uuid).to_owned()} />
+                <Button onclick={add_member_onclick} label={"Invite"} disabled={handle_add_member.loading || member_uuid.is_empty()} />
+            }
+            <h3 class="font-bold">{"Direct Messages"}</h3>
+            {
+                for contacts.data.iter().flatten().map(|contact| {
+                    let target = ChatTarget::Direct(contact.clone());
+                    let onselect = props.onselect.clone();
+                    let onclick = {
+                        let target = target.clone();
+                        Callback::from(move |_| onselect.emit(target.clone()))
+                    };
+                    if target == props.active {
+                        html! { <Button onclick={onclick} label={contact.username.clone()} color={ACTIVE_COLOR} /> }
+                    } else {
+                        html! { <Button onclick={onclick} label={contact.username.clone()} /> }
+                    }
+                })
+            }
+            <Input input_type="text" placeholder="User UUID..." oninput={input_callback(&direct_uuid)} value={(*direct_uuid).to_owned()} />
+            <Button onclick={direct_onclick} label={"Message"} disabled={direct_uuid.is_empty()} />
+            if let Some(error) = (*error_state).clone() {
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
+            }
+        </div>
+    }
+}
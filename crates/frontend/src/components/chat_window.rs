@@ -1,23 +1,166 @@
+use std::{cell::{Cell, RefCell}, collections::{HashMap, HashSet}, rc::Rc, time::Duration};
+
+use gloo_console::error;
 use tauri_sys::tauri::invoke;
+use types::chat::{ChatContact, ChatErrorType, ChatHistoryQuery, ChatMessage, ClientMessage, ReadMarker, RoomResume, ServerMessage, CHAT_PROTOCOL_VERSION, DEFAULT_ROOM, TYPING_THROTTLE_SECS};
 use web_sys::{HtmlInputElement, WebSocket};
 use yew::prelude::*;
 use yew_hooks::prelude::*;
 
-use crate::{services::AuthStorage, graphics::icons::send_icon::SendIcon, components::{buttons::button::Button, input::Input}};
+use crate::{services::{self, AuthStorage}, graphics::icons::send_icon::SendIcon, hooks::use_user_info,
+    components::{buttons::button::Button, chat_sidebar::{direct_key, room_key, ChatTarget}, input::Input}};
+
+// how often the session expiry is checked
+const SESSION_CHECK_MILLIS: u32 = 10_000;
+// a newer requester token is sent this long before the session expires
+const SESSION_REFRESH_LEAD_SECS: u64 = 60;
+// delay before the first reconnect, doubled for every failed attempt up to the maximum
+const RECONNECT_BASE_MILLIS: u32 = 1_000;
+const RECONNECT_MAX_MILLIS: u32 = 30_000;
+
+// conversation key, message id and rendered text of a shown line
+type Line = (String, Option<i64>, String);
+
+// state of the chat socket shown below the messages
+#[derive(Clone, Debug, PartialEq)]
+enum ConnectionStatus {
+    Connecting,
+    Connected,
+    // attempt counts from 1, the socket is opened again after delay_millis
+    Reconnecting { attempt: u32, delay_millis: u32 },
+    // reconnecting cannot help, the reason is shown
+    Closed(String)
+}
+
+fn reconnect_delay(attempt: u32) -> u32 {
+    RECONNECT_BASE_MILLIS
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(RECONNECT_MAX_MILLIS)
+}
+
+// errors of the handshake or session the same token would run into again
+fn is_fatal(error: ChatErrorType) -> bool {
+    matches!(error, ChatErrorType::InvalidToken | ChatErrorType::TokenExpired | ChatErrorType::SessionRevoked | ChatErrorType::UnsupportedVersion)
+}
+
+// drop the lines of conversations, their newest page is loaded once they are shown
+fn reset_conversations(
+    keys: &HashSet<String>,
+    history: &UseListHandle<Line>,
+    cursors: &RefCell<HashMap<String, i64>>,
+    exhausted: &RefCell<HashSet<String>>,
+    stale: &RefCell<HashSet<String>>
+) {
+    for key in keys {
+        cursors.borrow_mut().remove(key);
+        exhausted.borrow_mut().remove(key);
+        stale.borrow_mut().insert(key.clone());
+    }
+    let lines: Vec<Line> = history.current().iter()
+        .filter(|(key, _, _)| !keys.contains(key))
+        .cloned()
+        .collect();
+    history.set(lines);
+}
+
+// keys of every direct conversation shown or loaded so far
+fn direct_keys(history: &UseListHandle<Line>, cursors: &RefCell<HashMap<String, i64>>) -> HashSet<String> {
+    history.current().iter()
+        .map(|(key, _, _)| key.clone())
+        .chain(cursors.borrow().keys().cloned())
+        .filter(|key| key.starts_with('@'))
+        .collect()
+}
 
 #[derive(Clone, Properties, PartialEq)]
 pub struct Props {
+    #[prop_or_default]
+    pub target: ChatTarget,
+    // called with a direct conversation when an online user is clicked
+    #[prop_or_default]
+    pub onselect: Option<Callback<ChatTarget>>,
     #[prop_or(String::new())]
     pub class: String
 }
 
+// encode a message to the target, ids only have to be unique per connection
+fn chat_frame(next_id: &RefCell<u64>, target: &ChatTarget, body: String) -> String {
+    let mut id = next_id.borrow_mut();
+    *id += 1;
+    let message = match target {
+        ChatTarget::Room(room) => ClientMessage::Chat { id: *id, room: room.clone(), body },
+        ChatTarget::Direct(contact) => ClientMessage::Direct { id: *id, to: contact.uuid.clone(), body }
+    };
+    serde_json::to_string(&message).unwrap_or_default()
+}
+
+// key of the conversation a message belongs to
+fn message_key(message: &ChatMessage) -> String {
+    match (&message.room, &message.recipient_uuid) {
+        (Some(room), _) => room_key(room),
+        (None, Some(recipient_uuid)) => direct_key(&message.author_uuid, recipient_uuid),
+        (None, None) => String::new()
+    }
+}
+
 #[function_component(ChatWindow)]
 pub fn chat_windows(props: &Props) -> Html {
     let props = props.clone();
     let chat_disabled = use_state(|| true);
     let chat_message = use_state(|| String::new());
 
-    let history = use_list(vec![]);
+    let user_info = use_user_info();
+    let target_key = props.target.key(&user_info.uuid);
+    // lines of every conversation, tagged with its key and the id of their message
+    let history = use_list(Vec::<Line>::new());
+    // id of the oldest message shown per conversation, older pages are loaded before it
+    let cursors = use_mut_ref(HashMap::<String, i64>::new);
+    // conversations without older messages
+    let exhausted = use_mut_ref(HashSet::<String>::new);
+    // conversations cleared after the server skipped frames of them, reloaded when shown
+    let stale = use_mut_ref(HashSet::<String>::new);
+    // the server joins the default room after the handshake
+    let joined = use_mut_ref(|| HashSet::from([DEFAULT_ROOM.to_string()]));
+    // errors are shown in the conversation open when they arrive
+    let active_key = use_mut_ref(String::new);
+    *active_key.borrow_mut() = target_key.clone();
+    let next_id = use_mut_ref(|| 0u64);
+    // users with an open connection, ordered by username
+    let online = use_list(Vec::<ChatContact>::new());
+    // id of the newest message per room, and the newest one a read marker was sent for
+    let newest = use_mut_ref(HashMap::<String, i64>::new);
+    let read_sent = use_mut_ref(HashMap::<String, i64>::new);
+    // read markers and who is typing, by room
+    let markers = use_list(Vec::<ReadMarker>::new());
+    let typing = use_list(Vec::<(String, String)>::new());
+    // time of the last typing frame in milliseconds
+    let typing_sent = use_mut_ref(|| 0f64);
+    // expiry of the socket session and the requester token it was last extended with
+    let session = use_mut_ref(|| (0u64, String::new()));
+    let status = use_state(|| ConnectionStatus::Connecting);
+    // reconnect attempts since the last accepted handshake
+    let attempts = use_mut_ref(|| 0u32);
+    // set by errors after which reconnecting cannot help
+    let fatal_error = use_mut_ref(|| None::<ChatErrorType>);
+    // set after the first handshake, later ones resume the joined rooms
+    let connected_before = use_mut_ref(|| false);
+
+    // snapshot of online users, presence frames keep it current afterwards
+    let handle_online = {
+        let online = online.clone();
+        use_async(async move {
+            match services::chat::get_online_users().await {
+                Ok(users) => {
+                    online.set(users);
+                    Ok(())
+                },
+                Err(error) => {
+                    error!(format!("Could not load online users: {}", error.body().message));
+                    Err(())
+                }
+            }
+        })
+    };
 
     // Manually connect to websocket with custom options.
     let ws = {
@@ -40,47 +183,272 @@ pub fn chat_windows(props: &Props) -> Html {
         }
         let chat_disabled_for_open = chat_disabled.clone();
         let chat_disabled_for_close = chat_disabled.clone();
+        let cursors = cursors.clone();
+        let active_key = active_key.clone();
+        let online = online.clone();
+        let handle_online = handle_online.clone();
+        let newest = newest.clone();
+        let markers = markers.clone();
+        let typing = typing.clone();
+        let joined_for_message = joined.clone();
+        let session_for_open = session.clone();
+        let session_for_message = session.clone();
+        let exhausted = exhausted.clone();
+        let stale = stale.clone();
+        let status_for_open = status.clone();
+        let status_for_message = status.clone();
+        let status_for_close = status.clone();
+        let joined_for_open = joined.clone();
+        let newest_for_open = newest.clone();
+        let connected_before_for_open = connected_before.clone();
+        let fatal_error_for_open = fatal_error.clone();
+        let fatal_error_for_message = fatal_error.clone();
+        let attempts_for_message = attempts.clone();
+        let connected_before = connected_before.clone();
+        let fatal_error = fatal_error.clone();
+        let attempts = attempts.clone();
         use_websocket_with_options(
             format!("ws://localhost:{}/ws", port),
             UseWebSocketOptions {
                 onopen: Some(Box::new(move |event| {
                     let socket = event.target_dyn_into::<WebSocket>().unwrap();
+                    status_for_open.set(ConnectionStatus::Connecting);
                     if let Ok(token) = AuthStorage::get_requester_token() {
-                        socket.send_with_str(&token.access_token).unwrap();
+                        *session_for_open.borrow_mut() = (0, token.access_token.clone());
+                        // a reconnect catches up on every joined room from its newest message
+                        let resume = if *connected_before_for_open.borrow() {
+                            joined_for_open.borrow().iter()
+                                .map(|room| RoomResume { room: room.clone(), after: newest_for_open.borrow().get(room).copied() })
+                                .collect()
+                        } else {
+                            Vec::new()
+                        };
+                        let auth = ClientMessage::Auth { version: CHAT_PROTOCOL_VERSION, token: token.access_token, resume };
+                        socket.send_with_str(&serde_json::to_string(&auth).unwrap_or_default()).unwrap();
                         chat_disabled_for_open.set(false);
                     } else {
+                        *fatal_error_for_open.borrow_mut() = Some(ChatErrorType::InvalidToken);
                         socket.close().unwrap();
                     }
                 })),
                 // Receive message by callback `onmessage`.
                 onmessage: Some(Box::new(move |message| {
-                    history.push(format!("{}", message));
+                    let message = match serde_json::from_str::<ServerMessage>(&message) {
+                        Ok(message) => message,
+                        Err(error) => {
+                            error!(format!("Invalid chat frame: {}", error));
+                            return;
+                        }
+                    };
+                    match message {
+                        ServerMessage::Chat { message } => {
+                            // a resume can replay messages that were received before
+                            if history.current().iter().any(|(_, id, _)| *id == Some(message.id)) {
+                                return;
+                            }
+                            let key = message_key(&message);
+                            // replayed history arrives first, so the first message is the oldest shown
+                            cursors.borrow_mut().entry(key.clone()).or_insert(message.id);
+                            if let Some(room) = &message.room {
+                                let mut newest = newest.borrow_mut();
+                                let newest_id = newest.entry(room.clone()).or_default();
+                                *newest_id = (*newest_id).max(message.id);
+                            }
+                            history.push((key, Some(message.id), message.to_string()));
+                        },
+                        ServerMessage::Join { room, username } => history.push((room_key(&room), None, format!("{username} joined."))),
+                        ServerMessage::Leave { room, username } => history.push((room_key(&room), None, format!("{username} left."))),
+                        ServerMessage::Error { error, message } => {
+                            if is_fatal(error) {
+                                *fatal_error_for_message.borrow_mut() = Some(error);
+                            }
+                            history.push((active_key.borrow().clone(), None, format!("Error: {message}")));
+                        },
+                        ServerMessage::Presence { user, online: is_online } => {
+                            let mut users: Vec<ChatContact> = online.current().iter()
+                                .filter(|online_user| online_user.uuid != user.uuid)
+                                .cloned()
+                                .collect();
+                            if is_online {
+                                users.push(user);
+                                users.sort_by(|a, b| a.username.cmp(&b.username));
+                            }
+                            online.set(users);
+                        },
+                        ServerMessage::Typing { room, username, typing: is_typing } => {
+                            let mut typists: Vec<(String, String)> = typing.current().iter()
+                                .filter(|typist| **typist != (room.clone(), username.clone()))
+                                .cloned()
+                                .collect();
+                            if is_typing {
+                                typists.push((room, username));
+                            }
+                            typing.set(typists);
+                        },
+                        ServerMessage::Read { marker } => {
+                            let mut room_markers: Vec<ReadMarker> = markers.current().iter()
+                                .filter(|other| other.room != marker.room || other.username != marker.username)
+                                .cloned()
+                                .collect();
+                            room_markers.push(marker);
+                            markers.set(room_markers);
+                        },
+                        ServerMessage::Deleted { message_id } => {
+                            let lines: Vec<Line> = history.current().iter()
+                                .filter(|(_, id, _)| *id != Some(message_id))
+                                .cloned()
+                                .collect();
+                            history.set(lines);
+                        },
+                        ServerMessage::SlowMode { room, seconds } => {
+                            let notice = if seconds == 0 { String::from("Slow mode is off.") } else { format!("Slow mode: one message every {seconds} seconds.") };
+                            history.push((room_key(&room), None, notice));
+                        },
+                        ServerMessage::Muted { until } => {
+                            let notice = match until {
+                                Some(until) => {
+                                    let minutes = (until - (js_sys::Date::now() / 1000.0) as i64).max(0) / 60;
+                                    format!("You were muted for {minutes} minutes.")
+                                },
+                                None => String::from("You are no longer muted.")
+                            };
+                            history.push((active_key.borrow().clone(), None, notice));
+                        },
+                        // the room may be joined again once the ban is lifted
+                        ServerMessage::Banned { room } => {
+                            joined_for_message.borrow_mut().remove(&room);
+                            history.push((room_key(&room), None, String::from("You were banned from this room.")));
+                        },
+                        // None stands for direct conversations, presence may be off too
+                        ServerMessage::Resync { room, .. } => {
+                            let keys = match room {
+                                Some(room) => HashSet::from([room_key(&room)]),
+                                None => {
+                                    handle_online.run();
+                                    direct_keys(&history, &cursors)
+                                }
+                            };
+                            reset_conversations(&keys, &history, &cursors, &exhausted, &stale);
+                        },
+                        ServerMessage::Session { expires_at } => session_for_message.borrow_mut().0 = expires_at,
+                        // presence changes are sent once the handshake is acknowledged
+                        ServerMessage::Ack { id: None, .. } => {
+                            *attempts_for_message.borrow_mut() = 0;
+                            status_for_message.set(ConnectionStatus::Connected);
+                            handle_online.run();
+                            // rooms are resumed by the server, direct messages sent in between are loaded again
+                            if std::mem::replace(&mut *connected_before.borrow_mut(), true) {
+                                typing.set(Vec::new());
+                                reset_conversations(&direct_keys(&history, &cursors), &history, &cursors, &exhausted, &stale);
+                            }
+                        },
+                        ServerMessage::Ack { .. } | ServerMessage::Pong => {}
+                    }
                 })),
                 onclose: Some(Box::new(move |_event| {
                     chat_disabled_for_close.set(true);
+                    match *fatal_error.borrow() {
+                        Some(error) => status_for_close.set(ConnectionStatus::Closed(error.to_string())),
+                        None => {
+                            let mut attempts = attempts.borrow_mut();
+                            *attempts += 1;
+                            status_for_close.set(ConnectionStatus::Reconnecting { attempt: *attempts, delay_millis: reconnect_delay(*attempts) });
+                        }
+                    }
                 })),
                 manual: Some(true),
+                // reconnects back off in the effect below
+                reconnect_limit: Some(0),
                 ..Default::default()
             },
         )
     };
 
+    let handle_load_older = {
+        let history = history.clone();
+        let cursors = cursors.clone();
+        let exhausted = exhausted.clone();
+        let target = props.target.clone();
+        let key = target_key.clone();
+        use_async(async move {
+            let mut query = ChatHistoryQuery { before: cursors.borrow().get(&key).copied(), ..Default::default() };
+            match target {
+                ChatTarget::Room(room) => query.room = Some(room),
+                ChatTarget::Direct(contact) => query.with = Some(contact.uuid)
+            }
+            match services::chat::get_chat_history(&query).await {
+                Ok(messages) => {
+                    match messages.first() {
+                        Some(message) => { cursors.borrow_mut().insert(key.clone(), message.id); },
+                        None => { exhausted.borrow_mut().insert(key.clone()); }
+                    }
+                    // insert newest first so the page ends up in reading order above the shown lines
+                    for message in messages.iter().rev() {
+                        history.insert(0, (key.clone(), Some(message.id), message.to_string()));
+                    }
+                    Ok(())
+                },
+                Err(error) => {
+                    error!(format!("Could not load chat history: {}", error.body().message));
+                    Err(())
+                }
+            }
+        })
+    };
+
+    // the server closes the socket when the session expires, a token from a newer login extends it
+    {
+        let ws = ws.clone();
+        use_interval(move || {
+            let (expires_at, sent_token) = session.borrow().clone();
+            let now = (js_sys::Date::now() / 1000.0) as u64;
+            if expires_at == 0 || now + SESSION_REFRESH_LEAD_SECS < expires_at {
+                return;
+            }
+            if let Ok(token) = AuthStorage::get_requester_token() {
+                if token.access_token != sent_token {
+                    session.borrow_mut().1 = token.access_token.clone();
+                    ws.send(serde_json::to_string(&ClientMessage::Refresh { token: token.access_token }).unwrap_or_default());
+                }
+            }
+        }, SESSION_CHECK_MILLIS);
+    }
+
+    let load_older_onclick = {
+        let handle_load_older = handle_load_older.clone();
+        Callback::from(move |_| {
+            handle_load_older.run();
+        })
+    };
+
     let oninput = {
         let chat_message = chat_message.clone();
+        let ws = ws.clone();
+        let target = props.target.clone();
         Callback::from(move |e: InputEvent| {
             let input: HtmlInputElement = e.target_unchecked_into();
             chat_message.set(input.value());
+            // the server throttles too, this only saves frames
+            if let ChatTarget::Room(room) = &target {
+                let now = js_sys::Date::now();
+                if now - *typing_sent.borrow() >= (TYPING_THROTTLE_SECS * 1000) as f64 {
+                    *typing_sent.borrow_mut() = now;
+                    ws.send(serde_json::to_string(&ClientMessage::Typing { room: room.clone() }).unwrap_or_default());
+                }
+            }
         })
     };
 
     let send_chat = {
         let ws = ws.clone();
         let chat_message = chat_message.clone();
+        let next_id = next_id.clone();
+        let target = props.target.clone();
         Callback::from(move |_| {
                 if *chat_message == String::new() {
                     return;
                 }
-                ws.send(chat_message.to_string());
+                ws.send(chat_frame(&next_id, &target, chat_message.to_string()));
                 chat_message.set(String::new());
         })
     };
@@ -88,33 +456,191 @@ pub fn chat_windows(props: &Props) -> Html {
     let send_chat_submit = {
         let ws = ws.clone();
         let chat_message = chat_message.clone();
+        let target = props.target.clone();
         Callback::from(move |e: SubmitEvent| {
                 e.prevent_default();
-                ws.send(chat_message.to_string());
+                if *chat_message == String::new() {
+                    return;
+                }
+                ws.send(chat_frame(&next_id, &target, chat_message.to_string()));
                 chat_message.set(String::new());
         })
     };
 
+    // join rooms when they are first opened, the join replays their history
+    // direct conversations have no replay, so their newest page is loaded instead
+    {
+        let ws = ws.clone();
+        let handle_load_older = handle_load_older.clone();
+        let cursors = cursors.clone();
+        let key = target_key.clone();
+        use_effect_with_deps(move |target| {
+            match target {
+                ChatTarget::Room(room) => {
+                    if joined.borrow_mut().insert(room.clone()) {
+                        ws.send(serde_json::to_string(&ClientMessage::Join { room: room.clone() }).unwrap_or_default());
+                    }
+                },
+                ChatTarget::Direct(_) => {
+                    if !cursors.borrow().contains_key(&key) {
+                        handle_load_older.run();
+                    }
+                }
+            }
+            || ()
+        }, props.target.clone());
+    }
+
+    // reload the newest page of the open conversation after a resync cleared it
+    {
+        let handle_load_older = handle_load_older.clone();
+        let key = target_key.clone();
+        use_effect(move || {
+            if stale.borrow_mut().remove(&key) {
+                handle_load_older.run();
+            }
+            || ()
+        });
+    }
+
+    // mark the open room read up to its newest message
+    {
+        let ws = ws.clone();
+        let newest = newest.clone();
+        let target = props.target.clone();
+        use_effect(move || {
+            if let ChatTarget::Room(room) = target {
+                let newest_id = newest.borrow().get(&room).copied();
+                let sent_id = read_sent.borrow().get(&room).copied();
+                if *ws.ready_state == UseWebSocketReadyState::Open && newest_id > sent_id {
+                    if let Some(message_id) = newest_id {
+                        read_sent.borrow_mut().insert(room.clone(), message_id);
+                        ws.send(serde_json::to_string(&ClientMessage::Read { room, message_id }).unwrap_or_default());
+                    }
+                }
+            }
+            || ()
+        });
+    }
+
+    // open the socket again once the backoff delay of the attempt passed
+    {
+        let ws = ws.clone();
+        use_effect_with_deps(move |status| {
+            let cancelled = Rc::new(Cell::new(false));
+            if let ConnectionStatus::Reconnecting { delay_millis, .. } = status {
+                let cancelled = cancelled.clone();
+                let delay = Duration::from_millis(u64::from(*delay_millis));
+                yew::platform::spawn_local(async move {
+                    yew::platform::time::sleep(delay).await;
+                    if !cancelled.get() {
+                        ws.open();
+                    }
+                });
+            }
+            move || cancelled.set(true)
+        }, (*status).clone());
+    }
+
+    // reconnect at once, also after a fatal error once the user logged in again
+    let reconnect_onclick = {
+        let ws = ws.clone();
+        let status = status.clone();
+        Callback::from(move |_| {
+            *fatal_error.borrow_mut() = None;
+            *attempts.borrow_mut() = 0;
+            status.set(ConnectionStatus::Connecting);
+            ws.open();
+        })
+    };
+
     use_effect_once(move || {
         ws.open();
         move || {ws.close()}
     });
 
+    let (status_label, status_color) = match &*status {
+        ConnectionStatus::Connecting => (String::from("Connecting..."), "text-slate-500"),
+        ConnectionStatus::Connected => (String::from("Connected"), "text-green-600"),
+        ConnectionStatus::Reconnecting { attempt, delay_millis } =>
+            (format!("Disconnected, reconnecting in {}s (attempt {})", (delay_millis + 999) / 1000, attempt), "text-amber-600"),
+        ConnectionStatus::Closed(reason) => (format!("Disconnected: {}", reason), "text-red-600")
+    };
+    let can_reconnect = matches!(*status, ConnectionStatus::Reconnecting { .. } | ConnectionStatus::Closed(_));
+
+    let can_load_older = !exhausted.borrow().contains(&target_key);
+    let (typists, seen_by) = match &props.target {
+        ChatTarget::Room(room) => {
+            let newest_id = newest.borrow().get(room).copied().unwrap_or_default();
+            let typists: Vec<String> = typing.current().iter()
+                .filter(|(typing_room, username)| typing_room == room && *username != user_info.username)
+                .map(|(_, username)| username.clone())
+                .collect();
+            let seen_by: Vec<String> = markers.current().iter()
+                .filter(|marker| &marker.room == room && marker.username != user_info.username)
+                .filter(|marker| newest_id > 0 && marker.message_id >= newest_id)
+                .map(|marker| marker.username.clone())
+                .collect();
+            (typists, seen_by)
+        },
+        ChatTarget::Direct(_) => (vec![], vec![])
+    };
+
     html! {
         <div class={props.class}>
-            <div class="h-full px-4 py-2 py-2 
+            <div class="flex flex-row h-full min-h-0 space-x-2">
+            <div class="h-full w-full px-4 py-2 py-2 
             bg-slate-100 text-slate-800
             border-slate-300 dark:border-slate-700 border
             dark:bg-slate-900 dark:text-slate-100
             rounded-md ring-offset-background disabled:pointer-events-none
             overflow-y-auto text-wrap shadow-md">
+                if can_load_older {
+                    <Button onclick={load_older_onclick} label={"Load older"} disabled={handle_load_older.loading} />
+                }
                 {
-                    for history.current().iter().map(|message| {
+                    for history.current().iter().filter(|(key, _, _)| *key == target_key).map(|(_, _, message)| {
                         html! {
                             <p>{ message }</p>
                         }
                     })
                 }
+                if !seen_by.is_empty() {
+                    <p class="text-xs text-slate-500">{format!("Seen by {}", seen_by.join(", "))}</p>
+                }
+                if !typists.is_empty() {
+                    <p class="text-xs italic text-slate-500">{format!("{} typing...", typists.join(", "))}</p>
+                }
+            </div>
+            <div class="h-full w-40 shrink-0 px-4 py-2
+            bg-slate-100 text-slate-800
+            border-slate-300 dark:border-slate-700 border
+            dark:bg-slate-900 dark:text-slate-100
+            rounded-md overflow-y-auto shadow-md">
+                <h3 class="font-bold">{format!("Online ({})", online.current().len())}</h3>
+                {
+                    for online.current().iter().map(|user| {
+                        let onclick = {
+                            let onselect = props.onselect.clone();
+                            let target = ChatTarget::Direct(user.clone());
+                            Callback::from(move |_: MouseEvent| {
+                                if let Some(onselect) = &onselect {
+                                    onselect.emit(target.clone());
+                                }
+                            })
+                        };
+                        html! {
+                            <p class="cursor-pointer hover:underline" onclick={onclick}>{ &user.username }</p>
+                        }
+                    })
+                }
+            </div>
+            </div>
+            <div class="flex flex-row items-center space-x-2">
+                <p class={classes!("text-xs", status_color)}>{format!("● {}", status_label)}</p>
+                if can_reconnect {
+                    <Button onclick={reconnect_onclick} label={"Reconnect now"} />
+                }
             </div>
             <form class="flex flex-row h-12 w-full space-x-2" onsubmit={send_chat_submit}>
                 <Input input_type="text" placeholder="Message..." oninput={oninput} value={(*chat_message).to_owned()} />
@@ -3,7 +3,9 @@ use yew::prelude::*;
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct Props {
-    pub message: String
+    pub message: String,
+    #[prop_or_default]
+    pub request_id: Option<String>
 }
 
 #[function_component(ErrorMessage)]
@@ -13,6 +15,9 @@ pub fn error_message(props: &Props) -> Html {
     html! {
         <div class="px-4 py-2 rounded-md bg-red-300 text-red-600 border border-red-600 text-center shadow-md">
             {props.message}
+            if let Some(request_id) = props.request_id {
+                <p class="text-xs">{format!("Request ID: {}", request_id)}</p>
+            }
         </div>
     }
 }
\ No newline at end of file
@@ -1,10 +1,40 @@
 use yew::prelude::*;
-use crate::{app::AppRoute, components::buttons::nav_button::NavButton, hooks::use_user_info};
+use yew_hooks::prelude::*;
+use crate::{app::AppRoute, components::buttons::nav_button::NavButton, hooks::use_user_info, services};
+
+// how often the unread count of the chat button is refreshed
+const UNREAD_POLL_MILLIS: u32 = 30_000;
 
 #[function_component(Header)]
 pub fn header() -> Html {
     let user_info = use_user_info();
 
+    let unread = use_async(async move {
+        services::chat::get_unread_counts().await
+            .map(|counts| counts.iter().map(|count| count.unread).sum::<i64>())
+            .map_err(|_| ())
+    });
+
+    {
+        let unread = unread.clone();
+        let logged_in = user_info.uuid != String::new();
+        use_effect_with_deps(move |logged_in| {
+            if *logged_in {
+                unread.run();
+            }
+            || ()
+        }, logged_in);
+    }
+    {
+        let unread = unread.clone();
+        let logged_in = user_info.uuid != String::new();
+        use_interval(move || {
+            if logged_in {
+                unread.run();
+            }
+        }, UNREAD_POLL_MILLIS);
+    }
+
     html! {
         <header class="flex flex-row h-12 z-10 fixed border-slate-300 dark:border-slate-700 border-b
             bg-slate-100 dark:bg-slate-900 shadow-md w-screen
@@ -12,7 +42,7 @@ pub fn header() -> Html {
             <div class="flex flex-row h-full">
                 <NavButton label="Home" destination={AppRoute::Home} />
                 if user_info.uuid != String::new() {
-                    <NavButton label="Chat" destination={AppRoute::Chat} />
+                    <NavButton label="Chat" destination={AppRoute::Chat} badge={unread.data.unwrap_or_default()} />
                 }
             </div>
             <div class="flex flex-row h-full">
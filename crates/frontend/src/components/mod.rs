@@ -6,4 +6,6 @@ pub mod buttons;
 pub mod user_info_panel;
 pub mod chat_window;
 pub mod users_table;
-pub mod error_message;
\ No newline at end of file
+pub mod error_message;
+pub mod chat_sidebar;
+pub mod moderation_panel;
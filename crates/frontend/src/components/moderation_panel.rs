@@ -0,0 +1,147 @@
+use types::chat::{ModerationAction, DEFAULT_ROOM};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_hooks::prelude::*;
+
+use crate::{services::{self, AuthError}, components::{buttons::button::Button, error_message::ErrorMessage, input::Input}};
+
+// seconds a mute lasts unless the admin enters another duration
+const DEFAULT_MUTE_SECS: &str = "600";
+
+// admin tools for the chat: delete messages, mute users, ban from rooms and slow mode
+#[function_component(ModerationPanel)]
+pub fn moderation_panel() -> Html {
+    let error_state = use_state(|| None::<AuthError>);
+    let user_uuid = use_state(|| String::new());
+    let room = use_state(|| DEFAULT_ROOM.to_string());
+    let seconds = use_state(|| String::from(DEFAULT_MUTE_SECS));
+    let message_id = use_state(|| String::new());
+
+    let overview = {
+        let error_state = error_state.clone();
+        use_async_with_options(async move {
+            services::chat::get_moderation().await.map_err(|error| {
+                error_state.set(Some(error));
+            })
+        }, UseAsyncOptions::enable_auto())
+    };
+
+    // actions are built from the inputs on click, so they run outside of use_async
+    let run_action = {
+        let error_state = error_state.clone();
+        let overview = overview.clone();
+        Callback::from(move |action: ModerationAction| {
+            let error_state = error_state.clone();
+            let overview = overview.clone();
+            yew::platform::spawn_local(async move {
+                match services::chat::moderate(&action).await {
+                    Ok(()) => {
+                        error_state.set(None);
+                        overview.run();
+                    },
+                    Err(error) => error_state.set(Some(error))
+                }
+            });
+        })
+    };
+
+    let input_callback = |state: &UseStateHandle<String>| {
+        let state = state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            state.set(input.value());
+        })
+    };
+
+    let parsed_seconds = seconds.trim().parse::<u32>().ok();
+    let parsed_message_id = message_id.trim().parse::<i64>().ok();
+    let user = (*user_uuid).trim().to_string();
+
+    let mute_onclick = {
+        let run_action = run_action.clone();
+        let user = user.clone();
+        Callback::from(move |_| {
+            if let Some(seconds) = parsed_seconds {
+                run_action.emit(ModerationAction::Mute { user: user.clone(), seconds: u64::from(seconds) });
+            }
+        })
+    };
+    let ban_onclick = {
+        let run_action = run_action.clone();
+        let user = user.clone();
+        let room = (*room).clone();
+        Callback::from(move |_| run_action.emit(ModerationAction::Ban { room: room.clone(), user: user.clone() }))
+    };
+    let slow_mode_onclick = {
+        let run_action = run_action.clone();
+        let room = (*room).clone();
+        Callback::from(move |_| {
+            if let Some(seconds) = parsed_seconds {
+                run_action.emit(ModerationAction::SlowMode { room: room.clone(), seconds });
+            }
+        })
+    };
+    let delete_onclick = {
+        let run_action = run_action.clone();
+        Callback::from(move |_| {
+            if let Some(message_id) = parsed_message_id {
+                run_action.emit(ModerationAction::DeleteMessage { message_id });
+            }
+        })
+    };
+
+    let now = (js_sys::Date::now() / 1000.0) as i64;
+
+    html! {
+        <div class="flex flex-col space-y-2 p-4 w-full max-w-3xl">
+            <h2 class="font-bold">{"Chat Moderation"}</h2>
+            <div class="flex flex-row space-x-2">
+                <Input input_type="text" placeholder="User UUID..." oninput={input_callback(&user_uuid)} value={(*user_uuid).to_owned()} />
+                <Input input_type="text" placeholder="Room..." oninput={input_callback(&room)} value={(*room).to_owned()} />
+                <Input input_type="number" placeholder="Seconds..." oninput={input_callback(&seconds)} value={(*seconds).to_owned()} />
+            </div>
+            <div class="flex flex-row space-x-2">
+                <Button onclick={mute_onclick} label={"Mute"} disabled={user.is_empty() || parsed_seconds.is_none()} />
+                <Button onclick={ban_onclick} label={"Ban from room"} disabled={user.is_empty() || room.is_empty()} />
+                <Button onclick={slow_mode_onclick} label={"Set slow mode"} disabled={room.is_empty() || parsed_seconds.is_none()} />
+            </div>
+            <p class="text-xs">{"A mute or slow mode of 0 seconds lifts it."}</p>
+            <div class="flex flex-row space-x-2">
+                <Input input_type="number" placeholder="Message ID..." oninput={input_callback(&message_id)} value={(*message_id).to_owned()} />
+                <Button onclick={delete_onclick} label={"Delete message"} disabled={parsed_message_id.is_none()} />
+            </div>
+            <h3 class="font-bold">{"Muted users"}</h3>
+            {
+                for overview.data.iter().flat_map(|overview| overview.mutes.iter()).map(|mute| {
+                    let run_action = run_action.clone();
+                    let user = mute.uuid.clone();
+                    let onclick = Callback::from(move |_| run_action.emit(ModerationAction::Mute { user: user.clone(), seconds: 0 }));
+                    let minutes_left = (mute.muted_until - now).max(0) / 60;
+                    html! {
+                        <div class="flex flex-row space-x-2 items-center">
+                            <span>{format!("{} ({} min left)", mute.username, minutes_left)}</span>
+                            <Button onclick={onclick} label={"Unmute"} />
+                        </div>
+                    }
+                })
+            }
+            <h3 class="font-bold">{"Room bans"}</h3>
+            {
+                for overview.data.iter().flat_map(|overview| overview.bans.iter()).map(|ban| {
+                    let run_action = run_action.clone();
+                    let action = ModerationAction::Unban { room: ban.room.clone(), user: ban.uuid.clone() };
+                    let onclick = Callback::from(move |_| run_action.emit(action.clone()));
+                    html! {
+                        <div class="flex flex-row space-x-2 items-center">
+                            <span>{format!("{} in #{}", ban.username, ban.room)}</span>
+                            <Button onclick={onclick} label={"Unban"} />
+                        </div>
+                    }
+                })
+            }
+            if let Some(error) = (*error_state).clone() {
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
+            }
+        </div>
+    }
+}
@@ -1,21 +1,43 @@
-use types::user::UserInfo;
+use types::{page::Page, user::{SortOrder, UserInfo, UserListQuery, UserSort}};
+use web_sys::HtmlInputElement;
 use yew::prelude::*;
-use yew_hooks::{use_async, use_effect_once};
+use yew_hooks::use_async;
 
-use crate::{services, components::buttons::button::Button};
+use crate::{services, components::{buttons::button::Button, input::Input}};
+
+const BUTTON_COLOR: &str = "bg-slate-200 text-slate-800 hover:bg-slate-300 dark:bg-slate-800 dark:text-slate-100 dark:hover:bg-slate-700";
+
+// cycle a filter through any -> yes -> no
+fn next_filter(filter: Option<bool>) -> Option<bool> {
+    match filter {
+        None => Some(true),
+        Some(true) => Some(false),
+        Some(false) => None
+    }
+}
+
+fn filter_label(name: &str, filter: Option<bool>) -> String {
+    match filter {
+        None => format!("{}: any", name),
+        Some(true) => format!("{}: yes", name),
+        Some(false) => format!("{}: no", name)
+    }
+}
 
 #[function_component(UsersTable)]
 pub fn users_table() -> Html {
-    let users = use_state(|| Vec::<UserInfo>::new());
+    let page = use_state(Page::<UserInfo>::default);
+    let query = use_state(UserListQuery::default);
     let delete_user_uuid = use_state(|| String::new());
 
     let handle_get_users = {
-        let users = users.clone();
+        let page = page.clone();
+        let query = (*query).clone();
         use_async(async move {
-            let response = services::user::get_all_users().await;
+            let response = services::user::get_all_users(&query).await;
             match response {
                 Ok(data) => {
-                    users.set(data.1);
+                    page.set(data.1);
                     Ok(data.0)
                 },
                 Err(error) => {
@@ -47,31 +69,95 @@ pub fn users_table() -> Html {
         handle_delete.run();
     });
 
-    let handle_get_users_clone = handle_get_users.clone();
-    use_effect_once(move || {
-        handle_get_users_clone.run();
-        move || {}
-    });
+    // refetch whenever search, filters, sorting or page change
+    {
+        let handle_get_users = handle_get_users.clone();
+        use_effect_with_deps(move |_| {
+            handle_get_users.run();
+            || {}
+        }, (*query).clone());
+    }
+
+    // update query and return to the first page unless the page itself is changed
+    let update_query = {
+        let query = query.clone();
+        move |update: Box<dyn Fn(&mut UserListQuery)>| {
+            let query = query.clone();
+            Callback::from(move |_| {
+                let mut new_query = (*query).clone();
+                new_query.page = None;
+                update(&mut new_query);
+                query.set(new_query);
+            })
+        }
+    };
+
+    let search_oninput = {
+        let query = query.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let value = input.value();
+            query.set(UserListQuery {
+                search: if value.is_empty() { None } else { Some(value) },
+                page: None,
+                ..(*query).clone()
+            });
+        })
+    };
+
+    // clicking the sorted column flips the order, other columns sort ascending
+    let sort_onclick = |sort: UserSort| {
+        update_query(Box::new(move |query: &mut UserListQuery| {
+            query.order = if query.sort == sort && query.order == SortOrder::Asc { SortOrder::Desc } else { SortOrder::Asc };
+            query.sort = sort;
+        }))
+    };
+    let sort_label = |label: &str, sort: UserSort| {
+        if query.sort != sort {
+            label.to_string()
+        } else if query.order == SortOrder::Asc {
+            format!("{} ▲", label)
+        } else {
+            format!("{} ▼", label)
+        }
+    };
+
+    let page_onclick = |page_number: u32| {
+        let query = query.clone();
+        Callback::from(move |_| {
+            query.set(UserListQuery { page: Some(page_number), ..(*query).clone() });
+        })
+    };
 
     html! {
         <div class="w-11/12 flex flex-col h-min
         rounded-md text-lg font-strong overflow-y-auto
         border-slate-300 dark:border-slate-700 border
-        h-10 px-4 py-2 my-10
+        px-4 py-2 my-10 space-y-2
         bg-slate-100 text-slate-800 shadow-md
         dark:bg-slate-900 dark:text-slate-100">
+            <div class="flex flex-row items-center space-x-2">
+                <Input placeholder="Search username or email" oninput={search_oninput}
+                    value={query.search.clone().unwrap_or_default()} />
+                <Button color={BUTTON_COLOR} label={filter_label("Admin", query.is_admin)}
+                    onclick={update_query(Box::new(|query: &mut UserListQuery| query.is_admin = next_filter(query.is_admin)))} />
+                <Button color={BUTTON_COLOR} label={filter_label("Disabled", query.is_disabled)}
+                    onclick={update_query(Box::new(|query: &mut UserListQuery| query.is_disabled = next_filter(query.is_disabled)))} />
+                <Button color={BUTTON_COLOR} label={filter_label("Verified", query.is_verified)}
+                    onclick={update_query(Box::new(|query: &mut UserListQuery| query.is_verified = next_filter(query.is_verified)))} />
+            </div>
             <table>
                 <thead>
                     <tr class="text-left">
-                        <th>{"UUID"}</th>
-                        <th>{"Username"}</th>
-                        <th>{"Email"}</th>
-                        <th>{"Admin"}</th>
+                        <th class="cursor-pointer" onclick={sort_onclick(UserSort::Created)}>{sort_label("UUID", UserSort::Created)}</th>
+                        <th class="cursor-pointer" onclick={sort_onclick(UserSort::Username)}>{sort_label("Username", UserSort::Username)}</th>
+                        <th class="cursor-pointer" onclick={sort_onclick(UserSort::Email)}>{sort_label("Email", UserSort::Email)}</th>
+                        <th class="cursor-pointer" onclick={sort_onclick(UserSort::Admin)}>{sort_label("Admin", UserSort::Admin)}</th>
                         <th></th>
                     </tr>
                 </thead>
                 <tbody>
-                    { (*users).clone().into_iter().map(|user: UserInfo| {
+                    { page.items.clone().into_iter().map(|user: UserInfo| {
                         let onclick = onclick.clone();
                         let delete_id = user.uuid.clone();
                         html!{
@@ -80,13 +166,20 @@ pub fn users_table() -> Html {
                                 <td>{user.username}</td>
                                 <td>{user.email}</td>
                                 <td>{user.is_admin.to_string()}</td>
-                                <td><Button color="bg-slate-200 text-slate-800 hover:bg-slate-300 dark:bg-slate-800 dark:text-slate-100 dark:hover:bg-slate-700"
+                                <td><Button color={BUTTON_COLOR}
                                         label="Delete" onclick={move |_| {onclick.emit(delete_id.clone());}}/></td>
                             </tr>
                         }
                     }).collect::<Html>()}
                 </tbody>
             </table>
+            <div class="flex flex-row items-center justify-between">
+                <Button color={BUTTON_COLOR} label="Previous" disabled={!page.has_previous()}
+                    onclick={page_onclick(page.page.saturating_sub(1))} />
+                <span class="text-sm">{format!("Page {} of {}, {} users", page.page, page.page_count(), page.total)}</span>
+                <Button color={BUTTON_COLOR} label="Next" disabled={!page.has_next()}
+                    onclick={page_onclick(page.page + 1)} />
+            </div>
         </div>
     }
-}
\ No newline at end of file
+}
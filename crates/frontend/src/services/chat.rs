@@ -0,0 +1,96 @@
+use gloo_console::error;
+use serde::de::DeserializeOwned;
+use types::chat::{ChatContact, ChatHistoryQuery, ChatMessage, CreateRoom, ModerationAction, ModerationOverview, Room, UnreadCount};
+
+use super::{get_http_auth_client, AuthError};
+
+// check the status and parse a JSON body of a chat endpoint
+async fn parse_response<T: DeserializeOwned>(request_result: reqwest_middleware::Result<reqwest::Response>) -> Result<T, AuthError> {
+    if let Err(error) = request_result {
+        error!("Error with request: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+
+    // Unwrap request and extract status as owned value
+    let response = request_result.unwrap();
+    if !response.status().is_success() {
+        return Err(AuthError::from_response(response).await);
+    }
+
+    // Parse body as JSON
+    let json_result = response.json::<T>().await;
+    if let Err(error) = json_result {
+        error!("Error parsing body: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+    Ok(json_result.unwrap())
+}
+
+pub async fn get_chat_history(query: &ChatHistoryQuery) -> Result<Vec<ChatMessage>, AuthError> {
+    // Request messages of a room or direct conversation older than the query cursor, in reading order
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/history").query(query).send().await).await
+}
+
+pub async fn get_rooms() -> Result<Vec<Room>, AuthError> {
+    // Request public rooms and private rooms of the user
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/rooms").send().await).await
+}
+
+pub async fn create_room(room: &CreateRoom) -> Result<Room, AuthError> {
+    // Request new room, the user becomes its first member
+    parse_response(get_http_auth_client().post("http://localhost:3001/chat/rooms").json(room).send().await).await
+}
+
+pub async fn add_room_member(room: &str, uuid: String) -> Result<(), AuthError> {
+    // Request to add user with uuid to private room
+    let request_result = get_http_auth_client()
+        .post(format!("http://localhost:3001/chat/rooms/{}/members", room))
+        .body(uuid)
+        .send().await;
+    if let Err(error) = request_result {
+        error!("Error with request: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+    let response = request_result.unwrap();
+    if !response.status().is_success() {
+        return Err(AuthError::from_response(response).await);
+    }
+    Ok(())
+}
+
+pub async fn get_contacts() -> Result<Vec<ChatContact>, AuthError> {
+    // Request users direct messages were exchanged with
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/contacts").send().await).await
+}
+
+pub async fn get_online_users() -> Result<Vec<ChatContact>, AuthError> {
+    // Request users with an open chat connection
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/online").send().await).await
+}
+
+pub async fn get_unread_counts() -> Result<Vec<UnreadCount>, AuthError> {
+    // Request unread messages of every room the user has read before
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/unread").send().await).await
+}
+
+pub async fn get_moderation() -> Result<ModerationOverview, AuthError> {
+    // Request active mutes and room bans, admin only
+    parse_response(get_http_auth_client().get("http://localhost:3001/chat/moderation").send().await).await
+}
+
+pub async fn moderate(action: &ModerationAction) -> Result<(), AuthError> {
+    // Request a moderation action, admin only
+    let request_result = get_http_auth_client()
+        .post("http://localhost:3001/chat/moderation")
+        .json(action)
+        .send().await;
+    if let Err(error) = request_result {
+        error!("Error with request: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+    let response = request_result.unwrap();
+    if !response.status().is_success() {
+        return Err(AuthError::from_response(response).await);
+    }
+    Ok(())
+}
@@ -5,12 +5,13 @@ use gloo_storage::{Storage, errors::StorageError};
 use once_cell::sync::OnceCell;
 use reqwest::{header::{HeaderMap, AUTHORIZATION}, Client, Response, StatusCode};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use types::auth::{AuthErrorBody, AuthErrorType, AuthToken};
+use types::{auth::{AuthErrorBody, AuthErrorType, AuthToken}, problem::ProblemDetails};
 
 use self::auth::AuthMiddleware;
 
 pub mod auth;
 pub mod user;
+pub mod chat;
 
 static HTTP_CLIENT: OnceCell<Client> = OnceCell::new();
 static HTTP_CLIENT_WITH_AUTH: OnceCell<ClientWithMiddleware> = OnceCell::new();
@@ -77,35 +78,53 @@ impl AuthStorage {
 }
 
 #[derive(Debug, Clone)]
-pub struct AuthError(types::auth::AuthError);
+pub struct AuthError(types::auth::AuthError, Option<String>);
 
 impl AuthError {
+    // parse application/problem+json error response from server
     pub async fn from_response(response: Response) -> Self {
         let status: StatusCode = response.status();
-        let error_body = response.json::<AuthErrorBody>().await;
-        if let Err(_) = error_body {
-            return Self {
-                0: types::auth::AuthError::default()
-            }
+        let problem = response.json::<ProblemDetails>().await;
+        if let Err(error) = problem {
+            error!("Error parsing problem details: {}", error.to_string());
+            return Self::default()
         }
+        let problem = problem.unwrap();
+        let error_type = problem.error_type.unwrap_or(AuthErrorType::ServerError);
         return Self  {
             0: types::auth::AuthError {
-                status: http::StatusCode::from_str(status.as_str()).unwrap(),
-                body: error_body.unwrap()
-            }
+                status: http::StatusCode::from_str(status.as_str()).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR),
+                body: AuthErrorBody {
+                    error_type,
+                    message: problem.detail
+                }
+            },
+            1: problem.request_id
         };
     }
     pub fn from_error_type(error_type: AuthErrorType) -> Self {
         Self {
-            0: types::auth::AuthError::from_error_type(error_type)
+            0: types::auth::AuthError::from_error_type(error_type),
+            1: None
+        }
+    }
+    pub fn with_message(self, message: String) -> Self {
+        Self {
+            0: self.0.with_message(message),
+            1: self.1
         }
     }
     pub fn body(&self) -> AuthErrorBody {
         self.0.body.to_owned()
     }
+    // request ID reported by the server, used when reporting errors
+    pub fn request_id(&self) -> Option<String> {
+        self.1.to_owned()
+    }
     pub fn default() -> Self {
         Self {
-            0: types::auth::AuthError::default()
+            0: types::auth::AuthError::default(),
+            1: None
         }
     }
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
 use gloo_console::error;
 use reqwest::{header::{HeaderMap, HeaderValue, AUTHORIZATION}, Method, StatusCode, Url};
-use types::user::UserInfo;
+use types::{page::Page, user::{DeleteAccount, UserInfo, UserListQuery}};
 
-use super::{get_http_auth_client, get_http_client, AuthStorage};
+use super::{get_http_auth_client, get_http_client, AuthError, AuthStorage};
 
 pub async fn get_user_info() -> UserInfo {
     // Attempt to get auth requester token from storage
@@ -46,9 +46,9 @@ pub async fn get_user_info() -> UserInfo {
     return data;
 }
 
-pub async fn get_all_users() -> Result<(StatusCode, Vec<UserInfo>), StatusCode> {
-    // Request all users from server
-    let request_result = get_http_auth_client().get("http://localhost:3001/user/all").send().await;
+pub async fn get_all_users(query: &UserListQuery) -> Result<(StatusCode, Page<UserInfo>), StatusCode> {
+    // Request one page of users matching the query from server
+    let request_result = get_http_auth_client().get("http://localhost:3001/user/all").query(query).send().await;
     if let Err(error) = request_result {
         error!("Error with request: {}", error.to_string());
         return Err(error.status().unwrap());
@@ -57,14 +57,17 @@ pub async fn get_all_users() -> Result<(StatusCode, Vec<UserInfo>), StatusCode>
     // Unwrap request and extract status as owned value
     let response = request_result.unwrap();
     let status = response.status();
+    if !status.is_success() {
+        return Err(status);
+    }
 
     // Parse body as JSON
-    let json_result = response.json::<Vec<UserInfo>>().await;
+    let json_result = response.json::<Page<UserInfo>>().await;
     if let Err(error) = json_result {
         return Err(error.status().unwrap_or_default());
     }
 
-    // Return vec of users
+    // Return page of users
     let users = json_result.unwrap();
     Ok((status, users))
 }
@@ -82,4 +85,46 @@ pub async fn delete_user(uuid: String) -> Result<StatusCode, StatusCode> {
 
     // Return status of response
     Ok(response.status())
-}
\ No newline at end of file
+}
+pub async fn delete_own_account(delete_account: DeleteAccount) -> Result<StatusCode, AuthError> {
+    // Request to delete the logged in user, password is re-entered to confirm
+    let request_result = get_http_auth_client().delete("http://localhost:3001/user/me").json(&delete_account).send().await;
+    if let Err(error) = request_result {
+        error!("Error with request: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+
+    // Unwrap request and extract status as owned value
+    let response = request_result.unwrap();
+    let status = response.status();
+    if !status.is_success() {
+        return Err(AuthError::from_response(response).await);
+    }
+
+    // Clear tokens of the deleted account
+    AuthStorage::clear();
+    Ok(status)
+}
+
+pub async fn export_user_data() -> Result<String, AuthError> {
+    // Request everything the server holds about the logged in user
+    let request_result = get_http_auth_client().get("http://localhost:3001/user/me/export").send().await;
+    if let Err(error) = request_result {
+        error!("Error with request: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+
+    // Unwrap request and extract status as owned value
+    let response = request_result.unwrap();
+    if !response.status().is_success() {
+        return Err(AuthError::from_response(response).await);
+    }
+
+    // Return export as JSON text to be saved as a file
+    let text_result = response.text().await;
+    if let Err(error) = text_result {
+        error!("Error reading body: {}", error.to_string());
+        return Err(AuthError::default());
+    }
+    Ok(text_result.unwrap())
+}
@@ -1,6 +1,6 @@
 use yew::prelude::*;
 
-use crate::components::users_table::UsersTable;
+use crate::components::{moderation_panel::ModerationPanel, users_table::UsersTable};
 
 #[function_component(AdminView)]
 pub fn admin_view() -> Html {
@@ -8,6 +8,7 @@ pub fn admin_view() -> Html {
     html! {
         <main class="col-span-12 row-span-24 flex flex-col items-center">
             <UsersTable />
+            <ModerationPanel />
         </main>
     }
 }
\ No newline at end of file
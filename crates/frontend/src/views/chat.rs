@@ -1,11 +1,20 @@
 use yew::prelude::*;
-use crate::components::chat_window::ChatWindow;
+use crate::components::{chat_sidebar::{ChatSidebar, ChatTarget}, chat_window::ChatWindow};
 
 #[function_component(Chat)]
 pub fn chat() -> Html {
+    let target = use_state(ChatTarget::default);
+
+    let onselect = {
+        let target = target.clone();
+        Callback::from(move |selected: ChatTarget| target.set(selected))
+    };
+
     html! {
-        <main class="col-span-12 row-span-24 h-full">
-            <ChatWindow class="flex shrink flex-col w-full h-full
+        <main class="col-span-12 row-span-24 h-full flex flex-row">
+            <ChatSidebar active={(*target).clone()} onselect={onselect.clone()} class="flex flex-col w-48 shrink-0
+            p-4 space-y-2 text-sm overflow-y-auto"/>
+            <ChatWindow target={(*target).clone()} onselect={onselect} class="flex shrink flex-col w-full h-full
             ring-offset-background disabled:pointer-events-none
             p-4 space-y-2 text-sm"/>
         </main>
@@ -1,17 +1,42 @@
-use types::user::UserInfo;
+use gloo_console::error;
+use types::user::{DeleteAccount, UserInfo};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, HtmlInputElement, Url};
 use yew::prelude::*;
 use yew_hooks::use_async;
 use yewdux::functional::use_store;
 
-use crate::{components::{buttons::button::Button, error_message::ErrorMessage, user_info_panel::UserInfoPanel}, services::{self, AuthError}};
+use crate::{components::{buttons::button::Button, error_message::ErrorMessage, input::Input, user_info_panel::UserInfoPanel}, services::{self, AuthError}};
 use crate::hooks::StoredUserInfo;
 
+// save text as a file through a temporary object URL
+fn download(filename: &str, contents: &str) -> Result<(), String> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let mut options = BlobPropertyBag::new();
+    options.type_("application/json");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)
+        .map_err(|error| format!("{:?}", error))?;
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|error| format!("{:?}", error))?;
+    let document = web_sys::window().and_then(|window| window.document())
+        .ok_or_else(|| String::from("No document to download from"))?;
+    let anchor: HtmlAnchorElement = document.create_element("a")
+        .map_err(|error| format!("{:?}", error))?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    Url::revoke_object_url(&url).map_err(|error| format!("{:?}", error))
+}
+
 #[function_component(UserView)]
 pub fn user_view() -> Html {
-    let (_user_info, user_info_dispatch) = use_store::<StoredUserInfo>();
+    let (user_info, user_info_dispatch) = use_store::<StoredUserInfo>();
     let error_state = use_state(|| None::<AuthError>);
+    let delete_account = use_state(DeleteAccount::default);
 
     let logout_onclick = {
+        let user_info_dispatch = user_info_dispatch.clone();
         Callback::from(move |_| {
             services::auth::logout_user();
             user_info_dispatch.set(StoredUserInfo { user_info: UserInfo::default() });
@@ -41,15 +66,84 @@ pub fn user_view() -> Html {
         })
     };
 
+    let pass_oninput = {
+        let delete_account = delete_account.clone();
+        let error_state = error_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some(_) = *error_state {
+                error_state.set(None);
+            }
+            delete_account.set(DeleteAccount { pass: input.value() });
+        })
+    };
+
+    let handle_delete = {
+        let error_state = error_state.clone();
+        let delete_account = delete_account.clone();
+        use_async(async move {
+            let response = services::user::delete_own_account((*delete_account).clone()).await;
+            delete_account.set(DeleteAccount::default());
+            match response {
+                Ok(status_code) => {
+                    user_info_dispatch.set(StoredUserInfo { user_info: UserInfo::default() });
+                    Ok(status_code)
+                },
+                Err(error) => {
+                    error_state.set(Some(error));
+                    Err(())
+                }
+            }
+        })
+    };
+
+    let delete_onclick = {
+        let handle_delete = handle_delete.clone();
+        Callback::from(move |_| {
+            handle_delete.run();
+        })
+    };
+
+    let handle_export = {
+        let error_state = error_state.clone();
+        let uuid = user_info.user_info.uuid.clone();
+        use_async(async move {
+            match services::user::export_user_data().await {
+                Ok(export) => {
+                    if let Err(message) = download(&format!("user-{}.json", uuid), &export) {
+                        error!("Could not save export: {}", message);
+                    }
+                    Ok(())
+                },
+                Err(error) => {
+                    error_state.set(Some(error));
+                    Err(())
+                }
+            }
+        })
+    };
+
+    let export_onclick = {
+        let handle_export = handle_export.clone();
+        Callback::from(move |_| {
+            handle_export.run();
+        })
+    };
+
     html! {
         <div class="col-span-12 row-span-24 flex flex-col justify-center items-center h-full space-y-2">
             if let Some(error) = (*error_state).to_owned() {
-                <ErrorMessage message={error.body().message} />
+                <ErrorMessage message={error.body().message} request_id={error.request_id()} />
             }
             <UserInfoPanel />
             <div class="flex flex-row space-x-4">
                 <Button label={"Logout"} onclick={logout_onclick} />
                 <Button onclick={test_onclick} label={"Test Auth"} />
+                <Button onclick={export_onclick} label={"Export Data"} />
+            </div>
+            <div class="flex flex-row space-x-4">
+                <Input input_type="password" placeholder="Password" oninput={pass_oninput} value={delete_account.pass.to_owned()} />
+                <Button onclick={delete_onclick} label={"Delete Account"} />
             </div>
         </div>
     }
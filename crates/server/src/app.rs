@@ -0,0 +1,39 @@
+use axum::{middleware, Router};
+use http::{header::{AUTHORIZATION, CONTENT_TYPE}, HeaderName};
+use tower::ServiceBuilder;
+use tower_http::cors::{Any, CorsLayer};
+use types::problem::REQUEST_ID_HEADER;
+
+use crate::{controllers, middleware::request_id, monitoring, state::AppState, telemetry};
+
+// build the full application router with all routes and layers
+pub fn build_app(state: AppState) -> Router {
+    // install prometheus recorder before any metrics are recorded
+    monitoring::install_recorder();
+
+    let cors = CorsLayer::permissive()
+        .allow_origin(Any)
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, HeaderName::from_static(REQUEST_ID_HEADER)])
+        .expose_headers(Any);
+
+    let mut app = Router::new()
+        .nest("/ws", controllers::ws_controller::routes())
+        .nest("/chat", controllers::chat_controller::routes(state.clone()))
+        .nest("/auth", controllers::auth_controller::routes(state.clone()))
+        .nest("/user", controllers::users_controller::routes(state.clone()))
+        .merge(controllers::health_controller::routes());
+
+    // metrics are served on their own listener when server.metrics_addr is set, otherwise on /metrics for admins
+    if state.config.server.metrics_addr.is_none() {
+        app = app.nest("/metrics", monitoring::routes(state.clone()));
+    }
+
+    app
+        .layer(
+            ServiceBuilder::new()
+            .layer(middleware::from_fn(request_id::assign_request_id))
+            .layer(telemetry::trace_layer())
+            .layer(middleware::from_fn(monitoring::track_requests))
+            .layer(cors))
+        .with_state(state)
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use axum::{body::Body, response::{IntoResponse, Response}, Json};
+use http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+use types::{auth::AuthErrorType, problem::{ProblemDetails, PROBLEM_JSON}};
+
+use crate::{db_error::DbError, middleware::request_id, strategies::{authentication::AuthError, users::UserRepositoryError}};
+
+// Application wide error returned by every handler
+#[derive(Debug)]
+pub enum AppError {
+    Auth(AuthError),
+    Validation(String),
+    Database(DbError),
+    NotFound(String),
+    Internal(String)
+}
+
+impl AppError {
+    pub fn from_error_type(error_type: AuthErrorType) -> Self {
+        AppError::Auth(AuthError::from_error_type(error_type))
+    }
+    pub fn internal<E: fmt::Display>(error: E) -> Self {
+        AppError::Internal(error.to_string())
+    }
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Auth(error) => error.status(),
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Database(error) => types::auth::AuthError::from_error_type(error.auth_error_type()).status(),
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+    // build RFC 7807 body, internal details are never exposed to clients
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.status();
+        let (kind, error_type, detail) = match self {
+            AppError::Auth(error) => ("auth", error.body().error_type, error.body().message),
+            AppError::Validation(message) => ("validation", AuthErrorType::BadRequest, message.to_owned()),
+            AppError::Database(error) => {
+                let body = types::auth::AuthError::from_error_type(error.auth_error_type()).body();
+                ("database", body.error_type, body.message)
+            },
+            AppError::NotFound(message) => ("not-found", AuthErrorType::NotFound, message.to_owned()),
+            AppError::Internal(_) => ("internal", AuthErrorType::ServerError, String::from("Server error"))
+        };
+        ProblemDetails {
+            problem_type: format!("/problems/{}", kind),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            request_id: request_id::current(),
+            error_type: Some(error_type)
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Auth(error) => write!(f, "Auth error: {}", error.body().message),
+            AppError::Validation(message) => write!(f, "Validation error: {}", message),
+            AppError::Database(error) => write!(f, "Database error: {}", error),
+            AppError::NotFound(message) => write!(f, "Not found: {}", message),
+            AppError::Internal(message) => write!(f, "Internal error: {}", message)
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(error: AuthError) -> Self {
+        AppError::Auth(error)
+    }
+}
+
+impl From<DbError> for AppError {
+    fn from(error: DbError) -> Self {
+        AppError::Database(error)
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        AppError::Database(DbError::from(error))
+    }
+}
+
+impl From<UserRepositoryError> for AppError {
+    fn from(error: UserRepositoryError) -> Self {
+        match error {
+            UserRepositoryError::NotFound => AppError::from_error_type(AuthErrorType::UserDoesNotExist),
+            UserRepositoryError::PasswordHash(error) => AppError::internal(error),
+            UserRepositoryError::Database(error) => AppError::Database(error)
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response<Body> {
+        let problem = self.problem();
+        if self.status().is_server_error() {
+            tracing::error!(error = %self, "Request failed");
+        } else {
+            tracing::debug!(error = %self, "Request rejected");
+        }
+        let mut response = (self.status(), Json(problem)).into_response();
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
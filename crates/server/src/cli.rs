@@ -0,0 +1,106 @@
+use std::fmt;
+
+// help text printed for invalid arguments
+pub const USAGE: &str = "Usage:
+    server [serve]              run the API server
+    server migrate up           apply all pending migrations
+    server migrate down         revert the most recently applied migration
+    server migrate status       list applied and pending migrations
+    server user create <username> <email> [--admin]
+                                create a user, the password is read from stdin
+    server user set-password <username|email>
+                                replace a password, the new password is read from stdin
+    server user promote <username|email>
+                                grant admin access
+    server user list            list all users
+    server user delete <username|email>
+                                delete a user, it can be restored until the grace period ends
+    server user restore <uuid>  restore a deleted user";
+
+// Error returned for arguments that do not name a known command
+#[derive(Debug)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.0, USAGE)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+// Command selected by the command line arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Serve,
+    Migrate(MigrateCommand),
+    User(UserCommand)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrateCommand {
+    Up,
+    Down,
+    Status
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserCommand {
+    Create { username: String, email: String, is_admin: bool },
+    SetPassword { user: String },
+    Promote { user: String },
+    List,
+    Delete { user: String },
+    Restore { uuid: String }
+}
+
+impl UserCommand {
+    // commands that need a password entered by the operator
+    pub fn needs_password(&self) -> bool {
+        matches!(self, UserCommand::Create { .. } | UserCommand::SetPassword { .. })
+    }
+}
+
+impl Command {
+    // parse arguments without the program name, no arguments means serve
+    pub fn parse<I, S>(args: I) -> Result<Command, CliError>
+    where I: IntoIterator<Item = S>, S: AsRef<str> {
+        let args: Vec<S> = args.into_iter().collect();
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+        match args.as_slice() {
+            [] | ["serve"] => Ok(Command::Serve),
+            ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up)),
+            ["migrate", "down"] => Ok(Command::Migrate(MigrateCommand::Down)),
+            ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+            ["migrate", ..] => Err(CliError(String::from("migrate expects one of up, down or status"))),
+            ["user", rest @ ..] => parse_user_command(rest).map(Command::User),
+            [command, ..] => Err(CliError(format!("Unknown command {}", command)))
+        }
+    }
+}
+
+fn parse_user_command(args: &[&str]) -> Result<UserCommand, CliError> {
+    // flags may appear anywhere after the subcommand
+    let is_admin = args.contains(&"--admin");
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with("--") && **arg != "--admin") {
+        return Err(CliError(format!("Unknown option {}", flag)));
+    }
+    let positional: Vec<&str> = args.iter().copied().filter(|arg| !arg.starts_with("--")).collect();
+    if is_admin && positional.first() != Some(&"create") {
+        return Err(CliError(String::from("--admin is only valid for user create")));
+    }
+    match positional.as_slice() {
+        ["create", username, email] => Ok(UserCommand::Create {
+            username: username.to_string(),
+            email: email.to_string(),
+            is_admin
+        }),
+        ["set-password", user] => Ok(UserCommand::SetPassword { user: user.to_string() }),
+        ["promote", user] => Ok(UserCommand::Promote { user: user.to_string() }),
+        ["list"] => Ok(UserCommand::List),
+        ["delete", user] => Ok(UserCommand::Delete { user: user.to_string() }),
+        ["restore", uuid] => Ok(UserCommand::Restore { uuid: uuid.to_string() }),
+        [command @ ("create" | "set-password" | "promote" | "list" | "delete" | "restore"), ..] => Err(CliError(format!("Wrong number of arguments for user {}", command))),
+        _ => Err(CliError(String::from("user expects one of create, set-password, promote, list, delete or restore")))
+    }
+}
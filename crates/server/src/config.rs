@@ -0,0 +1,442 @@
+use std::{env, fmt, fs, io, net::{IpAddr, Ipv4Addr, SocketAddr}, path::PathBuf, str::FromStr};
+
+use serde::Deserialize;
+use types::{chat::{CHAT_HISTORY_DEFAULT_LIMIT, CHAT_HISTORY_MAX_LIMIT}, user::REDACTED, username::DEFAULT_RESERVED_USERNAMES};
+
+use crate::pubsub::PubSubKind;
+use crate::telemetry::{LogFormat, DEFAULT_LOG_FILTER};
+
+// config file read when CONFIG_FILE is not set, it is optional
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Error returned when configuration cannot be loaded or is invalid
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, error: toml::de::Error },
+    Missing { key: &'static str, env: &'static str },
+    Invalid { key: &'static str, message: String }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "Could not read config file {}: {}", path.display(), error),
+            ConfigError::Parse { path, error } => write!(f, "Could not parse config file {}: {}", path.display(), error),
+            ConfigError::Missing { key, env } => write!(f, "Missing required setting {} (set it in the config file or the {} environment variable)", key, env),
+            ConfigError::Invalid { key, message } => write!(f, "Invalid setting {}: {}", key, message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Validated application configuration, loaded once at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub company: CompanyConfig,
+    pub accounts: AccountsConfig,
+    pub chat: ChatConfig,
+    pub smtp: Option<SmtpConfig>,
+    pub log: LogConfig
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // address the API listens on
+    pub addr: SocketAddr,
+    // separate address serving /metrics without auth
+    pub metrics_addr: Option<SocketAddr>
+}
+
+#[derive(Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    // apply pending embedded migrations when the server starts
+    pub run_migrations: bool
+}
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    // secret used for signing JWTs
+    pub token_secret: String,
+    // salt used for hashing passwords
+    pub password_salt: [u8; 16],
+    // lifetime in seconds of AuthClaims tokens
+    pub token_lifetime: u64,
+    // lifetime in seconds of AuthRequesterClaims tokens
+    pub requester_token_lifetime: u64,
+    // usernames that cannot be registered
+    pub reserved_usernames: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct CompanyConfig {
+    // set as the com claim in JWTs and used in emails
+    pub name: String,
+    // set as the aud claim in JWTs and used for links in emails
+    pub domain: String
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountsConfig {
+    // seconds a soft deleted account can be restored before it is erased
+    pub deletion_grace_period: u64,
+    // seconds between runs of the erasure job
+    pub erasure_interval: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatConfig {
+    // messages replayed after the websocket handshake and default page size of the history
+    pub history_limit: u32,
+    // seconds between pings to every chat connection
+    pub ping_interval: u64,
+    // seconds a connection may stay silent after a ping, or take to accept a frame, before it is dropped
+    pub pong_timeout: u64,
+    // seconds a new connection has to send its auth frame
+    pub handshake_timeout: u64,
+    // largest inbound websocket message in bytes, bigger ones close the connection
+    pub max_frame_bytes: usize,
+    // frames queued per connection before broadcasts to it lag behind
+    pub outbound_queue: usize,
+    // how chat events reach the other server instances
+    pub pubsub: PubSubKind,
+    // seconds between the snapshots of who is connected to this instance, an instance
+    // missing three of them is considered gone
+    pub presence_interval: u64
+}
+
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub username: String,
+    pub password: String
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    // tracing EnvFilter directives
+    pub filter: String
+}
+
+// secrets are never printed
+impl fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("url", &REDACTED)
+            .field("max_connections", &self.max_connections)
+            .field("run_migrations", &self.run_migrations)
+            .finish()
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("token_secret", &REDACTED)
+            .field("password_salt", &REDACTED)
+            .field("token_lifetime", &self.token_lifetime)
+            .field("requester_token_lifetime", &self.requester_token_lifetime)
+            .field("reserved_usernames", &self.reserved_usernames)
+            .finish()
+    }
+}
+
+impl fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+// layout of the config file, every field can be left out and supplied by an environment variable
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    server: RawServerConfig,
+    database: RawDatabaseConfig,
+    auth: RawAuthConfig,
+    company: RawCompanyConfig,
+    accounts: RawAccountsConfig,
+    chat: RawChatConfig,
+    smtp: RawSmtpConfig,
+    log: RawLogConfig
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawServerConfig {
+    host: Option<IpAddr>,
+    port: Option<u16>,
+    metrics_addr: Option<SocketAddr>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawDatabaseConfig {
+    url: Option<String>,
+    max_connections: Option<u32>,
+    run_migrations: Option<bool>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawAuthConfig {
+    token_secret: Option<String>,
+    password_salt: Option<String>,
+    token_lifetime: Option<u64>,
+    requester_token_lifetime: Option<u64>,
+    reserved_usernames: Option<Vec<String>>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawCompanyConfig {
+    name: Option<String>,
+    domain: Option<String>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawAccountsConfig {
+    deletion_grace_period: Option<u64>,
+    erasure_interval: Option<u64>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawChatConfig {
+    history_limit: Option<u32>,
+    ping_interval: Option<u64>,
+    pong_timeout: Option<u64>,
+    handshake_timeout: Option<u64>,
+    max_frame_bytes: Option<usize>,
+    outbound_queue: Option<usize>,
+    pubsub: Option<PubSubKind>,
+    presence_interval: Option<u64>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawSmtpConfig {
+    host: Option<String>,
+    username: Option<String>,
+    password: Option<String>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawLogConfig {
+    format: Option<LogFormat>,
+    filter: Option<String>
+}
+
+impl Config {
+    // load config file named by CONFIG_FILE (default config.toml) and apply environment overrides
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match env::var("CONFIG_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false)
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            // the default config file is optional, everything can come from the environment
+            Err(error) if error.kind() == io::ErrorKind::NotFound && !required => String::new(),
+            Err(error) => return Err(ConfigError::Read { path, error })
+        };
+        let raw: RawConfig = toml::from_str(&contents)
+            .map_err(|error| ConfigError::Parse { path, error })?;
+        Self::from_raw(raw, |name| env::var(name).ok())
+    }
+    // build config from TOML contents and an environment lookup, used by tests and embedders
+    pub fn from_toml<F>(contents: &str, lookup: F) -> Result<Config, ConfigError>
+    where F: Fn(&str) -> Option<String> {
+        let raw: RawConfig = toml::from_str(contents)
+            .map_err(|error| ConfigError::Parse { path: PathBuf::from("<inline>"), error })?;
+        Self::from_raw(raw, lookup)
+    }
+    fn from_raw<F>(mut raw: RawConfig, lookup: F) -> Result<Config, ConfigError>
+    where F: Fn(&str) -> Option<String> {
+        raw.apply_env(&lookup)?;
+        raw.validate()
+    }
+}
+
+// overwrite target with parsed environment variable if it is set
+fn env_override<T, F>(target: &mut Option<T>, lookup: &F, key: &'static str, name: &'static str) -> Result<(), ConfigError>
+where T: FromStr, T::Err: fmt::Display, F: Fn(&str) -> Option<String> {
+    if let Some(value) = lookup(name) {
+        let value = value.parse::<T>()
+            .map_err(|error| ConfigError::Invalid { key, message: format!("{} from {}", error, name) })?;
+        *target = Some(value);
+    }
+    Ok(())
+}
+
+// take required setting or report which key and environment variable are missing
+fn required<T>(value: Option<T>, key: &'static str, env: &'static str) -> Result<T, ConfigError> {
+    value.ok_or(ConfigError::Missing { key, env })
+}
+
+// treat empty strings the same as unset values
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+impl RawConfig {
+    fn apply_env<F>(&mut self, lookup: &F) -> Result<(), ConfigError>
+    where F: Fn(&str) -> Option<String> {
+        env_override(&mut self.server.host, lookup, "server.host", "SERVER_HOST")?;
+        env_override(&mut self.server.port, lookup, "server.port", "SERVER_PORT")?;
+        env_override(&mut self.server.metrics_addr, lookup, "server.metrics_addr", "METRICS_ADDR")?;
+        env_override(&mut self.database.url, lookup, "database.url", "DATABASE_URL")?;
+        env_override(&mut self.database.max_connections, lookup, "database.max_connections", "DATABASE_MAX_CONNECTIONS")?;
+        env_override(&mut self.database.run_migrations, lookup, "database.run_migrations", "DATABASE_RUN_MIGRATIONS")?;
+        env_override(&mut self.auth.token_secret, lookup, "auth.token_secret", "AUTH_TOKEN_SECRET")?;
+        env_override(&mut self.auth.password_salt, lookup, "auth.password_salt", "PASSWORD_SALT")?;
+        env_override(&mut self.auth.token_lifetime, lookup, "auth.token_lifetime", "AUTH_TOKEN_EXPIRE")?;
+        env_override(&mut self.auth.requester_token_lifetime, lookup, "auth.requester_token_lifetime", "AUTH_REQUEST_TOKEN_EXPIRE")?;
+        env_override(&mut self.company.name, lookup, "company.name", "COMPANY_NAME")?;
+        env_override(&mut self.company.domain, lookup, "company.domain", "COMPANY_DOMAIN")?;
+        env_override(&mut self.accounts.deletion_grace_period, lookup, "accounts.deletion_grace_period", "ACCOUNT_DELETION_GRACE_PERIOD")?;
+        env_override(&mut self.accounts.erasure_interval, lookup, "accounts.erasure_interval", "ACCOUNT_ERASURE_INTERVAL")?;
+        env_override(&mut self.chat.history_limit, lookup, "chat.history_limit", "CHAT_HISTORY_LIMIT")?;
+        env_override(&mut self.chat.ping_interval, lookup, "chat.ping_interval", "CHAT_PING_INTERVAL")?;
+        env_override(&mut self.chat.pong_timeout, lookup, "chat.pong_timeout", "CHAT_PONG_TIMEOUT")?;
+        env_override(&mut self.chat.handshake_timeout, lookup, "chat.handshake_timeout", "CHAT_HANDSHAKE_TIMEOUT")?;
+        env_override(&mut self.chat.max_frame_bytes, lookup, "chat.max_frame_bytes", "CHAT_MAX_FRAME_BYTES")?;
+        env_override(&mut self.chat.outbound_queue, lookup, "chat.outbound_queue", "CHAT_OUTBOUND_QUEUE")?;
+        env_override(&mut self.chat.pubsub, lookup, "chat.pubsub", "CHAT_PUBSUB")?;
+        env_override(&mut self.chat.presence_interval, lookup, "chat.presence_interval", "CHAT_PRESENCE_INTERVAL")?;
+        env_override(&mut self.smtp.host, lookup, "smtp.host", "SMTP_HOST")?;
+        env_override(&mut self.smtp.username, lookup, "smtp.username", "SMTP_USERNAME")?;
+        env_override(&mut self.smtp.password, lookup, "smtp.password", "SMTP_PASSWORD")?;
+        env_override(&mut self.log.format, lookup, "log.format", "LOG_FORMAT")?;
+        env_override(&mut self.log.filter, lookup, "log.filter", "RUST_LOG")?;
+        // comma separated list of reserved usernames
+        if let Some(names) = lookup("RESERVED_USERNAMES") {
+            self.auth.reserved_usernames = Some(names.split(',').map(|name| name.to_string()).collect());
+        }
+        Ok(())
+    }
+    fn validate(self) -> Result<Config, ConfigError> {
+        let server = ServerConfig {
+            addr: SocketAddr::new(
+                self.server.host.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                self.server.port.unwrap_or(3001)),
+            metrics_addr: self.server.metrics_addr
+        };
+
+        let database = DatabaseConfig {
+            url: required(non_empty(self.database.url), "database.url", "DATABASE_URL")?,
+            max_connections: self.database.max_connections.unwrap_or(100),
+            run_migrations: self.database.run_migrations.unwrap_or(true)
+        };
+        if database.max_connections == 0 {
+            return Err(ConfigError::Invalid { key: "database.max_connections", message: String::from("must be at least 1") });
+        }
+
+        let token_secret = required(non_empty(self.auth.token_secret), "auth.token_secret", "AUTH_TOKEN_SECRET")?;
+        let salt = required(non_empty(self.auth.password_salt), "auth.password_salt", "PASSWORD_SALT")?;
+        // bcrypt takes exactly 16 bytes of salt, longer values are truncated
+        let mut password_salt: [u8; 16] = [0; 16];
+        match salt.as_bytes().get(0..16) {
+            Some(bytes) => password_salt.copy_from_slice(bytes),
+            None => return Err(ConfigError::Invalid { key: "auth.password_salt", message: String::from("must be at least 16 bytes") })
+        }
+        let token_lifetime = required(self.auth.token_lifetime, "auth.token_lifetime", "AUTH_TOKEN_EXPIRE")?;
+        let requester_token_lifetime = required(self.auth.requester_token_lifetime, "auth.requester_token_lifetime", "AUTH_REQUEST_TOKEN_EXPIRE")?;
+        if token_lifetime == 0 || requester_token_lifetime == 0 {
+            return Err(ConfigError::Invalid { key: "auth.token_lifetime", message: String::from("token lifetimes must be at least 1 second") });
+        }
+        let reserved_usernames = match self.auth.reserved_usernames {
+            Some(names) => names.into_iter()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            None => DEFAULT_RESERVED_USERNAMES.iter().map(|name| name.to_string()).collect()
+        };
+        let auth = AuthConfig { token_secret, password_salt, token_lifetime, requester_token_lifetime, reserved_usernames };
+
+        let company = CompanyConfig {
+            name: required(non_empty(self.company.name), "company.name", "COMPANY_NAME")?,
+            domain: required(non_empty(self.company.domain), "company.domain", "COMPANY_DOMAIN")?
+        };
+
+        let accounts = AccountsConfig {
+            // 30 days
+            deletion_grace_period: self.accounts.deletion_grace_period.unwrap_or(30 * 24 * 3600),
+            erasure_interval: self.accounts.erasure_interval.unwrap_or(3600)
+        };
+        if accounts.erasure_interval == 0 {
+            return Err(ConfigError::Invalid { key: "accounts.erasure_interval", message: String::from("must be at least 1 second") });
+        }
+
+        let chat = ChatConfig {
+            history_limit: self.chat.history_limit.unwrap_or(CHAT_HISTORY_DEFAULT_LIMIT),
+            ping_interval: self.chat.ping_interval.unwrap_or(30),
+            pong_timeout: self.chat.pong_timeout.unwrap_or(10),
+            handshake_timeout: self.chat.handshake_timeout.unwrap_or(10),
+            // 16 KiB
+            max_frame_bytes: self.chat.max_frame_bytes.unwrap_or(16 * 1024),
+            outbound_queue: self.chat.outbound_queue.unwrap_or(64),
+            pubsub: self.chat.pubsub.unwrap_or(PubSubKind::Memory),
+            presence_interval: self.chat.presence_interval.unwrap_or(30)
+        };
+        if chat.history_limit == 0 || chat.history_limit > CHAT_HISTORY_MAX_LIMIT {
+            return Err(ConfigError::Invalid { key: "chat.history_limit", message: format!("must be between 1 and {}", CHAT_HISTORY_MAX_LIMIT) });
+        }
+        if chat.ping_interval == 0 || chat.pong_timeout == 0 {
+            return Err(ConfigError::Invalid { key: "chat.ping_interval", message: String::from("ping interval and pong timeout must be at least 1 second") });
+        }
+        if chat.presence_interval == 0 {
+            return Err(ConfigError::Invalid { key: "chat.presence_interval", message: String::from("must be at least 1 second") });
+        }
+        if chat.handshake_timeout == 0 {
+            return Err(ConfigError::Invalid { key: "chat.handshake_timeout", message: String::from("must be at least 1 second") });
+        }
+        // the auth frame has to fit
+        if chat.max_frame_bytes < 1024 {
+            return Err(ConfigError::Invalid { key: "chat.max_frame_bytes", message: String::from("must be at least 1024") });
+        }
+        if chat.outbound_queue == 0 {
+            return Err(ConfigError::Invalid { key: "chat.outbound_queue", message: String::from("must be at least 1") });
+        }
+        // LISTEN/NOTIFY needs the database to be Postgres
+        if chat.pubsub == PubSubKind::Postgres && !database.url.starts_with("postgres") {
+            return Err(ConfigError::Invalid { key: "chat.pubsub", message: String::from("postgres requires a postgres database url") });
+        }
+
+        // smtp is optional, but partially configured smtp is a mistake
+        let smtp = match (non_empty(self.smtp.host), non_empty(self.smtp.username), non_empty(self.smtp.password)) {
+            (None, None, None) => None,
+            (Some(host), Some(username), Some(password)) => Some(SmtpConfig { host, username, password }),
+            (host, username, _) => {
+                let (key, env) = if host.is_none() {
+                    ("smtp.host", "SMTP_HOST")
+                } else if username.is_none() {
+                    ("smtp.username", "SMTP_USERNAME")
+                } else {
+                    ("smtp.password", "SMTP_PASSWORD")
+                };
+                return Err(ConfigError::Missing { key, env });
+            }
+        };
+
+        let log = LogConfig {
+            format: self.log.format.unwrap_or(LogFormat::Pretty),
+            filter: non_empty(self.log.filter).unwrap_or_else(|| DEFAULT_LOG_FILTER.to_string())
+        };
+
+        Ok(Config { server, database, auth, company, accounts, chat, smtp, log })
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, env, fs, str::FromStr, sync::Arc, time::{Duration, SystemTime}};
+use std::{collections::HashMap, str::FromStr, time::{Duration, SystemTime}};
 
 use axum::{
     extract::{Path, Request, State}, http::StatusCode, middleware, routing::{get, post}, Json, Router
@@ -7,235 +7,176 @@ use bcrypt::verify;
 use email_address::EmailAddress;
 use futures::lock::Mutex;
 use http::{header::AUTHORIZATION, HeaderMap, HeaderValue};
-use lettre::{message::header::ContentType, transport::smtp::{authentication::Credentials, client::Tls}, Message, SmtpTransport, Transport};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
-use types::{auth::{AuthErrorType, AuthToken}, user::{LoginUser, RegisterUser, ResetUser, UserInfo}};
+use types::{auth::{AuthErrorType, AuthToken}, user::{LoginUser, RegisterUser, ResetUser, UserInfo}, username::validate_username};
 
-use crate::{middleware::token_authentication, strategies::{authentication::{AuthClaims, AuthError, AuthRequesterClaims, Claims}, users}};
+use crate::{app_error::AppError, mail::Email, middleware::token_authentication, monitoring, state::AppState, strategies::authentication::{AuthClaims, AuthError, AuthRequesterClaims, Claims}};
+
+// html template for password reset emails
+const RESET_TEMPLATE: &str = include_str!("../../resources/reset_template.html");
 
 struct TimeStampedEmail {
     time_stamp: SystemTime,
     email: EmailAddress
 }
-struct ResetKeysState {
+
+// pending password reset keys
+#[derive(Default)]
+pub struct ResetKeysState {
     keys: Mutex<HashMap<String, TimeStampedEmail>>
 }
 
 // route function to nest endpoints in router
-pub fn routes() -> Router {
-    let keys = Mutex::new(HashMap::<String, TimeStampedEmail>::new());
-    let key_state = Arc::new(ResetKeysState{keys});
+pub fn routes(state: AppState) -> Router<AppState> {
     // create routes
     Router::new()
         // create nested router for routes requiring AuthClaims
         .nest("/test", Router::new()
             .route("/", get(test_auth_route)))
-            .layer(middleware::from_fn(token_authentication::authenticate_token::<AuthClaims>))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>))
         // create nested router for routes requiring AuthRequesterClaims
         .nest("/request", Router::new()
             .route("/",get(request_auth_token))
-            .layer(middleware::from_fn(token_authentication::authenticate_token::<AuthRequesterClaims>)))
+            .layer(middleware::from_fn_with_state(state, token_authentication::authenticate_token::<AuthRequesterClaims>)))
         // routes that do not need middleware
         .route("/login", post(login_user))
         .route("/register", post(register_user))
         .nest("/reset", Router::new()
             .route("/", post(request_reset))
-            .route("/:reset_key", post(reset_password))
-            .with_state(key_state))
+            .route("/:reset_key", post(reset_password)))
 }
 
-async fn test_auth_route(request: Request) -> Result<(StatusCode, String), AuthError> {
+async fn test_auth_route(request: Request) -> Result<(StatusCode, String), AppError> {
     // generate AuthClaims from encoded x-claim header
-    let claims = AuthClaims::from_header(request.headers());
-    println!("Authentication claims: {:?}", claims);
+    let claims = AuthClaims::from_header(request.headers())?;
+    tracing::debug!(?claims, "Authentication claims");
     // return with OK Status and body containing verified response
     Ok((StatusCode::OK, "Auth verified".to_string()))
 }
 
-async fn request_auth_token(request: Request) -> Result<(StatusCode, HeaderMap), AuthError> {
+// build header map with token inserted into Authorization header
+fn auth_header(auth_token: AuthToken) -> Result<HeaderMap, AppError> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(AUTHORIZATION, HeaderValue::from_str(&auth_token.to_string()).map_err(AppError::internal)?);
+    Ok(header_map)
+}
+
+async fn request_auth_token(
+    State(state): State<AppState>,
+    request: Request
+) -> Result<(StatusCode, HeaderMap), AppError> {
     // generate AuthRequesterClaims from encoded x-claim header
-    let claims = AuthRequesterClaims::from_header(request.headers());
+    let claims = AuthRequesterClaims::from_header(request.headers())?;
     // generate new AuthClaims token from UUID in AuthRequesterClaims
-    if let Ok(auth_claims) = AuthClaims::new(claims.sub.clone()).await {
-        let token_result = auth_claims.generate_token();
-        let auth_token: AuthToken;
-        match token_result {
-            Ok(token) => auth_token = token,
-            Err(error) => {
-                println!("Error creating token for UUID {}: {:?}", claims.sub, error);
-                return Err(error)
-            }
-        }
-        // insert newly generated token into Authorization header
-        let mut header_map = HeaderMap::new();
-        header_map.insert(AUTHORIZATION, HeaderValue::from_str(&auth_token.to_string()).unwrap());
-        // respond to request with token in header
-        Ok((StatusCode::CREATED, header_map.clone()))
-    } else {
-        Err(AuthError::from_error_type(AuthErrorType::AccessDenied))
-    }
+    let auth_claims = AuthClaims::new(&state, claims.sub.clone()).await?;
+    let auth_token = auth_claims.generate_token(&state)?;
+    // respond to request with token in header
+    Ok((StatusCode::CREATED, auth_header(auth_token)?))
 }
 
 // route for logging in user with provided LoginUser json
 async fn login_user(
+    State(state): State<AppState>,
     Json(payload): Json<LoginUser>,
-) -> Result<(StatusCode, HeaderMap, Json<UserInfo>), AuthError> {
+) -> Result<(StatusCode, HeaderMap, Json<UserInfo>), AppError> {
     // check if supplied credentials are not empty
     if payload.username.is_empty() || payload.pass.is_empty() {
-        return Err(AuthError::from_error_type(AuthErrorType::WrongCredentials));
-    }
-    // get user by username from database
-    let result = users::get_db_user_by_username_or_email(payload.username).await;
-    // if can't get user by username, return 400
-    if let Err(_) = result {
-        return Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist));
+        monitoring::record_login(false);
+        return Err(AppError::from_error_type(AuthErrorType::WrongCredentials));
     }
-    // unwrap result from DB as user object
-    let user = result.unwrap();
+    // get user by username from database, missing user maps to UserDoesNotExist
+    let user = match state.users.find_by_username_or_email(&payload.username).await {
+        Ok(user) => user,
+        Err(error) => {
+            monitoring::record_login(false);
+            return Err(error.into());
+        }
+    };
     // verify supplied password is validated
-    if verify(payload.pass, &user.pass).unwrap() {
+    if verify(payload.pass, &user.pass).map_err(AppError::internal)? {
+        monitoring::record_login(true);
         // build response user
         let user_info = UserInfo::from_user(user);
         // generate token from UserInfo uuid
-        let token_result = AuthRequesterClaims::new(user_info.uuid.clone()).await.unwrap().generate_token();
-        let auth_token: AuthToken;
-        match token_result {
-            Ok(token) => auth_token = token,
-            Err(error) => return Err(error)
-        }
-        // insert newly generated token into Authorization header
-        let mut header_map = HeaderMap::new();
-        header_map.insert(AUTHORIZATION, HeaderValue::from_str(&auth_token.to_string()).unwrap());
-        // respond to request with UserInfo in body
-        Ok((StatusCode::CREATED, header_map.clone(), axum::Json(user_info)))
+        let auth_token = AuthRequesterClaims::new(&state, user_info.uuid.clone()).await?.generate_token(&state)?;
+        // respond to request with UserInfo in body and token in Authorization header
+        Ok((StatusCode::CREATED, auth_header(auth_token)?, axum::Json(user_info)))
     } else {
         // respond with wrong credentials error
-        return Err(AuthError::from_error_type(AuthErrorType::WrongCredentials));
+        monitoring::record_login(false);
+        Err(AppError::from_error_type(AuthErrorType::WrongCredentials))
     }
 }
 
 
 // handler for creating a new user
 async fn register_user(
-    Json(payload): Json<RegisterUser>,
-) -> Result<(StatusCode, HeaderMap, Json<UserInfo>), AuthError> {
+    State(state): State<AppState>,
+    Json(mut payload): Json<RegisterUser>,
+) -> Result<(StatusCode, HeaderMap, Json<UserInfo>), AppError> {
     if payload.username.is_empty() || payload.pass.is_empty() || payload.email.is_empty() {
-        return Err(AuthError::from_error_type(AuthErrorType::MissingFields));
+        return Err(AppError::from_error_type(AuthErrorType::MissingFields));
+    }
+    // validate username rules and store the normalized form
+    match validate_username(&payload.username, &state.config.auth.reserved_usernames) {
+        Ok(username) => payload.username = username,
+        Err(error) => {
+            return Err(AuthError::from_error_type(AuthErrorType::InvalidUsername).with_message(error.to_string()).into());
+        }
     }
     // validate email address before inserting
     if !EmailAddress::is_valid(&payload.email) {
-        return Err(AuthError::from_error_type(AuthErrorType::InvalidEmail));
-    }
-    // insert user into table
-    let db_result = users::insert_db_user(payload).await;
-    // handle db errors
-    if let Err(error) = db_result {
-        println!("Error creating user: {}", error);
-        if error.to_string().contains("duplicate key") {
-            return Err(AuthError::from_error_type(AuthErrorType::UserAlreadyExists))
-        }
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError));
+        return Err(AppError::from_error_type(AuthErrorType::InvalidEmail));
     }
-    // unwrap returned User object
-    let user = db_result.unwrap();
+    // insert user into table, unique violations map to UsernameTaken/EmailTaken
+    let user = state.users.insert(payload, state.config.auth.password_salt).await?;
     // build UserInfo to return from User object
     let user_info = UserInfo::from_user(user);
     // generate token from UserInfo uuid
-    let token_result = AuthRequesterClaims::new(user_info.uuid.clone()).await.unwrap().generate_token();
-    let auth_token: AuthToken;
-    match token_result {
-        Ok(token) => auth_token = token,
-        Err(error) => {
-            println!("Error creating token for UUID {}: {:?}", user_info.uuid, error);
-            return Err(AuthError::from_error_type(AuthErrorType::TokenCreation))
-        }
-    }
-    // insert parsed token into headermap
-    let mut header_map = HeaderMap::new();
-    header_map.insert(AUTHORIZATION, HeaderValue::from_str(&auth_token.to_string()).unwrap());
-    // respond to request with UserInfo in body
-    Ok((StatusCode::CREATED, header_map.clone(), axum::Json(user_info)))
+    let auth_token = AuthRequesterClaims::new(&state, user_info.uuid.clone()).await?.generate_token(&state)?;
+    // respond to request with UserInfo in body and token in Authorization header
+    Ok((StatusCode::CREATED, auth_header(auth_token)?, axum::Json(user_info)))
 }
 
 async fn request_reset(
-    State(state): State<Arc<ResetKeysState>>,
+    State(state): State<AppState>,
     email_address: String
-) -> Result<StatusCode, AuthError> {
-    // parse email string
-    let email_address = EmailAddress::from_str(&email_address);
-    if let Err(_) = email_address {
-        return Err(AuthError::from_error_type(AuthErrorType::InvalidEmail));
+) -> Result<StatusCode, AppError> {
+    // reset emails cannot be sent without smtp settings
+    if !state.mailer.is_configured() {
+        return Err(AuthError::from_error_type(AuthErrorType::ServiceUnavailable)
+            .with_message(String::from("Password reset email is not configured")).into());
     }
-    let email_address = email_address.unwrap();
+    // parse email string
+    let email_address = EmailAddress::from_str(&email_address)
+        .map_err(|_| AppError::from_error_type(AuthErrorType::InvalidEmail))?;
     // ensure user exists in db
-    if let Err(_) = users::get_db_user_by_username_or_email(email_address.to_string()).await {
-        return Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist));
-    }
+    state.users.find_by_username_or_email(email_address.as_str()).await?;
     // generate reset key and insert into state
     let reset_key = gen_reset_key();
-    let mut keys = state.keys.lock().await;
-    // parse env variables for generating email content
-    let company_name =  env::var("COMPANY_NAME").unwrap();
-    let company_domain =  env::var("COMPANY_DOMAIN").unwrap();
+    let mut keys = state.reset_keys.keys.lock().await;
     keys.insert(reset_key.clone(), TimeStampedEmail{email: email_address.to_owned(), time_stamp: SystemTime::now()});
     // free mutex
     drop(keys);
-    // read html template from static path
-    let html = fs::read_to_string("crates/server/resources/reset_template.html");
-    if let Err(_) = html {
-        println!("Could not read password reset template!");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
+    let company_name = &state.config.company.name;
+    let company_domain = &state.config.company.domain;
     // replace placeholder text in html with proper information
-    let html = html.unwrap()
-        .replace("{COMPANY_NAME}", &company_name)
+    let html = RESET_TEMPLATE
+        .replace("{COMPANY_NAME}", company_name)
         .replace("{RESET_PASSWORD_URL}", &format!("{company_domain}/reset?key={reset_key}&email={email_address}"));
     // build email
-    let email = Message::builder()
-        .from(format!("{} <noreply@{}>", company_name, company_domain).parse().unwrap())
-        .to(email_address.to_string().parse().unwrap())
-        .subject(format!("Password Reset Requested for {}", company_name))
-        .header(ContentType::TEXT_HTML)
-        .body(html);
-    if let Err(_) = email {
-        println!("Could not parse email!");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
-    let email = email.unwrap();
-    // generate smtp credentials from env vars
-    let smtp_username = env::var("SMTP_USERNAME").to_owned();
-    if let Err(_) = smtp_username {
-        println!("SMTP_USERNAME environment variable not configured!");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
-    let smtp_username = smtp_username.unwrap();
-    let smtp_password = env::var("SMTP_PASSWORD").to_owned();
-    if let Err(_) = smtp_password {
-        println!("SMTP_PASSWORD environment variable not configured!");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
-    let smtp_password = smtp_password.unwrap();
-    let smtp_host = env::var("SMTP_HOST").to_owned();
-    if let Err(_) = smtp_host {
-        println!("SMTP_HOST environment variable not configured!");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
-    let smtp_host = smtp_host.unwrap();
-    let creds = Credentials::new(smtp_username, smtp_password);
-    // build mailer and send email to user email address
-    let mailer = SmtpTransport::relay(&smtp_host)
-        .unwrap()
-        .tls(Tls::None)
-        .credentials(creds)
-        .build();
-    match mailer.send(&email) {
-        Ok(_) => println!("Reset email sent successfully to {email_address}"),
-        Err(e) => {
-            println!("Failed to send email to {email_address}: {e:?}");
-            return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-        }
-    }
+    let email = Email {
+        to: email_address.to_string(),
+        subject: format!("Password Reset Requested for {}", company_name),
+        html
+    };
+    // send on a blocking thread so smtp does not stall the runtime
+    let mailer = state.mailer.clone();
+    tokio::task::spawn_blocking(move || mailer.send(email)).await
+        .map_err(AppError::internal)?
+        .map_err(AppError::internal)?;
+    tracing::info!(%email_address, "Reset email sent successfully");
     Ok(StatusCode::CREATED)
 }
 
@@ -248,41 +189,31 @@ fn gen_reset_key() -> String {
 }
 
 async fn reset_password(
-    State(key_state):  State<Arc<ResetKeysState>>,
+    State(state): State<AppState>,
     Path(reset_key): Path<String>,
     Json(reset_user): Json<ResetUser>
-) -> Result<StatusCode, AuthError> {
+) -> Result<StatusCode, AppError> {
     // retrieve user from db using reset_user email_address field
-    let db_result = users::get_db_user_by_username_or_email(reset_user.email_address.to_string()).await;
-    if let Err(_) = db_result {
-        return Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist));
-    }
-    let mut user = db_result.unwrap();
+    let mut user = state.users.find_by_username_or_email(reset_user.email_address.as_str()).await?;
     // lock state mutex
-    let mut keys = key_state.keys.lock().await;
+    let mut keys = state.reset_keys.keys.lock().await;
     // get key by passed reset_key param
-    let key = keys.get(&reset_key);
-    if let None = key {
-        return Err(AuthError::from_error_type(AuthErrorType::ResetLinkInvalid));
-    }
-    let timestamped_email = key.unwrap();
+    let timestamped_email = keys.get(&reset_key)
+        .ok_or(AppError::from_error_type(AuthErrorType::ResetLinkInvalid))?;
     // ensure timestamped_email email field is same as reset_user body email_address field
     if timestamped_email.email != reset_user.email_address {
-        return Err(AuthError::from_error_type(AuthErrorType::ResetLinkInvalid));
+        return Err(AppError::from_error_type(AuthErrorType::ResetLinkInvalid));
     }
     // ensure reset link is not over 24 hours old
     let expiration_time = 3600*24;
-    if SystemTime::now().duration_since(timestamped_email.time_stamp).unwrap() > Duration::from_secs(expiration_time) {
-        return Err(AuthError::from_error_type(AuthErrorType::ResetLinkInvalid));
+    let age = SystemTime::now().duration_since(timestamped_email.time_stamp).unwrap_or_default();
+    if age > Duration::from_secs(expiration_time) {
+        return Err(AppError::from_error_type(AuthErrorType::ResetLinkInvalid));
     }
     // update user pass field
     user.pass = reset_user.pass;
     // update db user
-    let db_result = users::update_db_user(user).await;
-    if let Err(e) = db_result {
-        println!("{e}");
-        return Err(AuthError::from_error_type(AuthErrorType::ServerError))
-    }
+    state.users.update(user, state.config.auth.password_salt).await?;
     // remove reset key from state and drop mutex
     keys.remove(&reset_key);
     drop(keys);
@@ -0,0 +1,165 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Json, Path, Query, Request, State}, http::StatusCode, middleware, routing::{get, post}, RequestExt, Router
+};
+use http::HeaderMap;
+use types::{auth::AuthErrorType, chat::{normalize_room_name, ChatContact, ChatErrorType, ChatHistoryQuery, ChatMessage, CreateRoom, ModerationAction, ModerationOverview, Room, UnreadCount, CHAT_HISTORY_MAX_LIMIT, DEFAULT_ROOM, ROOM_NAME_MAX_LENGTH}};
+
+use crate::{app_error::AppError, db_error::DbError, middleware::token_authentication, moderation, state::AppState, strategies::{authentication::{AuthClaims, Claims}, rooms}};
+
+// route function to nest endpoints in router
+pub fn routes(state: AppState) -> Router<AppState> {
+    // create routes
+    Router::new()
+        .nest("/history", Router::new()
+            .route("/", get(get_history))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/rooms", Router::new()
+            .route("/", get(get_rooms).post(create_room))
+            .route("/:name/members", post(add_room_member))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/contacts", Router::new()
+            .route("/", get(get_contacts))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/online", Router::new()
+            .route("/", get(get_online_users))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/unread", Router::new()
+            .route("/", get(get_unread_counts))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/moderation", Router::new()
+            .route("/", get(get_moderation).post(moderate))
+            .layer(middleware::from_fn_with_state(state, token_authentication::authenticate_token::<AuthClaims>)))
+}
+
+// load a room the user may read, private rooms of other users are not found
+async fn accessible_room(state: &AppState, name: &str, user_uuid: &str) -> Result<Room, AppError> {
+    let name = normalize_room_name(name).ok_or_else(|| AppError::NotFound(String::from("room not found")))?;
+    match rooms::find_accessible(state.rooms.as_ref(), &name, user_uuid).await {
+        Err(DbError::NotFound) => Err(AppError::NotFound(String::from("room not found"))),
+        result => Ok(result?)
+    }
+}
+
+// get messages of a room, or the direct messages with another user, older than the before cursor
+async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<ChatHistoryQuery>,
+    request: Request
+) -> Result<(StatusCode, Json<Vec<ChatMessage>>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let limit = query.limit.unwrap_or(state.config.chat.history_limit);
+    if limit == 0 || limit > CHAT_HISTORY_MAX_LIMIT {
+        return Err(AppError::Validation(format!("limit must be between 1 and {}", CHAT_HISTORY_MAX_LIMIT)));
+    }
+    let messages = match (query.room, query.with) {
+        (Some(_), Some(_)) => return Err(AppError::Validation(String::from("room and with cannot be combined"))),
+        // only the two participants can read a direct conversation
+        (None, Some(other_uuid)) => state.messages.direct_history(&claims.sub, &other_uuid, query.before, limit).await?,
+        (room, None) => {
+            let room = accessible_room(&state, room.as_deref().unwrap_or(DEFAULT_ROOM), &claims.sub).await?;
+            if state.rooms.is_banned(room.id, &claims.sub).await? {
+                return Err(AppError::from_error_type(AuthErrorType::AccessDenied));
+            }
+            state.messages.history(room.id, query.before, limit).await?
+        }
+    };
+    Ok((StatusCode::OK, axum::Json(messages)))
+}
+
+// list the public rooms and the private rooms of the token owner
+async fn get_rooms(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<Vec<Room>>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let rooms = state.rooms.visible_to(&claims.sub).await?;
+    Ok((StatusCode::OK, axum::Json(rooms)))
+}
+
+// create a room with the token owner as its first member
+async fn create_room(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateRoom>
+) -> Result<(StatusCode, Json<Room>), AppError> {
+    let claims = AuthClaims::from_header(&headers)?;
+    let name = normalize_room_name(&payload.name).ok_or_else(|| AppError::Validation(format!(
+        "room names are 1 to {} lowercase letters, digits, dashes or underscores", ROOM_NAME_MAX_LENGTH)))?;
+    match state.rooms.create(&name, payload.is_private, &claims.sub).await {
+        Ok(room) => Ok((StatusCode::CREATED, axum::Json(room))),
+        Err(DbError::UniqueViolation(_)) => Err(AppError::Validation(String::from("room already exists"))),
+        Err(error) => Err(error.into())
+    }
+}
+
+// add a user to a private room, only members can invite
+async fn add_room_member(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    request: Request
+) -> Result<StatusCode, AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let uuid: String = request.extract().await
+        .map_err(|error| AppError::Validation(format!("Could not read user UUID from body: {}", error)))?;
+    let room = accessible_room(&state, &name, &claims.sub).await?;
+    if !room.is_private {
+        return Err(AppError::Validation(String::from("public rooms have no members")));
+    }
+    // deleted users are not found
+    let user = state.users.find_by_uuid(&uuid).await?;
+    state.rooms.add_member(room.id, &user.uuid).await?;
+    Ok(StatusCode::OK)
+}
+
+// list the users the token owner exchanged direct messages with
+async fn get_contacts(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<Vec<ChatContact>>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let contacts = state.messages.contacts(&claims.sub).await?;
+    Ok((StatusCode::OK, axum::Json(contacts)))
+}
+
+// list the users with an open chat connection
+async fn get_online_users(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<Vec<ChatContact>>), AppError> {
+    AuthClaims::from_header(request.headers())?;
+    Ok((StatusCode::OK, axum::Json(state.chat.online_users())))
+}
+
+// count unread messages in every room the token owner has read before
+async fn get_unread_counts(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<Vec<UnreadCount>>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let counts = state.rooms.unread_counts(&claims.sub).await?;
+    Ok((StatusCode::OK, axum::Json(counts)))
+}
+
+// list active mutes and room bans, admin only
+async fn get_moderation(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<ModerationOverview>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    if !claims.acc {
+        return Err(AppError::from_error_type(AuthErrorType::AccessDenied));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    let overview = ModerationOverview {
+        mutes: state.mutes.active(now).await?,
+        bans: state.rooms.bans().await?
+    };
+    Ok((StatusCode::OK, axum::Json(overview)))
+}
+
+// apply a moderation action, admin only, the same actions are accepted over the websocket
+async fn moderate(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(action): Json<ModerationAction>
+) -> Result<StatusCode, AppError> {
+    let claims = AuthClaims::from_header(&headers)?;
+    if !claims.acc {
+        return Err(AppError::from_error_type(AuthErrorType::AccessDenied));
+    }
+    match moderation::apply(&state, &claims.sub, &action).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(error @ (ChatErrorType::RoomNotFound | ChatErrorType::UserNotFound | ChatErrorType::MessageNotFound)) => Err(AppError::NotFound(error.to_string())),
+        Err(ChatErrorType::Unavailable) => Err(AppError::Internal(String::from("moderation action could not be stored"))),
+        Err(error) => Err(AppError::Validation(error.to_string()))
+    }
+}
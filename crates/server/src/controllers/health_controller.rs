@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+
+use crate::{migrations, pool::DbPool, state::AppState};
+
+// time allowed for each readiness check before it is reported as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// result of a single readiness check
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckResult {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>
+}
+
+impl CheckResult {
+    fn pass() -> Self {
+        CheckResult { ok: true, detail: None }
+    }
+    fn fail<S: Into<String>>(detail: S) -> Self {
+        CheckResult { ok: false, detail: Some(detail.into()) }
+    }
+}
+
+// body returned by /ready
+#[derive(Serialize, Debug, Clone)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: CheckResult,
+    pub migrations: CheckResult,
+    pub mail: CheckResult
+}
+
+// body returned by /version
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub features: Vec<&'static str>
+}
+
+// route function for probes, merged at the root of the router
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/version", get(version))
+}
+
+// liveness probe, responds as long as the process can serve requests
+async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+// readiness probe, responds 503 if any dependency is unavailable
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let (database, migrations) = check_database(&state.pool).await;
+    let mail = if state.mailer.is_configured() {
+        CheckResult::pass()
+    } else {
+        CheckResult::fail("SMTP is not configured")
+    };
+    let ready = database.ok && migrations.ok && mail.ok;
+    if !ready {
+        tracing::warn!(?database, ?migrations, ?mail, "Readiness check failed");
+    }
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(Readiness { ready, database, migrations, mail }))
+}
+
+async fn version() -> Json<BuildInfo> {
+    let mut features = Vec::new();
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite");
+    }
+    if cfg!(feature = "postgres") {
+        features.push("postgres");
+    }
+    Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        features
+    })
+}
+
+// check database is reachable and all migrations are applied
+async fn check_database(pool: &DbPool) -> (CheckResult, CheckResult) {
+    let mut connection = match tokio::time::timeout(CHECK_TIMEOUT, pool.acquire()).await {
+        Ok(Ok(connection)) => connection,
+        Ok(Err(error)) => return (CheckResult::fail(error.to_string()), CheckResult::fail("Database unreachable")),
+        Err(_) => return (CheckResult::fail("Timed out acquiring connection"), CheckResult::fail("Database unreachable"))
+    };
+    let database = match tokio::time::timeout(CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(&mut connection)).await {
+        Ok(Ok(_)) => CheckResult::pass(),
+        Ok(Err(error)) => return (CheckResult::fail(error.to_string()), CheckResult::fail("Database unreachable")),
+        Err(_) => return (CheckResult::fail("Timed out running query"), CheckResult::fail("Database unreachable"))
+    };
+    let migrations = match tokio::time::timeout(CHECK_TIMEOUT, migrations::status(&mut connection)).await {
+        Ok(Ok(status)) if status.is_up_to_date() => CheckResult::pass(),
+        Ok(Ok(status)) => match status.dirty {
+            Some(version) => CheckResult::fail(format!("Migration {} failed part way", version)),
+            None => CheckResult::fail(format!("Pending migrations: {:?}", status.pending))
+        },
+        Ok(Err(error)) => CheckResult::fail(error.to_string()),
+        Err(_) => CheckResult::fail("Timed out reading migrations")
+    };
+    (database, migrations)
+}
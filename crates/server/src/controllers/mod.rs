@@ -1,3 +1,5 @@
 pub mod users_controller;
 pub mod auth_controller;
-pub mod ws_controller;
\ No newline at end of file
+pub mod ws_controller;
+pub mod chat_controller;
+pub mod health_controller;
\ No newline at end of file
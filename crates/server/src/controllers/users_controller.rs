@@ -1,71 +1,128 @@
 use axum::{
-    extract::{Json, Request}, http::StatusCode, middleware, routing::{delete, get}, RequestExt, Router
+    extract::{Json, Query, Request, State}, http::StatusCode, middleware, routing::{delete, get, post}, RequestExt, Router
 };
+use bcrypt::verify;
+use http::{header::CONTENT_DISPOSITION, HeaderMap, HeaderName};
+use types::{auth::AuthErrorType, chat::ChatErrorType, export::UserExport, page::Page, user::{DeleteAccount, UserInfo, UserListQuery, USER_PAGE_MAX_LIMIT}};
 
-use types::{auth::AuthErrorType, user::UserInfo};
-
-use crate::{middleware::token_authentication, strategies::{authentication::{AuthClaims, AuthError, AuthRequesterClaims, Claims}, users::{delete_user_by_uuid, get_all_users, get_db_user_by_uuid}}};
+use crate::{app_error::AppError, middleware::token_authentication, state::AppState, strategies::authentication::{AuthClaims, AuthRequesterClaims, Claims}};
 
 // route function to nest endpoints in router
-pub fn routes() -> Router {
+pub fn routes(state: AppState) -> Router<AppState> {
     // create routes
     Router::new()
         .nest("/info", Router::new()
             .route("/",get(get_user_info))
-            .layer(middleware::from_fn(token_authentication::authenticate_token::<AuthRequesterClaims>)))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthRequesterClaims>)))
         .nest("/all", Router::new()
             .route("/",get(get_all_user_info))
-            .layer(middleware::from_fn(token_authentication::authenticate_token::<AuthClaims>)))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/me", Router::new()
+            .route("/", delete(delete_own_account))
+            .route("/export", get(export_own_data))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
+        .nest("/restore", Router::new()
+            .route("/", post(restore_user))
+            .layer(middleware::from_fn_with_state(state.clone(), token_authentication::authenticate_token::<AuthClaims>)))
         .nest("/", Router::new()
             .route("/", delete(delete_user))
-            .layer(middleware::from_fn(token_authentication::authenticate_token::<AuthClaims>)))
+            .layer(middleware::from_fn_with_state(state, token_authentication::authenticate_token::<AuthClaims>)))
 }
 
 
 // get user info by JWT claims
-async fn get_user_info(request: Request) -> Result<(StatusCode, Json<UserInfo>), AuthError> {
+async fn get_user_info(State(state): State<AppState>, request: Request) -> Result<(StatusCode, Json<UserInfo>), AppError> {
     // generate AuthRequesterClaims from encoded x-claim header
-    let claims = AuthRequesterClaims::from_header(request.headers());
-    match get_db_user_by_uuid(claims.sub).await {
-        Ok(user) => {
-            Ok((StatusCode::OK, axum::Json(UserInfo::from_user(user))))
-        }, Err(_) => Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist))
-    }
+    let claims = AuthRequesterClaims::from_header(request.headers())?;
+    let user = state.users.find_by_uuid(&claims.sub).await?;
+    Ok((StatusCode::OK, axum::Json(UserInfo::from_user(user))))
 }
 
-// get user info by JWT claims
-async fn get_all_user_info(request: Request) -> Result<(StatusCode, Json<Vec<UserInfo>>), AuthError> {
+// get one page of users matching the query parameters, admin only
+async fn get_all_user_info(
+    State(state): State<AppState>,
+    Query(query): Query<UserListQuery>,
+    request: Request
+) -> Result<(StatusCode, Json<Page<UserInfo>>), AppError> {
     // generate AuthRequesterClaims from encoded x-claim header
-    let claims = AuthClaims::from_header(request.headers());
+    let claims = AuthClaims::from_header(request.headers())?;
     if claims.acc {
-        match get_all_users().await {
-            Ok(users) => {
-                Ok((StatusCode::OK, axum::Json(users)))
-            }, Err(_) => Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist))
+        if query.page() == 0 {
+            return Err(AppError::Validation(String::from("page starts at 1")));
         }
+        if query.limit() == 0 || query.limit() > USER_PAGE_MAX_LIMIT {
+            return Err(AppError::Validation(format!("limit must be between 1 and {}", USER_PAGE_MAX_LIMIT)));
+        }
+        let users = state.users.page(&query).await?;
+        Ok((StatusCode::OK, axum::Json(users)))
     } else {
-        Err(AuthError::from_error_type(AuthErrorType::AccessDenied))
+        Err(AppError::from_error_type(AuthErrorType::AccessDenied))
     }
 }
 
-async fn delete_user(request: Request) -> Result<StatusCode, AuthError> {
+async fn delete_user(State(state): State<AppState>, request: Request) -> Result<StatusCode, AppError> {
     // generate AuthRequesterClaims from encoded x-claim header
-    let claims = AuthClaims::from_header(request.headers());
-    let uuid = request.extract().await;
-    match uuid {
-        Ok(uuid) => {
-            if claims.acc {
-                match delete_user_by_uuid(uuid).await {
-                    Ok(_) => {
-                        Ok(StatusCode::OK)
-                    }, Err(_) => Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist))
-                }
-            } else {
-                Err(AuthError::from_error_type(AuthErrorType::AccessDenied))
+    let claims = AuthClaims::from_header(request.headers())?;
+    let uuid: String = request.extract().await
+        .map_err(|error| AppError::Validation(format!("Could not read user UUID from body: {}", error)))?;
+    if claims.acc {
+        // no deleted rows means no user had the uuid, database failures surface as themselves
+        match state.users.delete(&uuid).await? {
+            0 => Err(AppError::from_error_type(AuthErrorType::UserDoesNotExist)),
+            _ => {
+                // open chat sockets authenticated before the delete
+                state.chat.disconnect_user(&uuid, ChatErrorType::SessionRevoked);
+                Ok(StatusCode::OK)
             }
-        }, Err(error) => {
-            println!("{error}");
-            Err(AuthError::from_error_type(AuthErrorType::ServerError))
         }
+    } else {
+        Err(AppError::from_error_type(AuthErrorType::AccessDenied))
     }
-}
\ No newline at end of file
+}
+
+// undo a soft delete before the grace period ends, admin only
+async fn restore_user(State(state): State<AppState>, request: Request) -> Result<StatusCode, AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let uuid: String = request.extract().await
+        .map_err(|error| AppError::Validation(format!("Could not read user UUID from body: {}", error)))?;
+    if claims.acc {
+        // erased or never deleted users cannot be restored
+        match state.users.restore(&uuid).await? {
+            0 => Err(AppError::from_error_type(AuthErrorType::UserDoesNotExist)),
+            _ => Ok(StatusCode::OK)
+        }
+    } else {
+        Err(AppError::from_error_type(AuthErrorType::AccessDenied))
+    }
+}
+
+// soft delete the account of the token owner, the password must be re-entered
+async fn delete_own_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<DeleteAccount>
+) -> Result<StatusCode, AppError> {
+    let claims = AuthClaims::from_header(&headers)?;
+    if payload.pass.is_empty() {
+        return Err(AppError::from_error_type(AuthErrorType::MissingFields));
+    }
+    let user = state.users.find_by_uuid(&claims.sub).await?;
+    if !verify(payload.pass, &user.pass).map_err(AppError::internal)? {
+        return Err(AppError::from_error_type(AuthErrorType::WrongCredentials));
+    }
+    state.users.delete(&user.uuid).await?;
+    state.chat.disconnect_user(&user.uuid, ChatErrorType::SessionRevoked);
+    Ok(StatusCode::OK)
+}
+
+// download everything the server holds about the token owner as one JSON document
+async fn export_own_data(
+    State(state): State<AppState>,
+    request: Request
+) -> Result<(StatusCode, [(HeaderName, String); 1], Json<UserExport>), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    let user = state.users.find_by_uuid(&claims.sub).await?;
+    let export = state.exporters.export(&state, &user).await?;
+    let disposition = format!("attachment; filename=\"user-{}.json\"", user.uuid);
+    Ok((StatusCode::OK, [(CONTENT_DISPOSITION, disposition)], axum::Json(export)))
+}
@@ -1,5 +1,6 @@
-use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use axum::{
     routing::get,
     Router
@@ -7,72 +8,921 @@ use axum::{
 use axum::{
     extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State}, response::IntoResponse
 };
-use tokio::sync::broadcast;
-use futures::{sink::SinkExt, stream::StreamExt};
+use tokio::{sync::{broadcast, mpsc}, task::JoinHandle};
+use futures::{sink::SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
+use types::{chat::{normalize_room_name, ChatContact, ChatErrorType, ChatMessage, ClientMessage, ModerationAction, ReadMarker, Room, RoomResume, ServerMessage, CHAT_PROTOCOL_VERSION, DEFAULT_ROOM, TYPING_THROTTLE_SECS, TYPING_TIMEOUT_SECS}, user::User};
+use uuid::Uuid;
 
-use crate::strategies::authentication::{AuthRequesterClaims, Claims};
-use crate::strategies::users::get_db_user_by_uuid;
+use crate::db_error::DbError;
+use crate::moderation;
+use crate::monitoring;
+use crate::pubsub::{ChatEvent, PubSub};
+use crate::state::AppState;
+use crate::strategies::{authentication::{AuthRequesterClaims, Claims}, messages::MessageTarget, rooms, users::UserRepositoryError};
 
-struct AppState {
-    user_set: Mutex<HashSet<String>>,
-    tx: broadcast::Sender<String>
+// snapshot intervals an instance may stay silent before its users are taken offline
+const INSTANCE_TTL_INTERVALS: u32 = 3;
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// frames queued for one connection, every joined room and direct message feeds into it
+enum Outbound {
+    Frame(ServerMessage),
+    // tell the client why and close the socket
+    Close(ChatErrorType)
+}
+
+// instructions for the receive loop of a connection from outside of it
+enum Control {
+    // stop receiving the room, used when the user is banned from it
+    Leave(String),
+    // close the connection, used when the account is deleted
+    Disconnect(ChatErrorType)
+}
+
+struct ConnectionHandle {
+    tx: mpsc::Sender<Outbound>,
+    control: mpsc::UnboundedSender<Control>,
+    // frames dropped because the queue was full, reported to the client by the send task
+    missed: Arc<AtomicU64>
+}
+
+// open connections of one user, a user is online while they have at least one
+struct OnlineUser {
+    username: String,
+    // by connection id
+    connections: HashMap<u64, ConnectionHandle>,
+    // connections in each room by name, the user is in a room while one of them is
+    rooms: HashMap<String, usize>
+}
+
+// user online on any instance
+struct Presence {
+    username: String,
+    // instances holding connections of the user
+    instances: HashSet<String>
+}
+
+// room broadcast channels and the connections of every online user,
+// events pass through the pub/sub backend so they reach the connections of every instance
+pub struct ChatState {
+    // created when a room is first joined on this instance and kept for the lifetime of the server
+    rooms: Mutex<HashMap<String, broadcast::Sender<ServerMessage>>>,
+    // users connected to this instance by uuid, used to route direct messages
+    online: Mutex<HashMap<String, OnlineUser>>,
+    // users connected to any instance by uuid
+    presence: Mutex<HashMap<String, Presence>>,
+    // other instances by when they were last heard of, silent ones are considered gone
+    instances: Mutex<HashMap<String, Instant>>,
+    next_connection_id: AtomicU64,
+    // identifies this instance in presence events
+    instance: String,
+    // time between two snapshots of this instance
+    presence_interval: Duration,
+    // drained in order by the task publishing to the backend
+    events: mpsc::UnboundedSender<ChatEvent>
+}
+
+impl ChatState {
+    // start publishing to and dispatching from the backend, must be called within a tokio runtime
+    pub fn new(pubsub: Arc<dyn PubSub>, presence_interval: Duration) -> Arc<Self> {
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let chat = Arc::new(Self {
+            rooms: Mutex::new(HashMap::new()),
+            online: Mutex::new(HashMap::new()),
+            presence: Mutex::new(HashMap::new()),
+            instances: Mutex::new(HashMap::new()),
+            next_connection_id: AtomicU64::new(0),
+            instance: Uuid::new_v4().to_string(),
+            presence_interval,
+            events
+        });
+        // subscribed before anything is published, so no event of this instance is missed
+        let subscription = pubsub.subscribe();
+        tokio::spawn(publish_events(pubsub, events_rx));
+        tokio::spawn(dispatch_events(Arc::downgrade(&chat), subscription));
+        tokio::spawn(heartbeat(Arc::downgrade(&chat)));
+        // learn who is online on the instances that are already running
+        chat.publish(ChatEvent::SnapshotRequest { instance: chat.instance.clone() });
+        chat
+    }
+    fn publish(&self, event: ChatEvent) {
+        let _ = self.events.send(event);
+    }
+    fn subscribe(&self, name: &str) -> broadcast::Receiver<ServerMessage> {
+        self.rooms.lock().unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| broadcast::channel(100).0)
+            .subscribe()
+    }
+    // send a frame to everyone in the room on every instance
+    pub(crate) fn send_to_room(&self, room: &str, message: ServerMessage) {
+        self.publish(ChatEvent::Room { room: room.to_string(), message });
+    }
+    // add a connection of the user, announces the user if it is their first on this instance
+    fn register(&self, user: &User, connection: ConnectionHandle) -> u64 {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let mut online = self.online.lock().unwrap();
+        let came_online = !online.contains_key(&user.uuid);
+        online.entry(user.uuid.to_string())
+            .or_insert_with(|| OnlineUser { username: user.username.to_string(), connections: HashMap::new(), rooms: HashMap::new() })
+            .connections.insert(id, connection);
+        if came_online {
+            metrics::gauge!(monitoring::CHAT_USERS_ONLINE).increment(1.0);
+            // published while the lock is held, so the presence events of a user stay in order
+            self.publish(ChatEvent::Presence { instance: self.instance.clone(), user: contact(user), online: true });
+        }
+        id
+    }
+    // remove a connection of the user, announces the user left if it was their last on this instance
+    fn unregister(&self, user: &User, id: u64) {
+        let mut online = self.online.lock().unwrap();
+        let Some(online_user) = online.get_mut(&user.uuid) else {
+            return;
+        };
+        online_user.connections.remove(&id);
+        if online_user.connections.is_empty() {
+            online.remove(&user.uuid);
+            metrics::gauge!(monitoring::CHAT_USERS_ONLINE).decrement(1.0);
+            self.publish(ChatEvent::Presence { instance: self.instance.clone(), user: contact(user), online: false });
+        }
+    }
+    // count a connection of the user in the room, true if it is their first one in the room on this instance
+    fn enter_room(&self, user_uuid: &str, room: &str) -> bool {
+        let mut online = self.online.lock().unwrap();
+        let Some(online_user) = online.get_mut(user_uuid) else {
+            return true;
+        };
+        let count = online_user.rooms.entry(room.to_string()).or_default();
+        *count += 1;
+        *count == 1
+    }
+    // uncount a connection of the user, true if it was their last one in the room on this instance
+    fn exit_room(&self, user_uuid: &str, room: &str) -> bool {
+        let mut online = self.online.lock().unwrap();
+        let Some(online_user) = online.get_mut(user_uuid) else {
+            return true;
+        };
+        let Some(count) = online_user.rooms.get_mut(room) else {
+            return true;
+        };
+        *count -= 1;
+        if *count > 0 {
+            return false;
+        }
+        online_user.rooms.remove(room);
+        true
+    }
+    // queue a frame on every connection of the user on every instance
+    pub(crate) fn send_to_user(&self, user_uuid: &str, message: &ServerMessage) {
+        self.publish(ChatEvent::User { uuid: user_uuid.to_string(), message: message.clone() });
+    }
+    // a full queue drops the frame for that connection
+    fn send_to_connections(online_user: &OnlineUser, message: &ServerMessage) {
+        for connection in online_user.connections.values() {
+            if connection.tx.try_send(Outbound::Frame(message.clone())).is_err() {
+                connection.missed.fetch_add(1, Ordering::Relaxed);
+                metrics::counter!(monitoring::CHAT_BROADCAST_LAGGED_TOTAL).increment(1);
+            }
+        }
+    }
+    // make every connection of the user leave the room
+    pub(crate) fn remove_from_room(&self, user_uuid: &str, room: &str) {
+        self.publish(ChatEvent::Leave { uuid: user_uuid.to_string(), room: room.to_string() });
+    }
+    // close every connection of the user, they have to authenticate again
+    pub(crate) fn disconnect_user(&self, user_uuid: &str, reason: ChatErrorType) {
+        self.publish(ChatEvent::Disconnect { uuid: user_uuid.to_string(), reason });
+    }
+    // users with at least one open connection on any instance, ordered by username
+    pub fn online_users(&self) -> Vec<ChatContact> {
+        let mut users: Vec<ChatContact> = self.presence.lock().unwrap().iter()
+            .map(|(uuid, presence)| ChatContact { uuid: uuid.to_string(), username: presence.username.to_string() })
+            .collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        users
+    }
+    // apply an event of any instance to the connections of this one
+    fn dispatch(&self, event: ChatEvent) {
+        match event {
+            ChatEvent::Room { room, message } => {
+                // rooms nobody joined on this instance have no channel
+                if let Some(room_tx) = self.rooms.lock().unwrap().get(&room) {
+                    let _ = room_tx.send(message);
+                }
+            },
+            ChatEvent::User { uuid, message } => {
+                if let Some(online_user) = self.online.lock().unwrap().get(&uuid) {
+                    Self::send_to_connections(online_user, &message);
+                }
+            },
+            ChatEvent::Leave { uuid, room } => {
+                if let Some(online_user) = self.online.lock().unwrap().get(&uuid) {
+                    for connection in online_user.connections.values() {
+                        let _ = connection.control.send(Control::Leave(room.clone()));
+                    }
+                }
+            },
+            ChatEvent::Disconnect { uuid, reason } => {
+                if let Some(online_user) = self.online.lock().unwrap().get(&uuid) {
+                    tracing::info!(username = %online_user.username, ?reason, "Disconnecting chat user");
+                    for connection in online_user.connections.values() {
+                        let _ = connection.control.send(Control::Disconnect(reason));
+                    }
+                }
+            },
+            ChatEvent::Presence { instance, user, online } => {
+                self.heard_of(&instance);
+                self.update_presence(&instance, user, online);
+            },
+            ChatEvent::Snapshot { instance, users } => {
+                self.heard_of(&instance);
+                self.apply_snapshot(&instance, users);
+            },
+            ChatEvent::SnapshotRequest { instance } => {
+                if instance != self.instance {
+                    self.publish_snapshot();
+                }
+            },
+            ChatEvent::Lost => {
+                tracing::warn!("Chat events were lost, resyncing");
+                // how many is unknown, every connection reloads and presence is asked for again
+                self.lagged(1);
+                self.publish(ChatEvent::SnapshotRequest { instance: self.instance.clone() });
+                self.publish_snapshot();
+            }
+        }
+    }
+    fn heard_of(&self, instance: &str) {
+        if instance != self.instance {
+            self.instances.lock().unwrap().insert(instance.to_string(), Instant::now());
+        }
+    }
+    // replace what is known about the users of the instance
+    fn apply_snapshot(&self, instance: &str, users: Vec<ChatContact>) {
+        // users missing from the snapshot left that instance while events were lost
+        let gone: Vec<ChatContact> = self.presence.lock().unwrap().iter()
+            .filter(|(uuid, presence)| presence.instances.contains(instance) && !users.iter().any(|user| &user.uuid == *uuid))
+            .map(|(uuid, presence)| ChatContact { uuid: uuid.to_string(), username: presence.username.to_string() })
+            .collect();
+        for user in gone {
+            self.update_presence(instance, user, false);
+        }
+        for user in users {
+            self.update_presence(instance, user, true);
+        }
+    }
+    // tell every instance who is connected to this one
+    fn publish_snapshot(&self) {
+        let users = self.online.lock().unwrap().iter()
+            .map(|(uuid, online_user)| ChatContact { uuid: uuid.to_string(), username: online_user.username.to_string() })
+            .collect();
+        self.publish(ChatEvent::Snapshot { instance: self.instance.clone(), users });
+    }
+    // instances that crashed or lost their backend connection take their users offline
+    fn expire_instances(&self) {
+        let ttl = self.presence_interval * INSTANCE_TTL_INTERVALS;
+        let expired: Vec<String> = {
+            let mut instances = self.instances.lock().unwrap();
+            let expired = instances.iter()
+                .filter(|(_, heard_at)| heard_at.elapsed() > ttl)
+                .map(|(instance, _)| instance.to_string())
+                .collect::<Vec<_>>();
+            for instance in &expired {
+                instances.remove(instance);
+            }
+            expired
+        };
+        for instance in expired {
+            tracing::info!(%instance, "Chat instance stopped sending snapshots");
+            self.apply_snapshot(&instance, Vec::new());
+        }
+    }
+    // a user is announced when their first instance gains and their last one loses them
+    fn update_presence(&self, instance: &str, user: ChatContact, is_online: bool) {
+        let changed = {
+            let mut presence = self.presence.lock().unwrap();
+            if is_online {
+                let entry = presence.entry(user.uuid.clone())
+                    .or_insert_with(|| Presence { username: user.username.clone(), instances: HashSet::new() });
+                let came_online = entry.instances.is_empty();
+                entry.instances.insert(instance.to_string());
+                came_online
+            } else {
+                let went_offline = presence.get_mut(&user.uuid)
+                    .is_some_and(|entry| entry.instances.remove(instance) && entry.instances.is_empty());
+                if went_offline {
+                    presence.remove(&user.uuid);
+                }
+                went_offline
+            }
+        };
+        if changed {
+            let message = ServerMessage::Presence { user, online: is_online };
+            for online_user in self.online.lock().unwrap().values() {
+                Self::send_to_connections(online_user, &message);
+            }
+        }
+    }
+    // events were lost, every connection has to resync
+    fn lagged(&self, skipped: u64) {
+        for online_user in self.online.lock().unwrap().values() {
+            for connection in online_user.connections.values() {
+                connection.missed.fetch_add(skipped, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+fn contact(user: &User) -> ChatContact {
+    ChatContact { uuid: user.uuid.to_string(), username: user.username.to_string() }
+}
+
+// hand the events of this instance to the backend in the order they were queued
+async fn publish_events(pubsub: Arc<dyn PubSub>, mut events: mpsc::UnboundedReceiver<ChatEvent>) {
+    while let Some(event) = events.recv().await {
+        if let Err(error) = pubsub.publish(&event).await {
+            metrics::counter!(monitoring::CHAT_PUBSUB_ERRORS_TOTAL).increment(1);
+            tracing::error!(%error, "Could not publish chat event");
+        }
+    }
+}
+
+// send snapshots of this instance and expire silent ones until the chat state is dropped
+async fn heartbeat(chat: Weak<ChatState>) {
+    let Some(interval) = chat.upgrade().map(|chat| chat.presence_interval) else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes at once, the snapshot request already covers the start
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(chat) = chat.upgrade() else {
+            break;
+        };
+        chat.publish_snapshot();
+        chat.expire_instances();
+    }
+}
+
+// apply the events of every instance until the chat state is dropped
+async fn dispatch_events(chat: Weak<ChatState>, mut subscription: broadcast::Receiver<ChatEvent>) {
+    loop {
+        let event = subscription.recv().await;
+        let Some(chat) = chat.upgrade() else {
+            break;
+        };
+        match event {
+            Ok(event) => chat.dispatch(event),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                metrics::counter!(monitoring::CHAT_PUBSUB_LAGGED_TOTAL).increment(skipped);
+                tracing::warn!(skipped, "Chat events lagged behind the pub/sub backend");
+                chat.lagged(skipped);
+            },
+            Err(broadcast::error::RecvError::Closed) => break
+        }
+    }
 }
 
 // route function to nest endpoints in router
-pub fn routes() -> Router {
-    let user_set = Mutex::new(HashSet::new());
-    let (tx, _rx) = broadcast::channel(100);
-    let app_state = Arc::new(AppState{user_set, tx});
+pub fn routes() -> Router<AppState> {
     // create routes
     Router::new()
         .route("/", get(ws_handler))
-        .with_state(app_state)
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    ws.on_upgrade(|socket| {handle_socket(socket, state)})
+async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+    // bigger messages fail the receive loop and close the connection
+    ws.max_message_size(state.config.chat.max_frame_bytes)
+        .max_frame_size(state.config.chat.max_frame_bytes)
+        .on_upgrade(|socket| {handle_socket(socket, state)})
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
-    let (mut sender, mut receiver) = socket.split();
-    let mut username = String::new();
-    while let Some(Ok(auth)) = receiver.next().await {
-        if let Message::Text(text) = auth {
-            if let Ok(claims) = AuthRequesterClaims::from_string(&text) {
-                username = get_db_user_by_uuid(claims.sub.clone()).await.unwrap().username;
-                break;
-            } else {
-                sender.close().await.unwrap();
-                return;
+// encode a server message as a JSON text frame
+fn frame(message: &ServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+// write with a deadline, a client that stops reading must not hold its connection open
+async fn write(sender: &mut SplitSink<WebSocket, Message>, message: Message, timeout: Duration) -> bool {
+    match tokio::time::timeout(timeout, sender.send(message)).await {
+        Ok(result) => result.is_ok(),
+        Err(_) => {
+            metrics::counter!(monitoring::WEBSOCKET_SLOW_CONSUMERS_TOTAL).increment(1);
+            tracing::info!("Dropped chat connection that stopped reading");
+            false
+        }
+    }
+}
+
+// report a failed handshake to the client before closing the socket
+async fn reject(sender: &mut SplitSink<WebSocket, Message>, error: ChatErrorType) {
+    tracing::debug!(?error, "Rejected websocket handshake");
+    let _ = sender.send(frame(&ServerMessage::error(error))).await;
+    let _ = sender.close().await;
+}
+
+// load the user of a requester token with its expiry, disabled and deleted users have no session
+async fn session_user(app_state: &AppState, token: &str) -> Option<(User, u64)> {
+    let claims = AuthRequesterClaims::from_string(app_state, token).ok()?;
+    let user = app_state.users.find_by_uuid(&claims.sub).await.ok()?;
+    (!user.is_disabled).then_some((user, claims.exp))
+}
+
+// authenticated user of a new connection
+struct Handshake {
+    author: User,
+    expires_at: u64,
+    // rooms of the previous connection of a reconnecting client
+    resume: Vec<RoomResume>
+}
+
+// wait for the auth frame and load its user, None if the socket was rejected or closed
+async fn authenticate(
+    app_state: &AppState,
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut SplitStream<WebSocket>
+) -> Option<Handshake> {
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => return None,
+            _ => continue
+        };
+        let (version, token, resume) = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Auth { version, token, resume }) => (version, token, resume),
+            _ => {
+                reject(sender, ChatErrorType::InvalidFrame).await;
+                return None;
+            }
+        };
+        if version != CHAT_PROTOCOL_VERSION {
+            reject(sender, ChatErrorType::UnsupportedVersion).await;
+            return None;
+        }
+        // reject if token is invalid or user no longer exists
+        match session_user(app_state, &token).await {
+            Some((author, expires_at)) => return Some(Handshake { author, expires_at, resume }),
+            None => {
+                reject(sender, ChatErrorType::InvalidToken).await;
+                return None;
+            }
+        }
+    }
+    None
+}
+
+// typing state of a connection in one room
+struct Typing {
+    accepted_at: Instant,
+    // identifies the expiry task allowed to end this typing state
+    generation: u64
+}
+
+// authenticated socket, shared by its receive loop and the cleanup after it closes
+struct Connection {
+    app_state: AppState,
+    author: User,
+    tx: mpsc::Sender<Outbound>,
+    // every joined room by name with the task forwarding its broadcasts
+    rooms: Mutex<HashMap<String, (Room, JoinHandle<()>)>>,
+    // rooms the user is typing in, shared with the tasks expiring them
+    typing: Arc<Mutex<HashMap<String, Typing>>>,
+    next_typing_generation: AtomicU64,
+    // expiry of the newest token as unix timestamp, the socket is closed once it passes
+    expires_at: AtomicU64
+}
+
+impl Connection {
+    fn new(app_state: AppState, author: User, expires_at: u64, tx: mpsc::Sender<Outbound>) -> Self {
+        Self {
+            app_state,
+            author,
+            tx,
+            rooms: Mutex::new(HashMap::new()),
+            typing: Arc::new(Mutex::new(HashMap::new())),
+            next_typing_generation: AtomicU64::new(0),
+            expires_at: AtomicU64::new(expires_at)
+        }
+    }
+    async fn send(&self, message: ServerMessage) -> bool {
+        self.tx.send(Outbound::Frame(message)).await.is_ok()
+    }
+    // replay the room history, then forward its broadcasts and announce the join,
+    // a resumed room only replays the messages after the given id
+    async fn join(&self, name: &str, after: Option<i64>) -> Result<(), ChatErrorType> {
+        let name = normalize_room_name(name).ok_or(ChatErrorType::RoomNotFound)?;
+        if self.rooms.lock().unwrap().contains_key(&name) {
+            return Ok(());
+        }
+        let room = match rooms::find_accessible(self.app_state.rooms.as_ref(), &name, &self.author.uuid).await {
+            Ok(room) => room,
+            Err(DbError::NotFound) => return Err(ChatErrorType::RoomNotFound),
+            Err(error) => {
+                tracing::error!(%error, room = %name, "Could not load chat room");
+                return Err(ChatErrorType::Unavailable);
+            }
+        };
+        match self.app_state.rooms.is_banned(room.id, &self.author.uuid).await {
+            Ok(false) => {},
+            Ok(true) => return Err(ChatErrorType::Banned),
+            Err(error) => {
+                tracing::error!(%error, room = %room.name, "Could not load room ban");
+                return Err(ChatErrorType::Unavailable);
+            }
+        }
+        // subscribe before replaying so nothing sent in between is missed
+        let mut rx = self.app_state.chat.subscribe(&room.name);
+        self.replay(&room, after).await;
+        match self.app_state.rooms.read_markers(room.id).await {
+            Ok(markers) => {
+                for marker in markers {
+                    self.send(ServerMessage::Read { marker }).await;
+                }
+            },
+            Err(error) => tracing::error!(%error, room = %room.name, "Could not load read markers")
+        }
+        let tx = self.tx.clone();
+        let name = room.name.clone();
+        let forward = tokio::spawn(async move {
+            loop {
+                let message = match rx.recv().await {
+                    Ok(message) => message,
+                    // the full outbound queue held this task back, the client reloads the room instead
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        metrics::counter!(monitoring::CHAT_BROADCAST_LAGGED_TOTAL).increment(skipped);
+                        tracing::debug!(room = %name, skipped, "Chat connection lagged behind room");
+                        ServerMessage::Resync { room: Some(name.clone()), skipped }
+                    },
+                    Err(broadcast::error::RecvError::Closed) => break
+                };
+                if tx.send(Outbound::Frame(message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        self.rooms.lock().unwrap().insert(room.name.clone(), (room.clone(), forward));
+        // other tabs of the user already brought them into the room
+        if !self.app_state.chat.enter_room(&self.author.uuid, &room.name) {
+            return Ok(());
+        }
+        tracing::info!(username = %self.author.username, room = %room.name, "User joined chat room");
+        self.app_state.chat.send_to_room(&room.name, ServerMessage::Join { room: room.name.clone(), username: self.author.username.clone() });
+        Ok(())
+    }
+    // the room still works without history, so failures are only logged
+    async fn replay(&self, room: &Room, after: Option<i64>) {
+        let limit = self.app_state.config.chat.history_limit;
+        let Some(after) = after else {
+            match self.app_state.messages.history(room.id, None, limit).await {
+                Ok(messages) => {
+                    for message in messages {
+                        self.send(ServerMessage::Chat { message }).await;
+                    }
+                },
+                Err(error) => tracing::error!(%error, room = %room.name, "Could not load chat history")
+            }
+            return;
+        };
+        // one more than the limit tells whether the client missed too much to catch up
+        match self.app_state.messages.after(room.id, after, limit + 1).await {
+            Ok(messages) if messages.len() > limit as usize => {
+                self.send(ServerMessage::Resync { room: Some(room.name.clone()), skipped: messages.len() as u64 }).await;
+            },
+            Ok(messages) => {
+                for message in messages {
+                    self.send(ServerMessage::Chat { message }).await;
+                }
+            },
+            Err(error) => tracing::error!(%error, room = %room.name, "Could not load missed chat messages")
+        }
+    }
+    fn leave(&self, name: &str) -> Result<(), ChatErrorType> {
+        let name = normalize_room_name(name).ok_or(ChatErrorType::NotInRoom)?;
+        let (_, forward) = self.rooms.lock().unwrap().remove(&name).ok_or(ChatErrorType::NotInRoom)?;
+        forward.abort();
+        self.stop_typing(&name);
+        tracing::info!(username = %self.author.username, room = %name, "User left chat room");
+        // other tabs of the user keep them in the room
+        if self.app_state.chat.exit_room(&self.author.uuid, &name) {
+            self.app_state.chat.send_to_room(&name, ServerMessage::Leave { room: name.clone(), username: self.author.username.clone() });
+        }
+        Ok(())
+    }
+    fn leave_all(&self) {
+        let names: Vec<String> = self.rooms.lock().unwrap().keys().cloned().collect();
+        for name in names {
+            let _ = self.leave(&name);
+        }
+    }
+    fn joined_room(&self, name: &str) -> Result<Room, ChatErrorType> {
+        normalize_room_name(name)
+            .and_then(|name| self.rooms.lock().unwrap().get(&name).map(|(room, _)| room.clone()))
+            .ok_or(ChatErrorType::NotInRoom)
+    }
+    // announce typing when it starts and restart its expiry on every accepted frame
+    fn typing(&self, name: &str) -> Result<(), ChatErrorType> {
+        let room = self.joined_room(name)?;
+        let now = Instant::now();
+        let generation = self.next_typing_generation.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut typing = self.typing.lock().unwrap();
+            let previous = typing.get(&room.name).map(|state| state.accepted_at);
+            if previous.is_some_and(|accepted_at| now.duration_since(accepted_at) < Duration::from_secs(TYPING_THROTTLE_SECS)) {
+                return Ok(());
+            }
+            typing.insert(room.name.clone(), Typing { accepted_at: now, generation });
+            if previous.is_none() {
+                self.app_state.chat.send_to_room(&room.name, self.typing_frame(&room.name, true));
+            }
+        }
+        let typing = self.typing.clone();
+        let chat = self.app_state.chat.clone();
+        let stopped = self.typing_frame(&room.name, false);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(TYPING_TIMEOUT_SECS)).await;
+            let mut typing = typing.lock().unwrap();
+            // a newer frame or a sent message took over the typing state
+            if typing.get(&room.name).is_some_and(|state| state.generation == generation) {
+                typing.remove(&room.name);
+                chat.send_to_room(&room.name, stopped);
+            }
+        });
+        Ok(())
+    }
+    fn stop_typing(&self, room: &str) {
+        if self.typing.lock().unwrap().remove(room).is_some() {
+            self.app_state.chat.send_to_room(room, self.typing_frame(room, false));
+        }
+    }
+    fn typing_frame(&self, room: &str, typing: bool) -> ServerMessage {
+        ServerMessage::Typing { room: room.to_string(), username: self.author.username.clone(), typing }
+    }
+    // store the read marker and send a receipt to the room when it moved forward
+    async fn read(&self, name: &str, message_id: i64) -> Result<(), ChatErrorType> {
+        let room = self.joined_room(name)?;
+        match self.app_state.rooms.mark_read(room.id, &self.author.uuid, message_id).await {
+            Ok(true) => {
+                let marker = ReadMarker { room: room.name.clone(), username: self.author.username.clone(), message_id };
+                self.app_state.chat.send_to_room(&room.name, ServerMessage::Read { marker });
+                Ok(())
+            },
+            Ok(false) => Ok(()),
+            Err(error) => {
+                tracing::error!(%error, room = %room.name, "Could not store read marker");
+                Err(ChatErrorType::Unavailable)
+            }
+        }
+    }
+    async fn check_muted(&self) -> Result<(), ChatErrorType> {
+        match self.app_state.mutes.muted_until(&self.author.uuid, unix_now()).await {
+            Ok(None) => Ok(()),
+            Ok(Some(_)) => Err(ChatErrorType::Muted),
+            Err(error) => {
+                tracing::error!(%error, "Could not load chat mute");
+                Err(ChatErrorType::Unavailable)
+            }
+        }
+    }
+    // admins are never slowed down
+    async fn check_slow_mode(&self, room: &Room) -> Result<(), ChatErrorType> {
+        match self.app_state.rooms.slow_mode_wait(room.id, &self.author.uuid, unix_now()).await {
+            Ok(0) => Ok(()),
+            Ok(_) if self.is_admin().await? => Ok(()),
+            Ok(_) => Err(ChatErrorType::SlowMode),
+            Err(error) => {
+                tracing::error!(%error, room = %room.name, "Could not load slow mode");
+                Err(ChatErrorType::Unavailable)
+            }
+        }
+    }
+    // the role is loaded again on every use, the user may have been promoted or demoted since the handshake
+    async fn is_admin(&self) -> Result<bool, ChatErrorType> {
+        match self.app_state.users.find_by_uuid(&self.author.uuid).await {
+            Ok(user) => Ok(user.is_admin && !user.is_disabled),
+            Err(UserRepositoryError::NotFound) => Ok(false),
+            Err(error) => {
+                tracing::error!(%error, "Could not load chat user role");
+                Err(ChatErrorType::Unavailable)
             }
         }
     }
+    async fn chat(&self, id: u64, room: &str, body: &str) -> Result<ServerMessage, ChatErrorType> {
+        let room = self.joined_room(room)?;
+        self.check_muted().await?;
+        self.check_slow_mode(&room).await?;
+        let message = self.store(MessageTarget::Room(&room), body).await?;
+        let message_id = message.id;
+        self.stop_typing(&room.name);
+        self.app_state.chat.send_to_room(&room.name, ServerMessage::Chat { message });
+        Ok(ServerMessage::Ack { id: Some(id), message_id: Some(message_id) })
+    }
+    async fn direct(&self, id: u64, to: &str, body: &str) -> Result<ServerMessage, ChatErrorType> {
+        let recipient = match self.app_state.users.find_by_uuid(to).await {
+            Ok(recipient) => recipient,
+            Err(UserRepositoryError::NotFound) => return Err(ChatErrorType::UserNotFound),
+            Err(_) => return Err(ChatErrorType::Unavailable)
+        };
+        self.check_muted().await?;
+        let message = self.store(MessageTarget::Direct(&recipient.uuid), body).await?;
+        let message_id = message.id;
+        let message = ServerMessage::Chat { message };
+        self.app_state.chat.send_to_user(&recipient.uuid, &message);
+        if recipient.uuid != self.author.uuid {
+            self.app_state.chat.send_to_user(&self.author.uuid, &message);
+        }
+        Ok(ServerMessage::Ack { id: Some(id), message_id: Some(message_id) })
+    }
+    async fn moderate(&self, id: u64, action: &ModerationAction) -> Result<ServerMessage, ChatErrorType> {
+        if !self.is_admin().await? {
+            return Err(ChatErrorType::Forbidden);
+        }
+        moderation::apply(&self.app_state, &self.author.uuid, action).await?;
+        Ok(ServerMessage::Ack { id: Some(id), message_id: None })
+    }
+    // accept a newer token of the same user and move the session expiry to it
+    async fn refresh(&self, token: &str) -> Result<ServerMessage, ChatErrorType> {
+        let (user, expires_at) = session_user(&self.app_state, token).await.ok_or(ChatErrorType::InvalidToken)?;
+        if user.uuid != self.author.uuid {
+            return Err(ChatErrorType::InvalidToken);
+        }
+        let expires_at = self.expires_at.fetch_max(expires_at, Ordering::Relaxed).max(expires_at);
+        tracing::debug!(username = %self.author.username, expires_at, "Refreshed chat session");
+        Ok(ServerMessage::Session { expires_at })
+    }
+    fn session_left(&self) -> Duration {
+        let now = u64::try_from(unix_now()).unwrap_or_default();
+        Duration::from_secs(self.expires_at.load(Ordering::Relaxed).saturating_sub(now))
+    }
+    // returns the reason when the connection has to close
+    fn control(&self, control: Control) -> Option<ChatErrorType> {
+        match control {
+            Control::Leave(room) => {
+                let _ = self.leave(&room);
+                None
+            },
+            Control::Disconnect(reason) => Some(reason)
+        }
+    }
+    // queued behind pending frames, the send task closes the socket after the error
+    async fn close(&self, reason: ChatErrorType) {
+        let _ = self.tx.send(Outbound::Close(reason)).await;
+    }
+    // messages carry their stored id, so nothing is relayed when storing fails
+    async fn store(&self, target: MessageTarget<'_>, body: &str) -> Result<ChatMessage, ChatErrorType> {
+        if body.trim().is_empty() {
+            return Err(ChatErrorType::EmptyMessage);
+        }
+        self.app_state.messages.insert(&self.author, target, body).await.map_err(|error| {
+            tracing::error!(%error, "Could not store chat message");
+            ChatErrorType::Unavailable
+        })
+    }
+    // answer one client frame
+    async fn handle(&self, text: &str) -> Option<ServerMessage> {
+        let result = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(ClientMessage::Join { room }) => self.join(&room, None).await.map(|_| None),
+            Ok(ClientMessage::Leave { room }) => self.leave(&room).map(|_| None),
+            Ok(ClientMessage::Chat { id, room, body }) => self.chat(id, &room, &body).await.map(Some),
+            Ok(ClientMessage::Direct { id, to, body }) => self.direct(id, &to, &body).await.map(Some),
+            Ok(ClientMessage::Typing { room }) => self.typing(&room).map(|_| None),
+            Ok(ClientMessage::Read { room, message_id }) => self.read(&room, message_id).await.map(|_| None),
+            Ok(ClientMessage::Moderate { id, action }) => self.moderate(id, &action).await.map(Some),
+            Ok(ClientMessage::Refresh { token }) => self.refresh(&token).await.map(Some),
+            Ok(ClientMessage::Ping) => Ok(Some(ServerMessage::Pong)),
+            // a second auth frame is as invalid as unparseable JSON
+            Ok(ClientMessage::Auth { .. }) | Err(_) => Err(ChatErrorType::InvalidFrame)
+        };
+        result.unwrap_or_else(|error| Some(ServerMessage::error(error)))
+    }
+}
 
-    let mut rx = state.tx.subscribe();
+async fn handle_socket(socket: WebSocket, app_state: AppState) {
+    let (mut sender, mut receiver) = socket.split();
+    // a client that never authenticates must not hold its connection open
+    let handshake_timeout = Duration::from_secs(app_state.config.chat.handshake_timeout);
+    let handshake = match tokio::time::timeout(handshake_timeout, authenticate(&app_state, &mut sender, &mut receiver)).await {
+        Ok(handshake) => handshake,
+        Err(_) => {
+            reject(&mut sender, ChatErrorType::HandshakeTimeout).await;
+            None
+        }
+    };
+    let Some(Handshake { author, expires_at, resume }) = handshake else {
+        return;
+    };
+    if sender.send(frame(&ServerMessage::Ack { id: None, message_id: None })).await.is_err() {
+        return;
+    }
 
-    let msg = format!("{username} joined.");
-    let _ = state.tx.send(msg);
+    let chat_config = app_state.config.chat.clone();
+    let (tx, mut rx) = mpsc::channel::<Outbound>(chat_config.outbound_queue);
+    // queued first, so the client learns the expiry before any other frame
+    let _ = tx.send(Outbound::Frame(ServerMessage::Session { expires_at })).await;
+    let (control, mut control_rx) = mpsc::unbounded_channel::<Control>();
+    let chat = app_state.chat.clone();
+    let missed = Arc::new(AtomicU64::new(0));
+    let connection_id = chat.register(&author, ConnectionHandle { tx: tx.clone(), control, missed: missed.clone() });
+    let connection = Arc::new(Connection::new(app_state, author, expires_at, tx));
+    metrics::gauge!(monitoring::WEBSOCKET_CONNECTIONS_ACTIVE).increment(1.0);
 
+    let ping_interval = Duration::from_secs(chat_config.ping_interval);
+    let write_timeout = Duration::from_secs(chat_config.pong_timeout);
+    // any frame of the client proves it is alive, pongs included
+    let silence_limit = ping_interval + write_timeout;
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if msg == String::new() {
+        let mut ping = tokio::time::interval(ping_interval);
+        ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // the first tick completes at once
+        ping.tick().await;
+        loop {
+            let sent = tokio::select! {
+                outbound = rx.recv() => match outbound {
+                    Some(Outbound::Frame(message)) => write(&mut sender, frame(&message), write_timeout).await,
+                    Some(Outbound::Close(reason)) => {
+                        let _ = write(&mut sender, frame(&ServerMessage::error(reason)), write_timeout).await;
+                        let _ = sender.close().await;
+                        break;
+                    },
+                    None => break
+                },
+                _ = ping.tick() => write(&mut sender, Message::Ping(Vec::new()), write_timeout).await
+            };
+            if !sent {
                 break;
             }
-            if sender.send(Message::Text(msg)).await.is_err() {
+            // told once the queue has room again, at the latest with the next ping
+            let skipped = missed.swap(0, Ordering::Relaxed);
+            if skipped > 0 && !write(&mut sender, frame(&ServerMessage::Resync { room: None, skipped }), write_timeout).await {
                 break;
             }
         }
     });
 
-    let tx = state.tx.clone();
-    let name = username.clone();
-
+    let receiving = connection.clone();
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(text))) = receiver.next().await {
-            if text == String::new() {
-                break;
+        if resume.is_empty() {
+            if let Err(error) = receiving.join(DEFAULT_ROOM, None).await {
+                tracing::error!(?error, "Could not join default chat room");
+            }
+        }
+        // rooms the user was banned from or lost access to in between are reported
+        for room in resume {
+            if let Err(error) = receiving.join(&room.room, room.after).await {
+                receiving.send(ServerMessage::error(error)).await;
             }
-            let _ = tx.send(format!("{name}: {text}"));
+        }
+        let expiry = tokio::time::sleep(receiving.session_left());
+        tokio::pin!(expiry);
+        let heartbeat = tokio::time::sleep(silence_limit);
+        tokio::pin!(heartbeat);
+        // set once the close is queued, later frames of the client are ignored
+        let mut closing = false;
+        loop {
+            let message = tokio::select! {
+                message = receiver.next() => message,
+                Some(control) = control_rx.recv() => {
+                    if let Some(reason) = receiving.control(control) {
+                        if !closing {
+                            receiving.close(reason).await;
+                            closing = true;
+                        }
+                    }
+                    continue;
+                },
+                _ = &mut expiry, if !closing => {
+                    tracing::info!(username = %receiving.author.username, "Chat session expired");
+                    receiving.close(ChatErrorType::TokenExpired).await;
+                    closing = true;
+                    continue;
+                },
+                _ = &mut heartbeat => {
+                    metrics::counter!(monitoring::WEBSOCKET_HEARTBEAT_TIMEOUTS_TOTAL).increment(1);
+                    tracing::info!(username = %receiving.author.username, "Chat connection missed its heartbeat");
+                    break;
+                }
+            };
+            heartbeat.as_mut().reset(tokio::time::Instant::now() + silence_limit);
+            let text = match message {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue
+            };
+            if closing {
+                continue;
+            }
+            if let Some(reply) = receiving.handle(&text).await {
+                if !receiving.send(reply).await {
+                    break;
+                }
+            }
+            // a refresh frame may have moved the expiry
+            expiry.as_mut().reset(tokio::time::Instant::now() + receiving.session_left());
         }
     });
 
@@ -81,8 +931,7 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
         _ = (&mut recv_task) => send_task.abort(),
     };
 
-    let msg = format!("{username} left.");
-    let _ = state.tx.send(msg);
-
-    state.user_set.lock().unwrap().remove(&username);
+    metrics::gauge!(monitoring::WEBSOCKET_CONNECTIONS_ACTIVE).decrement(1.0);
+    connection.leave_all();
+    chat.unregister(&connection.author, connection_id);
 }
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use sqlx::{error::DatabaseError, postgres::PgDatabaseError};
+use types::auth::AuthErrorType;
+
+// Postgres SQLSTATE codes
+const PG_UNIQUE_VIOLATION: &str = "23505";
+const PG_FOREIGN_KEY_VIOLATION: &str = "23503";
+const PG_CONNECTION_EXCEPTION_CLASS: &str = "08";
+
+// SQLite extended result codes
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+const SQLITE_CONSTRAINT_PRIMARYKEY: &str = "1555";
+const SQLITE_CONSTRAINT_FOREIGNKEY: &str = "787";
+const SQLITE_IOERR: i32 = 10;
+const SQLITE_CANTOPEN: i32 = 14;
+
+// Database errors classified independently of the SQL backend
+#[derive(Debug)]
+pub enum DbError {
+    // unique constraint failed, holds the offending column when it can be determined
+    UniqueViolation(Option<String>),
+    ForeignKeyViolation,
+    NotFound,
+    ConnectionLost,
+    Other(sqlx::Error)
+}
+
+impl DbError {
+    // map classified error to the auth error type returned to clients
+    pub fn auth_error_type(&self) -> AuthErrorType {
+        match self {
+            DbError::UniqueViolation(column) => match column.as_deref() {
+                Some("username") | Some("username_key") => AuthErrorType::UsernameTaken,
+                Some("email") => AuthErrorType::EmailTaken,
+                _ => AuthErrorType::UserAlreadyExists
+            },
+            DbError::ForeignKeyViolation => AuthErrorType::BadRequest,
+            DbError::NotFound => AuthErrorType::UserDoesNotExist,
+            DbError::ConnectionLost => AuthErrorType::ServiceUnavailable,
+            DbError::Other(_) => AuthErrorType::ServerError
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::UniqueViolation(Some(column)) => write!(f, "Unique constraint violated on column {}", column),
+            DbError::UniqueViolation(None) => write!(f, "Unique constraint violated"),
+            DbError::ForeignKeyViolation => write!(f, "Foreign key constraint violated"),
+            DbError::NotFound => write!(f, "Row not found"),
+            DbError::ConnectionLost => write!(f, "Database connection lost"),
+            DbError::Other(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => DbError::ConnectionLost,
+            sqlx::Error::Database(database_error) => classify_database_error(database_error),
+            error => DbError::Other(error)
+        }
+    }
+}
+
+// classify driver specific error codes for Postgres and SQLite
+fn classify_database_error(error: Box<dyn DatabaseError>) -> DbError {
+    let code = error.code().map(|code| code.into_owned()).unwrap_or_default();
+    match code.as_str() {
+        PG_UNIQUE_VIOLATION => DbError::UniqueViolation(postgres_unique_column(error.as_ref())),
+        SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY => DbError::UniqueViolation(sqlite_unique_column(error.message())),
+        PG_FOREIGN_KEY_VIOLATION | SQLITE_CONSTRAINT_FOREIGNKEY => DbError::ForeignKeyViolation,
+        code if code.starts_with(PG_CONNECTION_EXCEPTION_CLASS) && code.len() == 5 => DbError::ConnectionLost,
+        code => match code.parse::<i32>() {
+            // SQLite primary result code is the low byte of the extended code
+            Ok(sqlite_code) if matches!(sqlite_code & 0xff, SQLITE_IOERR | SQLITE_CANTOPEN) => DbError::ConnectionLost,
+            _ => DbError::Other(sqlx::Error::Database(error))
+        }
+    }
+}
+
+// parse column from detail "Key (column)=(value) already exists." or fall back to constraint name
+fn postgres_unique_column(error: &dyn DatabaseError) -> Option<String> {
+    if let Some(detail) = error.try_downcast_ref::<PgDatabaseError>().and_then(|error| error.detail()) {
+        if let Some(column) = detail.strip_prefix("Key (").and_then(|rest| rest.split(')').next()) {
+            return Some(column.to_string());
+        }
+    }
+    // default Postgres constraint names are "{table}_{column}_key"
+    error.constraint().map(|constraint| {
+        constraint.strip_prefix("users_").unwrap_or(constraint)
+            .trim_end_matches("_idx")
+            .trim_end_matches("_key")
+            .to_string()
+    })
+}
+
+// parse column from message "UNIQUE constraint failed: table.column"
+fn sqlite_unique_column(message: &str) -> Option<String> {
+    message.split(": ").nth(1)
+        .and_then(|columns| columns.split(", ").next())
+        .and_then(|column| column.split('.').nth(1))
+        .map(|column| column.to_string())
+}
@@ -0,0 +1,28 @@
+use std::{sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use tokio::task::JoinHandle;
+
+use crate::{config::AccountsConfig, strategies::users::{UserRepository, UserRepositoryError}};
+
+// anonymize users whose deletion grace period has ended, returns number of erased users
+pub async fn erase_expired(users: &dyn UserRepository, config: &AccountsConfig) -> Result<u64, UserRepositoryError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let deleted_before = now.saturating_sub(config.deletion_grace_period);
+    users.erase_deleted(i64::try_from(deleted_before).unwrap_or(i64::MAX)).await
+}
+
+// run erase_expired every erasure_interval seconds until the runtime shuts down
+pub fn spawn(users: Arc<dyn UserRepository>, config: AccountsConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.erasure_interval));
+        loop {
+            interval.tick().await;
+            match erase_expired(users.as_ref(), &config).await {
+                Ok(0) => {},
+                Ok(erased) => tracing::info!(erased, "Erased deleted users"),
+                // failures are retried on the next tick
+                Err(error) => tracing::error!(%error, "Could not erase deleted users")
+            }
+        }
+    })
+}
@@ -0,0 +1,196 @@
+use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::async_trait;
+use types::{export::UserExport, user::{User, UserInfo}};
+
+use crate::{app_error::AppError, state::AppState};
+
+// Source of one section of a personal data export, every table holding user data registers one
+#[async_trait]
+pub trait Exporter: Send + Sync {
+    // key of the section in the export
+    fn section(&self) -> &'static str;
+    // tables whose rows of the user end up in the section
+    fn tables(&self) -> &'static [&'static str];
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError>;
+}
+
+// Exporters run in registration order to build a user's data export
+#[derive(Clone, Default)]
+pub struct ExporterRegistry {
+    exporters: Vec<Arc<dyn Exporter>>
+}
+
+impl ExporterRegistry {
+    // registry with an exporter for every table holding user data
+    pub fn new() -> Self {
+        let mut registry = Self::default();
+        registry.register(AccountExporter);
+        registry.register(MessagesExporter);
+        registry.register(DirectMessagesExporter);
+        registry.register(ReadMarkersExporter);
+        registry.register(MembershipsExporter);
+        registry.register(OwnedRoomsExporter);
+        registry.register(MuteExporter);
+        registry.register(BansExporter);
+        registry
+    }
+    pub fn register<E: Exporter + 'static>(&mut self, exporter: E) {
+        self.exporters.push(Arc::new(exporter));
+    }
+    // every table covered by the registered exporters
+    pub fn tables(&self) -> Vec<&'static str> {
+        let mut tables: Vec<_> = self.exporters.iter().flat_map(|exporter| exporter.tables().iter().copied()).collect();
+        tables.sort_unstable();
+        tables.dedup();
+        tables
+    }
+    pub async fn export(&self, state: &AppState, user: &User) -> Result<UserExport, AppError> {
+        let mut export = UserExport {
+            exported_at: SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs() as i64)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        for exporter in &self.exporters {
+            let section = exporter.export(state, user).await?;
+            export.sections.insert(exporter.section().to_string(), section);
+        }
+        Ok(export)
+    }
+}
+
+// account row of the user, the password hash is never exported
+pub struct AccountExporter;
+
+#[async_trait]
+impl Exporter for AccountExporter {
+    fn section(&self) -> &'static str {
+        "account"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["users"]
+    }
+    async fn export(&self, _state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        serde_json::to_value(UserInfo::from_user(user.clone())).map_err(AppError::internal)
+    }
+}
+
+// chat lines written by the user
+pub struct MessagesExporter;
+
+#[async_trait]
+impl Exporter for MessagesExporter {
+    fn section(&self) -> &'static str {
+        "messages"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["messages"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let messages = state.messages.by_author(&user.uuid).await?;
+        serde_json::to_value(messages).map_err(AppError::internal)
+    }
+}
+
+// direct messages other users sent to the user
+pub struct DirectMessagesExporter;
+
+#[async_trait]
+impl Exporter for DirectMessagesExporter {
+    fn section(&self) -> &'static str {
+        "received_messages"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["messages"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let messages = state.messages.received_by(&user.uuid).await?;
+        serde_json::to_value(messages).map_err(AppError::internal)
+    }
+}
+
+// how far the user has read in each room
+pub struct ReadMarkersExporter;
+
+#[async_trait]
+impl Exporter for ReadMarkersExporter {
+    fn section(&self) -> &'static str {
+        "read_markers"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["read_markers"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let markers = state.rooms.read_markers_of(&user.uuid).await?;
+        serde_json::to_value(markers).map_err(AppError::internal)
+    }
+}
+
+// rooms the user is a member of
+pub struct MembershipsExporter;
+
+#[async_trait]
+impl Exporter for MembershipsExporter {
+    fn section(&self) -> &'static str {
+        "memberships"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["room_members"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let rooms = state.rooms.member_of(&user.uuid).await?;
+        serde_json::to_value(rooms).map_err(AppError::internal)
+    }
+}
+
+// rooms the user created
+pub struct OwnedRoomsExporter;
+
+#[async_trait]
+impl Exporter for OwnedRoomsExporter {
+    fn section(&self) -> &'static str {
+        "owned_rooms"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["rooms"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let rooms = state.rooms.created_by(&user.uuid).await?;
+        serde_json::to_value(rooms).map_err(AppError::internal)
+    }
+}
+
+// chat mute of the user, null when they were never muted
+pub struct MuteExporter;
+
+#[async_trait]
+impl Exporter for MuteExporter {
+    fn section(&self) -> &'static str {
+        "mute"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["chat_mutes"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let mute = state.mutes.mute_of(&user.uuid).await?;
+        serde_json::to_value(mute).map_err(AppError::internal)
+    }
+}
+
+// rooms the user is banned from
+pub struct BansExporter;
+
+#[async_trait]
+impl Exporter for BansExporter {
+    fn section(&self) -> &'static str {
+        "bans"
+    }
+    fn tables(&self) -> &'static [&'static str] {
+        &["room_bans"]
+    }
+    async fn export(&self, state: &AppState, user: &User) -> Result<serde_json::Value, AppError> {
+        let bans = state.rooms.bans_of(&user.uuid).await?;
+        serde_json::to_value(bans).map_err(AppError::internal)
+    }
+}
@@ -0,0 +1,23 @@
+pub mod config;
+pub mod cli;
+pub mod state;
+pub mod mail;
+pub mod app;
+pub mod pool;
+pub mod migrations;
+pub mod manage;
+pub mod erasure;
+pub mod export;
+pub mod moderation;
+pub mod pubsub;
+pub mod db_error;
+pub mod app_error;
+pub mod strategies;
+pub mod controllers;
+pub mod middleware;
+pub mod telemetry;
+pub mod monitoring;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use app::build_app;
@@ -0,0 +1,89 @@
+use std::{fmt, sync::Arc};
+
+use lettre::{message::{header::ContentType, Mailbox}, transport::smtp::{authentication::Credentials, client::Tls}, Message, SmtpTransport, Transport};
+
+use crate::config::{CompanyConfig, Config, SmtpConfig};
+
+// Email to send, the sender address is chosen by the mailer
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html: String
+}
+
+#[derive(Debug)]
+pub struct MailError(String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+// trait for sending emails, replaceable so tests can capture outgoing mail
+pub trait Mailer: Send + Sync {
+    // false if emails cannot be delivered
+    fn is_configured(&self) -> bool {
+        true
+    }
+    // send email, blocks until delivered
+    fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+// build the mailer for a config, disabled if smtp is not configured
+pub fn from_config(config: &Config) -> Result<Arc<dyn Mailer>, MailError> {
+    match &config.smtp {
+        Some(smtp) => Ok(Arc::new(SmtpMailer::new(smtp, &config.company)?)),
+        None => Ok(Arc::new(DisabledMailer))
+    }
+}
+
+// Mailer delivering through an smtp relay
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox
+}
+
+impl SmtpMailer {
+    pub fn new(smtp: &SmtpConfig, company: &CompanyConfig) -> Result<Self, MailError> {
+        let creds = Credentials::new(smtp.username.to_owned(), smtp.password.to_owned());
+        let transport = SmtpTransport::relay(&smtp.host)
+            .map_err(|error| MailError(format!("Invalid SMTP host {}: {}", smtp.host, error)))?
+            .tls(Tls::None)
+            .credentials(creds)
+            .build();
+        let from = format!("{} <noreply@{}>", company.name, company.domain).parse()
+            .map_err(|error| MailError(format!("Invalid sender address: {}", error)))?;
+        Ok(Self { transport, from })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: Email) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.to_owned())
+            .to(email.to.parse().map_err(|error| MailError(format!("Invalid recipient {}: {}", email.to, error)))?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_HTML)
+            .body(email.html)
+            .map_err(|error| MailError(format!("Could not build email: {}", error)))?;
+        self.transport.send(&message)
+            .map(|_| ())
+            .map_err(|error| MailError(format!("Failed to send email to {}: {}", email.to, error)))
+    }
+}
+
+// Mailer used when smtp is not configured
+pub struct DisabledMailer;
+
+impl Mailer for DisabledMailer {
+    fn is_configured(&self) -> bool {
+        false
+    }
+    fn send(&self, _email: Email) -> Result<(), MailError> {
+        Err(MailError(String::from("SMTP is not configured")))
+    }
+}
@@ -1,50 +1,195 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{env, io::{self, BufRead, IsTerminal}, process};
 
-use axum::Router;
-use http::header::{AUTHORIZATION, CONTENT_TYPE};
-use tower::ServiceBuilder;
-use tower_http::cors::{Any, CorsLayer};
-
-mod pool;
-mod strategies;
-mod controllers;
-mod middleware;
+use server::{build_app, cli::{Command, MigrateCommand, UserCommand}, config::Config, erasure, mail, manage::{self, ManageError}, migrations, monitoring, pool::{self, DbPool}, state::AppState, strategies::users, telemetry};
+use sqlx::migrate::Migrate;
+use types::user::RegisterUser;
 
 #[tokio::main]
 async fn main() {
 
     // load environment variables with dotenv if debug
-    if cfg!(debug_assertions) {
-        match dotenv::dotenv() {
-            Ok(path) => path,
-            Err(error) => {
-                println!("Cannot access .env file: {}", error);
-                PathBuf::from("")
+    let dotenv_result = if cfg!(debug_assertions) {
+        Some(dotenv::dotenv())
+    } else {
+        None
+    };
+
+    // pick command before loading config so typos are reported without touching the database
+    let command = match Command::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+    };
+
+    // load and validate configuration before anything else starts
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Invalid configuration: {}", error);
+            process::exit(1);
+        }
+    };
+
+    // initialize tracing after config is loaded so log format and filter can be set there
+    telemetry::init_tracing(&config.log);
+    if let Some(Err(error)) = dotenv_result {
+        tracing::warn!("Cannot access .env file: {}", error);
+    }
+    tracing::debug!(?config, "Loaded configuration");
+
+    //create pool
+    let pool = match pool::create_pool(&config.database).await {
+        Ok(pool) => pool,
+        Err(error) => {
+            tracing::error!(%error, "Could not create pool");
+            process::exit(1);
+        }
+    };
+
+    // apply embedded migrations before touching users, the migrate command manages them itself
+    if config.database.run_migrations && !matches!(command, Command::Migrate(_)) {
+        if let Err(error) = migrations::run(&pool).await {
+            tracing::error!(%error, "Could not run migrations");
+            process::exit(1);
+        }
+        tracing::info!("Database migrations are up to date");
+    }
+
+    match command {
+        Command::Serve => serve(config, pool).await,
+        Command::Migrate(command) => {
+            if let Err(error) = migrate(command, &pool).await {
+                tracing::error!(%error, "Migration failed");
+                process::exit(1);
             }
-        };
+        },
+        Command::User(command) => {
+            if let Err(error) = manage_user(command, &pool, &config).await {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
     }
+}
 
-    //create pg pool
-    pool::create_pool().await;
+async fn serve(config: Config, pool: DbPool) {
+    // build mailer once so smtp settings are checked at startup
+    let mailer = match mail::from_config(&config) {
+        Ok(mailer) => mailer,
+        Err(error) => {
+            tracing::error!(%error, "Could not create mailer");
+            process::exit(1);
+        }
+    };
 
-    let cors = CorsLayer::permissive()
-        .allow_origin(Any)
-        .allow_headers([AUTHORIZATION, CONTENT_TYPE])
-        .expose_headers(Any);
+    let state = AppState::new(pool, config, mailer);
 
-    let app = Router::new()
-        .nest("/ws", controllers::ws_controller::routes())
-        .nest("/auth", controllers::auth_controller::routes())
-        .nest("/user", controllers::users_controller::routes())
-        .layer(
-            ServiceBuilder::new()
-            .layer(cors));
+    // erase soft deleted users once their grace period has ended
+    erasure::spawn(state.users.clone(), state.config.accounts.clone());
+
+    // serve metrics without auth on their own address if configured
+    if let Some(metrics_addr) = state.config.server.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(metrics_addr).await
+            .unwrap_or_else(|error| panic!("Could not bind to {}: {}", metrics_addr, error));
+        tracing::info!("Metrics listening on http://{}/metrics", metrics_addr);
+        let metrics_router = monitoring::standalone_router(state.clone());
+        tokio::spawn(async move {
+            if let Err(error) = axum::serve(listener, metrics_router).await {
+                tracing::error!(%error, "Metrics server stopped");
+            }
+        });
+    }
+
+    let addr = state.config.server.addr;
+    let app = build_app(state);
+    tracing::info!("Server listening on http://{}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .unwrap_or_else(|error| panic!("Could not bind to {}: {}", addr, error));
+    if let Err(error) = axum::serve(listener, app).await {
+        panic!("Server error on {}: {}", addr, error);
+    }
+}
+
+// run a migrate subcommand, results are printed for the operator
+async fn migrate(command: MigrateCommand, pool: &DbPool) -> Result<(), sqlx::migrate::MigrateError> {
+    let kind = pool.any_kind();
+    match command {
+        MigrateCommand::Up => {
+            migrations::run(pool).await?;
+            println!("Database migrations are up to date");
+        },
+        MigrateCommand::Down => match migrations::revert_last(pool).await? {
+            Some(version) => println!("Reverted {} {}", version, migrations::description(kind, version).unwrap_or_default()),
+            None => println!("No migrations are applied")
+        },
+        MigrateCommand::Status => {
+            let mut connection = pool.acquire().await?;
+            connection.ensure_migrations_table().await?;
+            let status = migrations::status(&mut connection).await?;
+            for version in &status.applied {
+                println!("applied  {} {}", version, migrations::description(kind, *version).unwrap_or_default());
+            }
+            for version in &status.pending {
+                println!("pending  {} {}", version, migrations::description(kind, *version).unwrap_or_default());
+            }
+            if let Some(version) = status.dirty {
+                println!("dirty    {}", version);
+            }
+        }
+    }
+    Ok(())
+}
+
+// run a user subcommand, results are printed for the operator
+async fn manage_user(command: UserCommand, pool: &DbPool, config: &Config) -> Result<(), ManageError> {
+    let repository = users::repository(pool.clone());
+    let users = repository.as_ref();
+    let pass = if command.needs_password() {
+        read_password().map_err(|_| ManageError::EmptyPassword)?
+    } else {
+        String::new()
+    };
+    match command {
+        UserCommand::Create { username, email, is_admin } => {
+            let user = manage::create_user(users, config, RegisterUser { username, pass, email }, is_admin).await?;
+            println!("Created {}", user.username);
+            println!("{}", user);
+        },
+        UserCommand::SetPassword { user } => {
+            let user = manage::set_password(users, config, &user, pass).await?;
+            println!("Password updated for {}", user.username);
+        },
+        UserCommand::Promote { user } => {
+            let user = manage::promote(users, &user).await?;
+            println!("{} is now an admin", user.username);
+        },
+        UserCommand::List => {
+            for user in manage::list_users(users).await? {
+                let role = if user.is_admin { "admin" } else { "user" };
+                println!("{}  {:<24}  {:<5}  {}", user.uuid, user.username, role, user.email);
+            }
+        },
+        UserCommand::Delete { user } => {
+            let user = manage::delete_user(users, &user).await?;
+            println!("Deleted {}, restore it with: server user restore {}", user.username, user.uuid);
+        },
+        UserCommand::Restore { uuid } => {
+            manage::restore_user(users, &uuid).await?;
+            println!("Restored {}", uuid);
+        }
+    }
+    Ok(())
+}
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3001));
-    println!("Server listening on http://{}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    match axum::serve(listener, app).await {
-        Ok(_) => {},
-        Err(error) => panic!("Could not bind to {}: {}", addr,error)
+// read one line from stdin, a person typing it is prompted and the input is not echoed
+fn read_password() -> io::Result<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return rpassword::prompt_password("Password: ");
     }
+    let mut line = String::new();
+    stdin.lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
 }
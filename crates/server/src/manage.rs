@@ -0,0 +1,102 @@
+use std::fmt;
+
+use email_address::EmailAddress;
+use types::{user::{RegisterUser, User, UserInfo}, username::{validate_username, UsernameError}};
+
+use crate::{config::Config, strategies::users::{UserRepository, UserRepositoryError}};
+
+// Error returned by user management commands
+#[derive(Debug)]
+pub enum ManageError {
+    InvalidUsername(UsernameError),
+    InvalidEmail(String),
+    EmptyPassword,
+    UserNotFound(String),
+    Repository(UserRepositoryError)
+}
+
+impl fmt::Display for ManageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManageError::InvalidUsername(error) => write!(f, "{}", error),
+            ManageError::InvalidEmail(email) => write!(f, "{} is not a valid email address", email),
+            ManageError::EmptyPassword => write!(f, "Password cannot be empty"),
+            ManageError::UserNotFound(user) => write!(f, "No user matching {}", user),
+            ManageError::Repository(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for ManageError {}
+
+impl From<UserRepositoryError> for ManageError {
+    fn from(error: UserRepositoryError) -> Self {
+        ManageError::Repository(error)
+    }
+}
+
+// create a user, reserved usernames are allowed since the operator is choosing them
+pub async fn create_user(users: &dyn UserRepository, config: &Config, register_user: RegisterUser, is_admin: bool) -> Result<UserInfo, ManageError> {
+    let username = validate_username::<&str>(&register_user.username, &[])
+        .map_err(ManageError::InvalidUsername)?;
+    if !EmailAddress::is_valid(&register_user.email) {
+        return Err(ManageError::InvalidEmail(register_user.email));
+    }
+    if register_user.pass.is_empty() {
+        return Err(ManageError::EmptyPassword);
+    }
+    let user = users.insert(RegisterUser { username, ..register_user }, config.auth.password_salt).await?;
+    let mut user_info = UserInfo::from_user(user);
+    if is_admin {
+        users.set_admin(&user_info.uuid, true).await?;
+        user_info.is_admin = true;
+    }
+    Ok(user_info)
+}
+
+// replace the password of a user found by username or email
+pub async fn set_password(users: &dyn UserRepository, config: &Config, username_or_email: &str, pass: String) -> Result<UserInfo, ManageError> {
+    if pass.is_empty() {
+        return Err(ManageError::EmptyPassword);
+    }
+    let mut user = find_user(users, username_or_email).await?;
+    user.pass = pass;
+    let user_info = UserInfo::from_user(user.clone());
+    users.update(user, config.auth.password_salt).await?;
+    Ok(user_info)
+}
+
+// grant admin access to a user found by username or email
+pub async fn promote(users: &dyn UserRepository, username_or_email: &str) -> Result<UserInfo, ManageError> {
+    let user = find_user(users, username_or_email).await?;
+    users.set_admin(&user.uuid, true).await?;
+    Ok(UserInfo { is_admin: true, ..UserInfo::from_user(user) })
+}
+
+pub async fn list_users(users: &dyn UserRepository) -> Result<Vec<UserInfo>, ManageError> {
+    Ok(users.list().await?)
+}
+
+// soft delete a user found by username or email
+pub async fn delete_user(users: &dyn UserRepository, username_or_email: &str) -> Result<UserInfo, ManageError> {
+    let user = find_user(users, username_or_email).await?;
+    users.delete(&user.uuid).await?;
+    Ok(UserInfo::from_user(user))
+}
+
+// restore a soft deleted user that was not erased yet
+pub async fn restore_user(users: &dyn UserRepository, uuid: &str) -> Result<(), ManageError> {
+    match users.restore(uuid).await? {
+        0 => Err(ManageError::UserNotFound(uuid.to_string())),
+        _ => Ok(())
+    }
+}
+
+// look up user, a missing row is reported with the name that was searched for
+async fn find_user(users: &dyn UserRepository, username_or_email: &str) -> Result<User, ManageError> {
+    match users.find_by_username_or_email(username_or_email).await {
+        Ok(user) => Ok(user),
+        Err(UserRepositoryError::NotFound) => Err(ManageError::UserNotFound(username_or_email.to_string())),
+        Err(error) => Err(error.into())
+    }
+}
@@ -1 +1,2 @@
-pub mod token_authentication;
\ No newline at end of file
+pub mod token_authentication;
+pub mod request_id;
\ No newline at end of file
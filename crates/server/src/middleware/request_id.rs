@@ -0,0 +1,41 @@
+use axum::{
+    response::Response,
+    middleware::Next,
+    extract::Request
+};
+use http::HeaderValue;
+use types::problem::REQUEST_ID_HEADER;
+use uuid::Uuid;
+
+tokio::task_local! {
+    // request ID of the request currently being handled
+    static REQUEST_ID: String;
+}
+
+// get request ID of the current request if called while handling one
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.to_owned()).ok()
+}
+
+// middleware function for tagging every request and response with a request ID
+pub async fn assign_request_id(
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // reuse caller supplied ID from proxies, otherwise generate one
+    let request_id = request.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 64)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&request_id).ok();
+    if let Some(value) = &header_value {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value.to_owned());
+    }
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    if let Some(value) = header_value {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
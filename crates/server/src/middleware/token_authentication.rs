@@ -9,7 +9,7 @@ use http::HeaderValue;
 use serde::Serialize;
 use serde_json::json;
 use base64::prelude::*;
-use crate::strategies::authentication::Claims;
+use crate::{strategies::authentication::Claims, telemetry};
 
 // middleware function for authenticating token
 pub async fn authenticate_token<T>(
@@ -18,6 +18,8 @@ pub async fn authenticate_token<T>(
     next: Next,
 ) -> Response
 where T: Claims,T: Serialize, T: Debug {
+    // tag request span with the authenticated user
+    telemetry::record_user(claims.subject());
     let header_map = request.headers_mut();
     while header_map.contains_key("X-Claims") {
         header_map.remove("X-Claims");
@@ -0,0 +1,110 @@
+use sqlx::{any::{AnyConnection, AnyKind}, migrate::{Migrate, MigrateError, Migrator}, Row};
+use types::username::username_key;
+
+use crate::pool::DbPool;
+
+// migrations embedded at compile time for each supported database
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("../../migrations/postgres");
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("../../migrations/sqlite");
+
+// applied and pending migration versions of a database
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub applied: Vec<i64>,
+    pub pending: Vec<i64>,
+    pub dirty: Option<i64>
+}
+
+impl MigrationStatus {
+    // true if every known migration is applied and none failed part way
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty() && self.dirty.is_none()
+    }
+}
+
+// get embedded migrator matching the database kind of a connection
+pub fn migrator(kind: AnyKind) -> &'static Migrator {
+    match kind {
+        AnyKind::Postgres => &POSTGRES_MIGRATOR,
+        AnyKind::Sqlite => &SQLITE_MIGRATOR
+    }
+}
+
+// description of an embedded migration, used when reporting status
+pub fn description(kind: AnyKind, version: i64) -> Option<&'static str> {
+    migrator(kind).iter()
+        .find(|migration| migration.version == version && !migration.migration_type.is_down_migration())
+        .map(|migration| migration.description.as_ref())
+}
+
+// apply all pending migrations for the database kind of the pool
+pub async fn run(pool: &DbPool) -> Result<(), MigrateError> {
+    migrator(pool.any_kind()).run(pool).await?;
+    if pool.any_kind() == AnyKind::Sqlite {
+        backfill_username_keys(pool).await?;
+    }
+    Ok(())
+}
+
+// sqlite has no NFKC and only folds ASCII case, so keys of names with other characters are computed here
+async fn backfill_username_keys(pool: &DbPool) -> Result<(), MigrateError> {
+    let rows = sqlx::query("SELECT id, username, username_key FROM users WHERE username GLOB '*[^ -~]*';")
+        .fetch_all(pool).await.map_err(MigrateError::Execute)?;
+    let mut transaction = pool.begin().await.map_err(MigrateError::Execute)?;
+    for row in rows {
+        let id: i32 = row.try_get("id").map_err(MigrateError::Execute)?;
+        let username: String = row.try_get("username").map_err(MigrateError::Execute)?;
+        let current: Option<String> = row.try_get("username_key").map_err(MigrateError::Execute)?;
+        let key = username_key(&username);
+        if current.as_deref() != Some(key.as_str()) {
+            sqlx::query("UPDATE users SET username_key = $1 WHERE id = $2;")
+                .bind(key)
+                .bind(id)
+                .execute(&mut transaction).await.map_err(MigrateError::Execute)?;
+        }
+    }
+    transaction.commit().await.map_err(MigrateError::Execute)?;
+    Ok(())
+}
+
+// revert the most recently applied migration, returns its version or None if nothing was applied
+pub async fn revert_last(pool: &DbPool) -> Result<Option<i64>, MigrateError> {
+    let migrator = migrator(pool.any_kind());
+    let mut applied: Vec<i64> = {
+        let mut connection = pool.acquire().await?;
+        connection.ensure_migrations_table().await?;
+        connection.list_applied_migrations().await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect()
+    };
+    applied.sort_unstable();
+    let latest = match applied.pop() {
+        Some(version) => version,
+        None => return Ok(None)
+    };
+    // undo silently skips migrations without a down script, report it instead
+    let reversible = migrator.iter()
+        .any(|migration| migration.version == latest && migration.migration_type.is_down_migration());
+    if !reversible {
+        return Err(MigrateError::Source(format!("migration {} has no down migration", latest).into()));
+    }
+    migrator.undo(pool, applied.last().copied().unwrap_or(0)).await?;
+    Ok(Some(latest))
+}
+
+// compare applied migrations against the embedded migrations, does not modify the database
+pub async fn status(connection: &mut AnyConnection) -> Result<MigrationStatus, MigrateError> {
+    let migrator = migrator(connection.kind());
+    let dirty = connection.dirty_version().await?;
+    let applied: Vec<i64> = connection.list_applied_migrations().await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+    let pending = migrator.iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect();
+    Ok(MigrationStatus { applied, pending, dirty })
+}
@@ -0,0 +1,87 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use types::chat::{normalize_room_name, ChatErrorType, ModerationAction, Room, ServerMessage};
+
+use crate::{db_error::DbError, state::AppState, strategies::users::UserRepositoryError};
+
+// log a storage failure, the moderator only learns the action did not happen
+fn unavailable<E: std::fmt::Display>(error: E) -> ChatErrorType {
+    tracing::error!(%error, "Could not apply moderation action");
+    ChatErrorType::Unavailable
+}
+
+async fn find_room(state: &AppState, name: &str) -> Result<Room, ChatErrorType> {
+    let name = normalize_room_name(name).ok_or(ChatErrorType::RoomNotFound)?;
+    match state.rooms.find_by_name(&name).await {
+        Ok(room) => Ok(room),
+        Err(DbError::NotFound) => Err(ChatErrorType::RoomNotFound),
+        Err(error) => Err(unavailable(error))
+    }
+}
+
+// moderated users must exist, the uuid of the stored user is returned
+async fn find_user(state: &AppState, uuid: &str) -> Result<String, ChatErrorType> {
+    match state.users.find_by_uuid(uuid).await {
+        Ok(user) => Ok(user.uuid),
+        Err(UserRepositoryError::NotFound) => Err(ChatErrorType::UserNotFound),
+        Err(error) => Err(unavailable(error))
+    }
+}
+
+// carry out a moderation action of an admin and tell the affected connections,
+// callers check that the moderator is an admin
+pub async fn apply(state: &AppState, moderator_uuid: &str, action: &ModerationAction) -> Result<(), ChatErrorType> {
+    tracing::info!(moderator = %moderator_uuid, ?action, "Applying chat moderation action");
+    match action {
+        ModerationAction::DeleteMessage { message_id } => {
+            let message = match state.messages.find(*message_id).await {
+                Ok(message) => message,
+                Err(DbError::NotFound) => return Err(ChatErrorType::MessageNotFound),
+                Err(error) => return Err(unavailable(error))
+            };
+            state.messages.delete(message.id).await.map_err(unavailable)?;
+            let deleted = ServerMessage::Deleted { message_id: message.id };
+            match (&message.room, &message.recipient_uuid) {
+                (Some(room), _) => state.chat.send_to_room(room, deleted),
+                (None, Some(recipient_uuid)) => {
+                    state.chat.send_to_user(recipient_uuid, &deleted);
+                    state.chat.send_to_user(&message.author_uuid, &deleted);
+                },
+                (None, None) => {}
+            }
+        },
+        ModerationAction::Mute { user, seconds } => {
+            let user_uuid = find_user(state, user).await?;
+            let until = if *seconds == 0 {
+                state.mutes.unmute(&user_uuid).await.map_err(unavailable)?;
+                None
+            } else {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default();
+                let until = i64::try_from(now.saturating_add(*seconds)).unwrap_or(i64::MAX);
+                state.mutes.mute(&user_uuid, until, moderator_uuid).await.map_err(unavailable)?;
+                Some(until)
+            };
+            state.chat.send_to_user(&user_uuid, &ServerMessage::Muted { until });
+        },
+        ModerationAction::Ban { room, user } => {
+            let room = find_room(state, room).await?;
+            let user_uuid = find_user(state, user).await?;
+            state.rooms.ban(room.id, &user_uuid, moderator_uuid).await.map_err(unavailable)?;
+            state.chat.send_to_user(&user_uuid, &ServerMessage::Banned { room: room.name.clone() });
+            state.chat.remove_from_room(&user_uuid, &room.name);
+        },
+        ModerationAction::Unban { room, user } => {
+            let room = find_room(state, room).await?;
+            let user_uuid = find_user(state, user).await?;
+            state.rooms.unban(room.id, &user_uuid).await.map_err(unavailable)?;
+        },
+        ModerationAction::SlowMode { room, seconds } => {
+            let room = find_room(state, room).await?;
+            state.rooms.set_slow_mode(room.id, *seconds).await.map_err(unavailable)?;
+            state.chat.send_to_room(&room.name, ServerMessage::SlowMode { room: room.name.clone(), seconds: *seconds });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::time::Instant;
+
+use axum::{extract::{MatchedPath, Request, State}, middleware::{self, Next}, response::Response, routing::get, Router};
+use http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::OnceCell;
+
+use crate::{app_error::AppError, middleware::token_authentication, state::AppState, strategies::authentication::{AuthClaims, Claims}};
+
+// metric names exposed on /metrics
+pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+pub const LOGIN_ATTEMPTS_TOTAL: &str = "login_attempts_total";
+pub const TOKENS_ISSUED_TOTAL: &str = "tokens_issued_total";
+pub const WEBSOCKET_CONNECTIONS_ACTIVE: &str = "websocket_connections_active";
+pub const CHAT_USERS_ONLINE: &str = "chat_users_online";
+pub const CHAT_BROADCAST_LAGGED_TOTAL: &str = "chat_broadcast_lagged_messages_total";
+pub const WEBSOCKET_HEARTBEAT_TIMEOUTS_TOTAL: &str = "websocket_heartbeat_timeouts_total";
+pub const WEBSOCKET_SLOW_CONSUMERS_TOTAL: &str = "websocket_slow_consumers_total";
+pub const CHAT_PUBSUB_ERRORS_TOTAL: &str = "chat_pubsub_errors_total";
+pub const CHAT_PUBSUB_LAGGED_TOTAL: &str = "chat_pubsub_lagged_events_total";
+pub const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+pub const DB_POOL_IDLE_CONNECTIONS: &str = "db_pool_idle_connections";
+
+// request latency buckets in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// global handle to the installed prometheus recorder
+static PROMETHEUS_HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
+
+// install the global prometheus recorder, safe to call more than once
+pub fn install_recorder() -> PrometheusHandle {
+    PROMETHEUS_HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(HTTP_REQUEST_DURATION_SECONDS.to_string()), LATENCY_BUCKETS)
+            .expect("latency buckets must not be empty")
+            .install_recorder()
+            .expect("could not install prometheus recorder")
+    }).to_owned()
+}
+
+// route function for metrics served on the public listener, requires admin AuthClaims
+pub fn routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(admin_metrics))
+        .layer(middleware::from_fn_with_state(state, token_authentication::authenticate_token::<AuthClaims>))
+}
+
+// router for metrics served on a separate bind address, no authentication
+pub fn standalone_router(state: AppState) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(state)
+}
+
+async fn admin_metrics(state: State<AppState>, request: Request) -> Result<(StatusCode, [(http::HeaderName, HeaderValue); 1], String), AppError> {
+    let claims = AuthClaims::from_header(request.headers())?;
+    if !claims.acc {
+        return Err(AppError::from_error_type(types::auth::AuthErrorType::AccessDenied));
+    }
+    Ok(render_metrics(state).await)
+}
+
+async fn render_metrics(State(state): State<AppState>) -> (StatusCode, [(http::HeaderName, HeaderValue); 1], String) {
+    // sample pool stats at scrape time
+    metrics::gauge!(DB_POOL_CONNECTIONS).set(state.pool.size() as f64);
+    metrics::gauge!(DB_POOL_IDLE_CONNECTIONS).set(state.pool.num_idle() as f64);
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4"))],
+        install_recorder().render()
+    )
+}
+
+// middleware function recording request counts and latency by route and status
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    // use matched route template to keep label cardinality bounded
+    let route = request.extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| String::from("<unmatched>"));
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+    let labels = [("method", method), ("route", route), ("status", status)];
+    metrics::counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    metrics::histogram!(HTTP_REQUEST_DURATION_SECONDS, &labels).record(start.elapsed().as_secs_f64());
+    response
+}
+
+// record outcome of a login attempt
+pub fn record_login(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    metrics::counter!(LOGIN_ATTEMPTS_TOTAL, "result" => result).increment(1);
+}
+
+// record token issued for a claim type
+pub fn record_token_issued(claim_type: &'static str) {
+    metrics::counter!(TOKENS_ISSUED_TOTAL, "claim_type" => claim_type).increment(1);
+}
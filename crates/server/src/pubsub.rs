@@ -0,0 +1,257 @@
+use std::{collections::HashMap, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use axum::async_trait;
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+use tracing::instrument;
+use types::chat::{ChatContact, ChatErrorType, ServerMessage};
+use uuid::Uuid;
+
+use crate::{db_error::DbError, monitoring, pool::DbPool};
+
+// events a subscriber may fall behind before it lags
+const EVENT_CAPACITY: usize = 1024;
+// postgres channel carrying the chat events
+const NOTIFY_CHANNEL: &str = "chat_events";
+// NOTIFY payloads must stay below 8000 bytes, bigger events are split into parts
+const NOTIFY_PART_BYTES: usize = 6000;
+// events still missing parts, older ones are dropped beyond this
+const MAX_PENDING_EVENTS: usize = 256;
+
+// backend fanning chat events out to every server instance
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PubSubKind {
+    // single instance, events stay in the process
+    Memory,
+    // events travel through LISTEN/NOTIFY of the database
+    Postgres
+}
+
+impl FromStr for PubSubKind {
+    type Err = String;
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.to_lowercase().as_str() {
+            "memory" => Ok(PubSubKind::Memory),
+            "postgres" => Ok(PubSubKind::Postgres),
+            _ => Err(format!("unknown pubsub backend {}, expected memory or postgres", kind))
+        }
+    }
+}
+
+// chat event published by one instance and handled by all of them, the publisher included
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    // frame for everyone in the room
+    Room { room: String, message: ServerMessage },
+    // frame for every connection of the user
+    User { uuid: String, message: ServerMessage },
+    // connections of the user leave the room
+    Leave { uuid: String, room: String },
+    // connections of the user are closed
+    Disconnect { uuid: String, reason: ChatErrorType },
+    // user got their first or lost their last connection on the instance
+    Presence { instance: String, user: ChatContact, online: bool },
+    // every user connected to the instance, replaces what was known about it
+    Snapshot { instance: String, users: Vec<ChatContact> },
+    // a starting instance asks the others for their snapshots
+    SnapshotRequest { instance: String },
+    // the backend missed events of other instances, only raised locally and never published
+    Lost
+}
+
+#[async_trait]
+pub trait PubSub: Send + Sync {
+    async fn publish(&self, event: &ChatEvent) -> Result<(), DbError>;
+    // events published from now on, in the order they were published by each instance
+    fn subscribe(&self) -> broadcast::Receiver<ChatEvent>;
+}
+
+// pick the backend of the config, must be called within a tokio runtime
+pub fn backend(kind: PubSubKind, pool: DbPool, database_url: &str) -> Arc<dyn PubSub> {
+    match kind {
+        PubSubKind::Memory => Arc::new(MemoryPubSub::default()),
+        PubSubKind::Postgres => Arc::new(PostgresPubSub::new(pool, database_url))
+    }
+}
+
+// delivers events within the process
+pub struct MemoryPubSub {
+    tx: broadcast::Sender<ChatEvent>
+}
+
+impl Default for MemoryPubSub {
+    fn default() -> Self {
+        Self { tx: broadcast::channel(EVENT_CAPACITY).0 }
+    }
+}
+
+#[async_trait]
+impl PubSub for MemoryPubSub {
+    async fn publish(&self, event: &ChatEvent) -> Result<(), DbError> {
+        // no subscriber is no error
+        let _ = self.tx.send(event.clone());
+        Ok(())
+    }
+    fn subscribe(&self) -> broadcast::Receiver<ChatEvent> {
+        self.tx.subscribe()
+    }
+}
+
+// one NOTIFY payload, holding a base64 slice of the JSON encoded event
+#[derive(Serialize, Deserialize)]
+struct Part {
+    sender: String,
+    seq: u64,
+    part: usize,
+    parts: usize,
+    data: String
+}
+
+// delivers events to every instance listening on the same Postgres database
+pub struct PostgresPubSub {
+    pool: DbPool,
+    tx: broadcast::Sender<ChatEvent>,
+    // tells parts of this instance apart from those of others
+    sender: String,
+    next_seq: AtomicU64
+}
+
+impl PostgresPubSub {
+    // listens on its own connection, the pool only sends
+    pub fn new(pool: DbPool, database_url: &str) -> Self {
+        let tx = broadcast::channel(EVENT_CAPACITY).0;
+        tokio::spawn(listen(database_url.to_string(), tx.clone()));
+        Self { pool, tx, sender: Uuid::new_v4().to_string(), next_seq: AtomicU64::new(0) }
+    }
+}
+
+#[async_trait]
+impl PubSub for PostgresPubSub {
+    #[instrument(name = "sql.chat_notify", skip_all)]
+    async fn publish(&self, event: &ChatEvent) -> Result<(), DbError> {
+        let json = serde_json::to_vec(event).unwrap_or_default();
+        let data = BASE64_STANDARD.encode(json);
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let parts = data.len().div_ceil(NOTIFY_PART_BYTES).max(1);
+        // parts are sent one by one, so they are delivered in order
+        for part in 0..parts {
+            let start = part * NOTIFY_PART_BYTES;
+            let end = (start + NOTIFY_PART_BYTES).min(data.len());
+            let payload = Part { sender: self.sender.clone(), seq, part, parts, data: data[start..end].to_string() };
+            sqlx::query("SELECT pg_notify($1, $2);")
+                .bind(NOTIFY_CHANNEL)
+                .bind(serde_json::to_string(&payload).unwrap_or_default())
+                .execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+    fn subscribe(&self) -> broadcast::Receiver<ChatEvent> {
+        self.tx.subscribe()
+    }
+}
+
+// receive notifications for the lifetime of the server, events sent while the connection is down are lost
+async fn listen(database_url: String, tx: broadcast::Sender<ChatEvent>) {
+    let mut relay = NotificationRelay::new(tx);
+    let mut listened = false;
+    loop {
+        let mut listener = match PgListener::connect(&database_url).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                tracing::error!(%error, "Could not connect chat listener");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        if let Err(error) = listener.listen(NOTIFY_CHANNEL).await {
+            tracing::error!(%error, "Could not listen for chat events");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        tracing::info!("Listening for chat events");
+        // a new listener missed what was sent since the last one gave up
+        if listened {
+            relay.relay(Ok(None));
+        }
+        listened = true;
+        // try_recv reports a dropped connection and reconnects on the next call, recv would hide it
+        loop {
+            let received = listener.try_recv().await;
+            let received = received.as_ref().map(|notification| notification.as_ref().map(|notification| notification.payload()));
+            if !relay.relay(received) {
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+// turns what the Postgres listener receives into chat events, apart from the connection so it can be driven without one
+pub struct NotificationRelay {
+    tx: broadcast::Sender<ChatEvent>,
+    // parts received so far by sender and sequence number
+    pending: HashMap<(String, u64), Vec<String>>
+}
+
+impl NotificationRelay {
+    pub fn new(tx: broadcast::Sender<ChatEvent>) -> Self {
+        Self { tx, pending: HashMap::new() }
+    }
+    // handle one result of the listener, a payload or None for a lost connection, false once it gave up
+    pub fn relay(&mut self, received: Result<Option<&str>, &sqlx::Error>) -> bool {
+        match received {
+            Ok(Some(payload)) => {
+                let Ok(part) = serde_json::from_str::<Part>(payload) else {
+                    tracing::warn!("Ignored malformed chat notification");
+                    return true;
+                };
+                if let Some(event) = assemble(&mut self.pending, part) {
+                    let _ = self.tx.send(event);
+                }
+                true
+            },
+            // notifications sent while the connection was down are gone, so the chat has to resync
+            Ok(None) => {
+                metrics::counter!(monitoring::CHAT_PUBSUB_ERRORS_TOTAL).increment(1);
+                tracing::warn!("Lost chat listener connection");
+                self.pending.clear();
+                let _ = self.tx.send(ChatEvent::Lost);
+                true
+            },
+            Err(error) => {
+                metrics::counter!(monitoring::CHAT_PUBSUB_ERRORS_TOTAL).increment(1);
+                tracing::error!(%error, "Chat listener gave up");
+                false
+            }
+        }
+    }
+}
+
+// collect the parts of an event, returns it once the last one arrived
+fn assemble(pending: &mut HashMap<(String, u64), Vec<String>>, part: Part) -> Option<ChatEvent> {
+    let data = if part.parts <= 1 {
+        part.data
+    } else {
+        if pending.len() >= MAX_PENDING_EVENTS {
+            pending.clear();
+        }
+        let key = (part.sender, part.seq);
+        let parts = pending.entry(key.clone()).or_default();
+        // a gap means a part was lost, the event cannot be completed
+        if parts.len() != part.part {
+            pending.remove(&key);
+            return None;
+        }
+        parts.push(part.data);
+        if parts.len() < part.parts {
+            return None;
+        }
+        pending.remove(&key)?.concat()
+    };
+    let json = BASE64_STANDARD.decode(data).ok()?;
+    serde_json::from_slice(&json).ok()
+}
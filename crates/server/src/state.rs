@@ -0,0 +1,51 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{config::Config, controllers::{auth_controller::ResetKeysState, ws_controller::ChatState}, export::ExporterRegistry, mail::Mailer, pool::DbPool, pubsub::{self, PubSub}, strategies::{authentication::Keys, messages::{self, MessageRepository}, mutes::{self, MuteRepository}, rooms::{self, RoomRepository}, users::{self, UserRepository}}};
+
+// Application state passed to every handler through Router::with_state
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: DbPool,
+    // user storage matching the database kind of the pool
+    pub users: Arc<dyn UserRepository>,
+    // chat history
+    pub messages: Arc<dyn MessageRepository>,
+    // chat rooms and their members
+    pub rooms: Arc<dyn RoomRepository>,
+    // users who cannot chat for a while
+    pub mutes: Arc<dyn MuteRepository>,
+    pub config: Arc<Config>,
+    pub keys: Arc<Keys>,
+    pub mailer: Arc<dyn Mailer>,
+    // pending password reset keys
+    pub reset_keys: Arc<ResetKeysState>,
+    // chat room channels and connected users, shared with other instances through pub/sub
+    pub chat: Arc<ChatState>,
+    // sections of the personal data export
+    pub exporters: Arc<ExporterRegistry>
+}
+
+impl AppState {
+    pub fn new(pool: DbPool, config: Config, mailer: Arc<dyn Mailer>) -> Self {
+        let pubsub = pubsub::backend(config.chat.pubsub, pool.clone(), &config.database.url);
+        Self::with_pubsub(pool, config, mailer, pubsub)
+    }
+    // state sending chat events through the given backend instead of the configured one
+    pub fn with_pubsub(pool: DbPool, config: Config, mailer: Arc<dyn Mailer>, pubsub: Arc<dyn PubSub>) -> Self {
+        let keys = Keys::new(config.auth.token_secret.as_bytes());
+        let chat = ChatState::new(pubsub, Duration::from_secs(config.chat.presence_interval));
+        Self {
+            users: users::repository(pool.clone()),
+            messages: messages::repository(pool.clone()),
+            rooms: rooms::repository(pool.clone()),
+            mutes: mutes::repository(pool.clone()),
+            pool,
+            config: Arc::new(config),
+            keys: Arc::new(keys),
+            mailer,
+            reset_keys: Arc::new(ResetKeysState::default()),
+            chat,
+            exporters: Arc::new(ExporterRegistry::new())
+        }
+    }
+}
@@ -1,44 +1,22 @@
-use std::env;
-use axum::{async_trait, body::Body, extract::FromRequestParts, http::request::Parts, response::{IntoResponse, Response}, Json, RequestPartsExt};
+use axum::{async_trait, body::Body, extract::{FromRef, FromRequestParts}, http::request::Parts, response::{IntoResponse, Response}, RequestPartsExt};
 use axum_extra::{headers::{Authorization, authorization::Bearer}, TypedHeader};
 use http::{HeaderMap, StatusCode};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use types::auth::{AuthErrorBody, AuthErrorType, AuthToken};
 use struct_iterable::Iterable;
 use base64::prelude::*;
 
-use super::users::get_db_user_by_uuid;
+use crate::{app_error::AppError, config::Config, monitoring, state::AppState, strategies::users::UserRepositoryError};
 
-// Keys for encoding/decoding authorization tokens with JWT_SECRET
-static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = std::env::var("AUTH_TOKEN_SECRET").expect("AUTH_TOKEN_SECRET must be configured.");
-    Keys::new(secret.as_bytes())
-});
-
-// Auth token lifetime
-static TOKEN_LIFETIME: Lazy<u64> = Lazy::new(|| {
-    u64::from_str_radix(env::var("AUTH_TOKEN_EXPIRE")
-        .expect("AUTH_TOKEN_EXPIRE must be configured").as_str(), 10)
-        .expect("Cannot parse AUTH_TOKEN_EXPIRE as u64")
-});
-
-// Auth request token lifetime
-static TOKEN_REQUESTER_LIFETIME: Lazy<u64> = Lazy::new(|| {
-    u64::from_str_radix(env::var("AUTH_REQUEST_TOKEN_EXPIRE")
-        .expect("AUTH_REQUEST_TOKEN_EXPIRE must be configured").as_str(), 10)
-        .expect("Cannot parse AUTH_REQUEST_TOKEN_EXPIRE as u64")
-});
-
-struct Keys {
+// Keys for encoding/decoding authorization tokens with the configured token secret
+pub struct Keys {
     encoding: EncodingKey,
     decoding: DecodingKey
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    pub fn new(secret: &[u8]) -> Self {
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret)
@@ -46,38 +24,52 @@ impl Keys {
     }
 }
 
+// build validation strategy for tokens issued with the configured company
+fn validation(config: &Config) -> Validation {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 5;
+    validation.set_audience(&[&config.company.domain]);
+    validation.set_issuer(&[&config.company.name]);
+    validation
+}
+
 // trait for JWT claims
+#[allow(async_fn_in_trait)]
 pub trait Claims {
+    // claim type label used in token issuance metrics
+    const CLAIM_TYPE: &'static str;
     // create empty claim
-    fn default() -> Self;
+    fn default(state: &AppState) -> Self;
     // create claim from UUID
-    async fn new(uuid: String) -> Result<Self, AuthError> where Self: Sized;
+    async fn new(state: &AppState, uuid: String) -> Result<Self, AuthError> where Self: Sized;
+    // get user UUID the claims were issued for
+    fn subject(&self) -> &str;
     // generate AuthToken from Claims
-    fn generate_token(&self) -> Result<AuthToken, AuthError>
+    fn generate_token(&self, state: &AppState) -> Result<AuthToken, AuthError>
     where Self: Serialize {
-        match encode(&Header::default(), &self, &KEYS.encoding) {
+        match encode(&Header::default(), &self, &state.keys.encoding) {
             Ok(encoded_string) => {
+                monitoring::record_token_issued(Self::CLAIM_TYPE);
                 Ok(AuthToken::new(encoded_string))
             },
             Err(error) => {
-                println!("Error creating token: {}", error);
+                tracing::error!(%error, "Error creating token");
                 Err(AuthError::from_error_type(AuthErrorType::TokenCreation))
             }
         }
     }
     // generate claims from X-Claims header
-    fn from_header(headers: &HeaderMap) -> Self
+    fn from_header(headers: &HeaderMap) -> Result<Self, AuthError>
     where Self: for<'de> Deserialize<'de> {
-        let value = headers.get("X-Claims").unwrap();
-        return serde_json::from_str(&String::from_utf8(BASE64_STANDARD.decode(value).unwrap()).unwrap()).unwrap();
+        headers.get("X-Claims")
+            .and_then(|value| BASE64_STANDARD.decode(value).ok())
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .ok_or(AuthError::from_error_type(AuthErrorType::InvalidToken))
     }
-    fn from_string(encoded_str: &str) -> Result<Self, AuthError>
+    fn from_string(state: &AppState, encoded_str: &str) -> Result<Self, AuthError>
     where Self: Sized,Self: for<'de> Deserialize<'de> {
-        let mut validation = Validation::new(Algorithm::HS256);
-        validation.leeway = 5;
-        validation.set_audience(&[env::var("COMPANY_DOMAIN").unwrap()]);
-        validation.set_issuer(&[env::var("COMPANY_NAME").unwrap()]);
-        match decode::<Self>(encoded_str, &KEYS.decoding, &validation) {
+        match decode::<Self>(encoded_str, &state.keys.decoding, &validation(&state.config)) {
             Ok(claims) => {
                 Ok(claims.claims)
             },
@@ -89,20 +81,15 @@ pub trait Claims {
 }
 
 // build claims from request Authorization header
-async fn claims_from_request<T>(parts: &mut Parts) -> Result<T, AuthError>
+async fn claims_from_request<T>(parts: &mut Parts, state: &AppState) -> Result<T, AuthError>
 where T: for<'de> Deserialize<'de> {
     // Extract the token from the authorization header
     let TypedHeader(Authorization(bearer)) = parts
         .extract::<TypedHeader<Authorization<Bearer>>>()
         .await
         .map_err(|_| AuthError::from_error_type(AuthErrorType::InvalidToken))?;
-    // Build validation strategy
-    let mut validation = Validation::new(Algorithm::HS256);
-    validation.leeway = 5;
-    validation.set_audience(&[env::var("COMPANY_DOMAIN").unwrap()]);
-    validation.set_issuer(&[env::var("COMPANY_NAME").unwrap()]);
     // Decode the user data
-    let token_data = decode::<T>(bearer.token(), &KEYS.decoding, &validation)
+    let token_data = decode::<T>(bearer.token(), &state.keys.decoding, &validation(&state.config))
     .map_err(|_| AuthError::from_error_type(AuthErrorType::InvalidToken))?;
     Ok(token_data.claims)
 }
@@ -118,39 +105,46 @@ pub struct AuthClaims {
 }
 
 impl Claims for AuthClaims {
-    fn default() -> AuthClaims {
+    const CLAIM_TYPE: &'static str = "auth";
+    fn default(state: &AppState) -> AuthClaims {
         Self {
             // user uuid
             sub: String::new(),
             // issuer domain
-            aud: env::var("COMPANY_DOMAIN").unwrap(),
+            aud: state.config.company.domain.to_owned(),
             // issuer company
-            com: env::var("COMPANY_NAME").unwrap(),
+            com: state.config.company.name.to_owned(),
             // expiration timestamp from unix epoch
-                exp: jsonwebtoken::get_current_timestamp() + *TOKEN_LIFETIME,
+            exp: jsonwebtoken::get_current_timestamp() + state.config.auth.token_lifetime,
             // access level
             acc: false
         }
     }
-    async fn new(uuid: String) -> Result<AuthClaims, AuthError> {
-        match get_db_user_by_uuid(uuid).await {
+    async fn new(state: &AppState, uuid: String) -> Result<AuthClaims, AuthError> {
+        match state.users.find_by_uuid(&uuid).await {
             Ok(user) => Ok(Self {
                 // user uuid
                 sub: user.uuid,
                 // issuer domain
-                aud: env::var("COMPANY_DOMAIN").unwrap(),
+                aud: state.config.company.domain.to_owned(),
                 // issuer company
-                com: env::var("COMPANY_NAME").unwrap(),
+                com: state.config.company.name.to_owned(),
                 // expiration timestamp from unix epoch
-                exp: jsonwebtoken::get_current_timestamp() + *TOKEN_LIFETIME,
+                exp: jsonwebtoken::get_current_timestamp() + state.config.auth.token_lifetime,
                 // access level
                 acc: user.is_admin
             }),
-            Err(_) => {
-                Err(AuthError::from_error_type(AuthErrorType::TokenCreation))
+            // deleted and erased users are not found either
+            Err(UserRepositoryError::NotFound) => Err(AuthError::from_error_type(AuthErrorType::UserDoesNotExist)),
+            Err(error) => {
+                tracing::error!(%error, "Could not load token user");
+                Err(AuthError::from_error_type(AuthErrorType::ServiceUnavailable))
             }
         }
     }
+    fn subject(&self) -> &str {
+        &self.sub
+    }
 }
 
 /**
@@ -159,11 +153,13 @@ impl Claims for AuthClaims {
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthClaims
 where
-    S: Sync,
+    AppState: FromRef<S>,
+    S: Send + Sync,
 {
     type Rejection = AuthError;
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        claims_from_request::<AuthClaims>(parts).await
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        claims_from_request::<AuthClaims>(parts, &state).await
     }
 }
 
@@ -177,31 +173,35 @@ pub struct AuthRequesterClaims {
 }
 
 impl Claims for AuthRequesterClaims {
-    fn default() -> AuthRequesterClaims {
+    const CLAIM_TYPE: &'static str = "requester";
+    fn default(state: &AppState) -> AuthRequesterClaims {
         Self {
             // user uuid
             sub: String::new(),
             // issuer domain
-            aud: env::var("COMPANY_DOMAIN").unwrap(),
+            aud: state.config.company.domain.to_owned(),
             // issuer company
-            com: env::var("COMPANY_NAME").unwrap(),
+            com: state.config.company.name.to_owned(),
             // expiration timestamp from unix epoch
-            exp: jsonwebtoken::get_current_timestamp() + *TOKEN_REQUESTER_LIFETIME,
+            exp: jsonwebtoken::get_current_timestamp() + state.config.auth.requester_token_lifetime,
             // access level
         }
     }
-    async fn new(uuid: String) -> Result<AuthRequesterClaims, AuthError> {
+    async fn new(state: &AppState, uuid: String) -> Result<AuthRequesterClaims, AuthError> {
         Ok(Self {
             // user uuid
             sub: uuid,
             // issuer domain
-            aud: env::var("COMPANY_DOMAIN").unwrap(),
+            aud: state.config.company.domain.to_owned(),
             // issuer company
-            com: env::var("COMPANY_NAME").unwrap(),
+            com: state.config.company.name.to_owned(),
             // expiration timestamp from unix epoch
-            exp: jsonwebtoken::get_current_timestamp() + *TOKEN_REQUESTER_LIFETIME,
+            exp: jsonwebtoken::get_current_timestamp() + state.config.auth.requester_token_lifetime,
         })
     }
+    fn subject(&self) -> &str {
+        &self.sub
+    }
 }
 
 /**
@@ -210,11 +210,13 @@ impl Claims for AuthRequesterClaims {
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthRequesterClaims
 where
-    S: Sync,
+    AppState: FromRef<S>,
+    S: Send + Sync,
 {
     type Rejection = AuthError;
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        claims_from_request::<AuthRequesterClaims>(parts).await
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        claims_from_request::<AuthRequesterClaims>(parts, &state).await
     }
 }
 
@@ -227,6 +229,9 @@ impl AuthError {
             0: types::auth::AuthError::from_error_type(error_type)
         }
     }
+    pub fn with_message(self, message: String) -> Self {
+        Self(self.0.with_message(message))
+    }
     pub fn status(&self) -> StatusCode {
         self.0.status.to_owned()
     }
@@ -237,6 +242,6 @@ impl AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response<Body> {
-        (self.status(), Json(json!(self.body()))).into_response()
+        AppError::from(self).into_response()
     }
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::async_trait;
+use tracing::instrument;
+use types::{chat::{ChatContact, ChatMessage, Room}, user::User};
+
+use crate::{db_error::DbError, pool::DbPool};
+
+// Where a message is posted
+#[derive(Debug, Clone, Copy)]
+pub enum MessageTarget<'a> {
+    Room(&'a Room),
+    // uuid of the recipient
+    Direct(&'a str)
+}
+
+// Storage of chat lines, returned messages carry the current username of their author
+#[async_trait]
+pub trait MessageRepository: Send + Sync {
+    async fn insert(&self, author: &User, target: MessageTarget<'_>, body: &str) -> Result<ChatMessage, DbError>;
+    // newest messages of the room with an id below before, or the newest overall, returned oldest first
+    async fn history(&self, room_id: i64, before: Option<i64>, limit: u32) -> Result<Vec<ChatMessage>, DbError>;
+    // oldest messages of the room with an id above after, in reading order
+    async fn after(&self, room_id: i64, after: i64, limit: u32) -> Result<Vec<ChatMessage>, DbError>;
+    // like history for the direct messages between two users
+    async fn direct_history(&self, user_uuid: &str, other_uuid: &str, before: Option<i64>, limit: u32) -> Result<Vec<ChatMessage>, DbError>;
+    // users the user exchanged direct messages with, ordered by username
+    async fn contacts(&self, user_uuid: &str) -> Result<Vec<ChatContact>, DbError>;
+    async fn find(&self, id: i64) -> Result<ChatMessage, DbError>;
+    // returns number of deleted messages
+    async fn delete(&self, id: i64) -> Result<u64, DbError>;
+    // every message written by the user, oldest first
+    async fn by_author(&self, author_uuid: &str) -> Result<Vec<ChatMessage>, DbError>;
+    // every direct message sent to the user, oldest first
+    async fn received_by(&self, recipient_uuid: &str) -> Result<Vec<ChatMessage>, DbError>;
+}
+
+// the queries are portable, so one repository serves every database kind
+pub fn repository(pool: DbPool) -> Arc<dyn MessageRepository> {
+    Arc::new(SqlMessageRepository::new(pool))
+}
+
+pub struct SqlMessageRepository {
+    pool: DbPool
+}
+
+impl SqlMessageRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+const SELECT_MESSAGES: &str = "SELECT m.id, m.author_uuid, u.username AS author, r.name AS room, m.recipient_uuid, m.body, m.created_at
+    FROM \"messages\" m JOIN \"users\" u ON u.uuid = m.author_uuid LEFT JOIN \"rooms\" r ON r.id = m.room_id";
+
+#[async_trait]
+impl MessageRepository for SqlMessageRepository {
+    #[instrument(name = "sql.insert_message", skip_all, fields(author = %author.uuid))]
+    async fn insert(&self, author: &User, target: MessageTarget<'_>, body: &str) -> Result<ChatMessage, DbError> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        let (room, recipient_uuid) = match target {
+            MessageTarget::Room(room) => (Some(room), None),
+            MessageTarget::Direct(recipient_uuid) => (None, Some(recipient_uuid.to_string()))
+        };
+        // fetch every row so SQLite finishes the statement and commits before the connection is reused
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO \"messages\" (author_uuid, room_id, recipient_uuid, body, created_at)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id;")
+            .bind(author.uuid.to_string())
+            .bind(room.map(|room| room.id))
+            .bind(recipient_uuid.clone())
+            .bind(body.to_string())
+            .bind(created_at)
+            .fetch_all(&self.pool).await?
+            .pop()
+            .ok_or(DbError::NotFound)?;
+        Ok(ChatMessage {
+            id,
+            author_uuid: author.uuid.to_string(),
+            author: author.username.to_string(),
+            room: room.map(|room| room.name.to_string()),
+            recipient_uuid,
+            body: body.to_string(),
+            created_at
+        })
+    }
+    #[instrument(name = "sql.get_message_history", skip_all, fields(room_id = room_id, before = before, limit = limit))]
+    async fn history(&self, room_id: i64, before: Option<i64>, limit: u32) -> Result<Vec<ChatMessage>, DbError> {
+        let mut messages = sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE m.room_id = $1 AND m.id < $2 ORDER BY m.id DESC LIMIT $3;", SELECT_MESSAGES))
+            .bind(room_id)
+            .bind(before.unwrap_or(i64::MAX))
+            .bind(i64::from(limit))
+            .fetch_all(&self.pool).await?;
+        // newest first keeps LIMIT on the right end, clients want reading order
+        messages.reverse();
+        Ok(messages)
+    }
+    #[instrument(name = "sql.get_messages_after", skip_all, fields(room_id = room_id, after = after, limit = limit))]
+    async fn after(&self, room_id: i64, after: i64, limit: u32) -> Result<Vec<ChatMessage>, DbError> {
+        Ok(sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE m.room_id = $1 AND m.id > $2 ORDER BY m.id LIMIT $3;", SELECT_MESSAGES))
+            .bind(room_id)
+            .bind(after)
+            .bind(i64::from(limit))
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_direct_history", skip_all, fields(before = before, limit = limit))]
+    async fn direct_history(&self, user_uuid: &str, other_uuid: &str, before: Option<i64>, limit: u32) -> Result<Vec<ChatMessage>, DbError> {
+        let mut messages = sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE ((m.author_uuid = $1 AND m.recipient_uuid = $2) OR (m.author_uuid = $2 AND m.recipient_uuid = $1))
+            AND m.id < $3 ORDER BY m.id DESC LIMIT $4;", SELECT_MESSAGES))
+            .bind(user_uuid.to_string())
+            .bind(other_uuid.to_string())
+            .bind(before.unwrap_or(i64::MAX))
+            .bind(i64::from(limit))
+            .fetch_all(&self.pool).await?;
+        messages.reverse();
+        Ok(messages)
+    }
+    #[instrument(name = "sql.get_chat_contacts", skip_all, fields(user = %user_uuid))]
+    async fn contacts(&self, user_uuid: &str) -> Result<Vec<ChatContact>, DbError> {
+        Ok(sqlx::query_as::<_, ChatContact>(
+            "SELECT uuid, username FROM \"users\" WHERE uuid IN (
+                SELECT recipient_uuid FROM \"messages\" WHERE author_uuid = $1 AND recipient_uuid IS NOT NULL
+                UNION SELECT author_uuid FROM \"messages\" WHERE recipient_uuid = $1)
+            ORDER BY username;")
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_message", skip_all, fields(id = id))]
+    async fn find(&self, id: i64) -> Result<ChatMessage, DbError> {
+        Ok(sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE m.id = $1;", SELECT_MESSAGES))
+            .bind(id)
+            .fetch_one(&self.pool).await?)
+    }
+    #[instrument(name = "sql.delete_message", skip_all, fields(id = id))]
+    async fn delete(&self, id: i64) -> Result<u64, DbError> {
+        let result = sqlx::query("DELETE FROM \"messages\" WHERE id = $1;")
+            .bind(id)
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+    #[instrument(name = "sql.get_messages_by_author", skip_all, fields(author = %author_uuid))]
+    async fn by_author(&self, author_uuid: &str) -> Result<Vec<ChatMessage>, DbError> {
+        Ok(sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE m.author_uuid = $1 ORDER BY m.id;", SELECT_MESSAGES))
+            .bind(author_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_messages_by_recipient", skip_all, fields(recipient = %recipient_uuid))]
+    async fn received_by(&self, recipient_uuid: &str) -> Result<Vec<ChatMessage>, DbError> {
+        Ok(sqlx::query_as::<_, ChatMessage>(
+            &format!("{} WHERE m.recipient_uuid = $1 ORDER BY m.id;", SELECT_MESSAGES))
+            .bind(recipient_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+}
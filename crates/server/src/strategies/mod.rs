@@ -1,2 +1,5 @@
 pub mod users;
+pub mod messages;
+pub mod rooms;
+pub mod mutes;
 pub mod authentication;
\ No newline at end of file
@@ -0,0 +1,88 @@
+use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::async_trait;
+use tracing::instrument;
+use types::chat::ChatMute;
+
+use crate::{db_error::DbError, pool::DbPool};
+
+// Storage of users who cannot send chat messages for a while
+#[async_trait]
+pub trait MuteRepository: Send + Sync {
+    // replaces an earlier mute of the user
+    async fn mute(&self, user_uuid: &str, muted_until: i64, muted_by: &str) -> Result<(), DbError>;
+    // returns number of lifted mutes
+    async fn unmute(&self, user_uuid: &str) -> Result<u64, DbError>;
+    // end of the mute of the user if it lasts beyond now
+    async fn muted_until(&self, user_uuid: &str, now: i64) -> Result<Option<i64>, DbError>;
+    // mutes lasting beyond now ordered by username
+    async fn active(&self, now: i64) -> Result<Vec<ChatMute>, DbError>;
+    // mute of the user, expired or not
+    async fn mute_of(&self, user_uuid: &str) -> Result<Option<ChatMute>, DbError>;
+}
+
+// the queries are portable, so one repository serves every database kind
+pub fn repository(pool: DbPool) -> Arc<dyn MuteRepository> {
+    Arc::new(SqlMuteRepository::new(pool))
+}
+
+pub struct SqlMuteRepository {
+    pool: DbPool
+}
+
+impl SqlMuteRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl MuteRepository for SqlMuteRepository {
+    #[instrument(name = "sql.upsert_chat_mute", skip_all, fields(user = %user_uuid, muted_until = muted_until))]
+    async fn mute(&self, user_uuid: &str, muted_until: i64, muted_by: &str) -> Result<(), DbError> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        sqlx::query(
+            "INSERT INTO \"chat_mutes\" (user_uuid, muted_until, muted_by, created_at) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_uuid) DO UPDATE SET muted_until = excluded.muted_until, muted_by = excluded.muted_by, created_at = excluded.created_at;")
+            .bind(user_uuid.to_string())
+            .bind(muted_until)
+            .bind(muted_by.to_string())
+            .bind(created_at)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+    #[instrument(name = "sql.delete_chat_mute", skip_all, fields(user = %user_uuid))]
+    async fn unmute(&self, user_uuid: &str) -> Result<u64, DbError> {
+        let result = sqlx::query("DELETE FROM \"chat_mutes\" WHERE user_uuid = $1;")
+            .bind(user_uuid.to_string())
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+    #[instrument(name = "sql.get_chat_mute", skip_all, fields(user = %user_uuid))]
+    async fn muted_until(&self, user_uuid: &str, now: i64) -> Result<Option<i64>, DbError> {
+        let mute = sqlx::query_as::<_, (i64,)>(
+            "SELECT muted_until FROM \"chat_mutes\" WHERE user_uuid = $1 AND muted_until > $2;")
+            .bind(user_uuid.to_string())
+            .bind(now)
+            .fetch_optional(&self.pool).await?;
+        Ok(mute.map(|(muted_until,)| muted_until))
+    }
+    #[instrument(name = "sql.get_active_chat_mutes", skip_all)]
+    async fn active(&self, now: i64) -> Result<Vec<ChatMute>, DbError> {
+        Ok(sqlx::query_as::<_, ChatMute>(
+            "SELECT u.uuid, u.username, k.muted_until FROM \"chat_mutes\" k JOIN \"users\" u ON u.uuid = k.user_uuid
+            WHERE k.muted_until > $1 ORDER BY u.username;")
+            .bind(now)
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_chat_mute_of_user", skip_all, fields(user = %user_uuid))]
+    async fn mute_of(&self, user_uuid: &str) -> Result<Option<ChatMute>, DbError> {
+        Ok(sqlx::query_as::<_, ChatMute>(
+            "SELECT u.uuid, u.username, k.muted_until FROM \"chat_mutes\" k JOIN \"users\" u ON u.uuid = k.user_uuid
+            WHERE k.user_uuid = $1;")
+            .bind(user_uuid.to_string())
+            .fetch_optional(&self.pool).await?)
+    }
+}
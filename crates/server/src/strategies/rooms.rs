@@ -0,0 +1,255 @@
+use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::async_trait;
+use tracing::instrument;
+use types::chat::{ReadMarker, Room, RoomBan, UnreadCount};
+
+use crate::{db_error::DbError, pool::DbPool};
+
+// Storage of chat rooms and the members of private rooms
+#[async_trait]
+pub trait RoomRepository: Send + Sync {
+    async fn find_by_name(&self, name: &str) -> Result<Room, DbError>;
+    // public rooms and private rooms the user is a member of, ordered by name
+    async fn visible_to(&self, user_uuid: &str) -> Result<Vec<Room>, DbError>;
+    // rooms the user is a member of, ordered by name
+    async fn member_of(&self, user_uuid: &str) -> Result<Vec<Room>, DbError>;
+    // rooms the user created, ordered by name
+    async fn created_by(&self, user_uuid: &str) -> Result<Vec<Room>, DbError>;
+    // name must already be normalized, the creator becomes the first member
+    async fn create(&self, name: &str, is_private: bool, creator_uuid: &str) -> Result<Room, DbError>;
+    // adding an existing member is not an error
+    async fn add_member(&self, room_id: i64, user_uuid: &str) -> Result<(), DbError>;
+    async fn is_member(&self, room_id: i64, user_uuid: &str) -> Result<bool, DbError>;
+    // move the read marker of the user forward, false if the message is older than the marker or not in the room
+    async fn mark_read(&self, room_id: i64, user_uuid: &str, message_id: i64) -> Result<bool, DbError>;
+    // read markers of every active user in the room, ordered by username
+    async fn read_markers(&self, room_id: i64) -> Result<Vec<ReadMarker>, DbError>;
+    async fn read_markers_of(&self, user_uuid: &str) -> Result<Vec<ReadMarker>, DbError>;
+    // messages of others after the marker, for every room the user has a read marker in
+    async fn unread_counts(&self, user_uuid: &str) -> Result<Vec<UnreadCount>, DbError>;
+    // banning also ends a private room membership, banning twice is not an error
+    async fn ban(&self, room_id: i64, user_uuid: &str, banned_by: &str) -> Result<(), DbError>;
+    // returns number of lifted bans
+    async fn unban(&self, room_id: i64, user_uuid: &str) -> Result<u64, DbError>;
+    async fn is_banned(&self, room_id: i64, user_uuid: &str) -> Result<bool, DbError>;
+    // every ban ordered by room and username
+    async fn bans(&self) -> Result<Vec<RoomBan>, DbError>;
+    async fn bans_of(&self, user_uuid: &str) -> Result<Vec<RoomBan>, DbError>;
+    async fn set_slow_mode(&self, room_id: i64, seconds: u32) -> Result<(), DbError>;
+    // seconds the user still has to wait before posting in the room at now, 0 if they may post
+    async fn slow_mode_wait(&self, room_id: i64, user_uuid: &str, now: i64) -> Result<i64, DbError>;
+}
+
+// the queries are portable, so one repository serves every database kind
+pub fn repository(pool: DbPool) -> Arc<dyn RoomRepository> {
+    Arc::new(SqlRoomRepository::new(pool))
+}
+
+// find a room the user may read and join, private rooms of other users are reported as missing
+pub async fn find_accessible(rooms: &dyn RoomRepository, name: &str, user_uuid: &str) -> Result<Room, DbError> {
+    let room = rooms.find_by_name(name).await?;
+    if room.is_private && !rooms.is_member(room.id, user_uuid).await? {
+        return Err(DbError::NotFound);
+    }
+    Ok(room)
+}
+
+pub struct SqlRoomRepository {
+    pool: DbPool
+}
+
+impl SqlRoomRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+const SELECT_READ_MARKERS: &str = "SELECT r.name AS room, u.username, k.message_id
+    FROM \"read_markers\" k JOIN \"rooms\" r ON r.id = k.room_id JOIN \"users\" u ON u.uuid = k.user_uuid";
+
+const SELECT_BANS: &str = "SELECT r.name AS room, u.uuid, u.username
+    FROM \"room_bans\" b JOIN \"rooms\" r ON r.id = b.room_id JOIN \"users\" u ON u.uuid = b.user_uuid";
+
+#[async_trait]
+impl RoomRepository for SqlRoomRepository {
+    #[instrument(name = "sql.get_room_by_name", skip_all, fields(room = %name))]
+    async fn find_by_name(&self, name: &str) -> Result<Room, DbError> {
+        Ok(sqlx::query_as::<_, Room>(
+            "SELECT id, name, is_private, slow_mode_secs FROM \"rooms\" WHERE name = $1;")
+            .bind(name.to_string())
+            .fetch_one(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_visible_rooms", skip_all, fields(user = %user_uuid))]
+    async fn visible_to(&self, user_uuid: &str) -> Result<Vec<Room>, DbError> {
+        Ok(sqlx::query_as::<_, Room>(
+            "SELECT id, name, is_private, slow_mode_secs FROM \"rooms\"
+            WHERE is_private = $1 OR id IN (SELECT room_id FROM \"room_members\" WHERE user_uuid = $2)
+            ORDER BY name;")
+            .bind(false)
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_member_rooms", skip_all, fields(user = %user_uuid))]
+    async fn member_of(&self, user_uuid: &str) -> Result<Vec<Room>, DbError> {
+        Ok(sqlx::query_as::<_, Room>(
+            "SELECT id, name, is_private, slow_mode_secs FROM \"rooms\"
+            WHERE id IN (SELECT room_id FROM \"room_members\" WHERE user_uuid = $1)
+            ORDER BY name;")
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_created_rooms", skip_all, fields(user = %user_uuid))]
+    async fn created_by(&self, user_uuid: &str) -> Result<Vec<Room>, DbError> {
+        Ok(sqlx::query_as::<_, Room>(
+            "SELECT id, name, is_private, slow_mode_secs FROM \"rooms\" WHERE created_by = $1 ORDER BY name;")
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.insert_room", skip_all, fields(room = %name, is_private = is_private))]
+    async fn create(&self, name: &str, is_private: bool, creator_uuid: &str) -> Result<Room, DbError> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        // fetch every row so SQLite finishes the statement and commits before the connection is reused
+        let room = sqlx::query_as::<_, Room>(
+            "INSERT INTO \"rooms\" (name, is_private, created_by, created_at) VALUES ($1, $2, $3, $4)
+            RETURNING id, name, is_private, slow_mode_secs;")
+            .bind(name.to_string())
+            .bind(is_private)
+            .bind(creator_uuid.to_string())
+            .bind(created_at)
+            .fetch_all(&self.pool).await?
+            .pop()
+            .ok_or(DbError::NotFound)?;
+        self.add_member(room.id, creator_uuid).await?;
+        Ok(room)
+    }
+    #[instrument(name = "sql.insert_room_member", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn add_member(&self, room_id: i64, user_uuid: &str) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO \"room_members\" (room_id, user_uuid) VALUES ($1, $2) ON CONFLICT DO NOTHING;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+    #[instrument(name = "sql.get_room_membership", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn is_member(&self, room_id: i64, user_uuid: &str) -> Result<bool, DbError> {
+        let (count,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM \"room_members\" WHERE room_id = $1 AND user_uuid = $2;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .fetch_one(&self.pool).await?;
+        Ok(count > 0)
+    }
+    #[instrument(name = "sql.upsert_read_marker", skip_all, fields(room_id = room_id, user = %user_uuid, message_id = message_id))]
+    async fn mark_read(&self, room_id: i64, user_uuid: &str, message_id: i64) -> Result<bool, DbError> {
+        let updated_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        // the WHERE clause of the SELECT also keeps SQLite from reading ON CONFLICT as a join constraint
+        let result = sqlx::query(
+            "INSERT INTO \"read_markers\" (user_uuid, room_id, message_id, updated_at)
+            SELECT $1, $2, $3, $4 WHERE EXISTS (SELECT 1 FROM \"messages\" WHERE id = $3 AND room_id = $2)
+            ON CONFLICT (user_uuid, room_id) DO UPDATE SET message_id = excluded.message_id, updated_at = excluded.updated_at
+            WHERE \"read_markers\".message_id < excluded.message_id;")
+            .bind(user_uuid.to_string())
+            .bind(room_id)
+            .bind(message_id)
+            .bind(updated_at)
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+    #[instrument(name = "sql.get_read_markers", skip_all, fields(room_id = room_id))]
+    async fn read_markers(&self, room_id: i64) -> Result<Vec<ReadMarker>, DbError> {
+        Ok(sqlx::query_as::<_, ReadMarker>(
+            &format!("{} WHERE k.room_id = $1 AND u.deleted_at IS NULL ORDER BY u.username;", SELECT_READ_MARKERS))
+            .bind(room_id)
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_read_markers_of_user", skip_all, fields(user = %user_uuid))]
+    async fn read_markers_of(&self, user_uuid: &str) -> Result<Vec<ReadMarker>, DbError> {
+        Ok(sqlx::query_as::<_, ReadMarker>(
+            &format!("{} WHERE k.user_uuid = $1 ORDER BY r.name;", SELECT_READ_MARKERS))
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_unread_counts", skip_all, fields(user = %user_uuid))]
+    async fn unread_counts(&self, user_uuid: &str) -> Result<Vec<UnreadCount>, DbError> {
+        Ok(sqlx::query_as::<_, UnreadCount>(
+            "SELECT r.name AS room, COUNT(m.id) AS unread FROM \"read_markers\" k
+            JOIN \"rooms\" r ON r.id = k.room_id
+            LEFT JOIN \"messages\" m ON m.room_id = k.room_id AND m.id > k.message_id AND m.author_uuid <> k.user_uuid
+            WHERE k.user_uuid = $1 GROUP BY r.name ORDER BY r.name;")
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.insert_room_ban", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn ban(&self, room_id: i64, user_uuid: &str, banned_by: &str) -> Result<(), DbError> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("INSERT INTO \"room_bans\" (room_id, user_uuid, banned_by, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .bind(banned_by.to_string())
+            .bind(created_at)
+            .execute(&mut transaction).await?;
+        sqlx::query("DELETE FROM \"room_members\" WHERE room_id = $1 AND user_uuid = $2;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .execute(&mut transaction).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+    #[instrument(name = "sql.delete_room_ban", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn unban(&self, room_id: i64, user_uuid: &str) -> Result<u64, DbError> {
+        let result = sqlx::query("DELETE FROM \"room_bans\" WHERE room_id = $1 AND user_uuid = $2;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+    #[instrument(name = "sql.get_room_ban", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn is_banned(&self, room_id: i64, user_uuid: &str) -> Result<bool, DbError> {
+        let (count,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM \"room_bans\" WHERE room_id = $1 AND user_uuid = $2;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .fetch_one(&self.pool).await?;
+        Ok(count > 0)
+    }
+    #[instrument(name = "sql.get_room_bans", skip_all)]
+    async fn bans(&self) -> Result<Vec<RoomBan>, DbError> {
+        Ok(sqlx::query_as::<_, RoomBan>(
+            &format!("{} ORDER BY r.name, u.username;", SELECT_BANS))
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.get_room_bans_of_user", skip_all, fields(user = %user_uuid))]
+    async fn bans_of(&self, user_uuid: &str) -> Result<Vec<RoomBan>, DbError> {
+        Ok(sqlx::query_as::<_, RoomBan>(
+            &format!("{} WHERE b.user_uuid = $1 ORDER BY r.name;", SELECT_BANS))
+            .bind(user_uuid.to_string())
+            .fetch_all(&self.pool).await?)
+    }
+    #[instrument(name = "sql.update_room_slow_mode", skip_all, fields(room_id = room_id, seconds = seconds))]
+    async fn set_slow_mode(&self, room_id: i64, seconds: u32) -> Result<(), DbError> {
+        sqlx::query("UPDATE \"rooms\" SET slow_mode_secs = $1 WHERE id = $2;")
+            .bind(i64::from(seconds))
+            .bind(room_id)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+    #[instrument(name = "sql.get_slow_mode_wait", skip_all, fields(room_id = room_id, user = %user_uuid))]
+    async fn slow_mode_wait(&self, room_id: i64, user_uuid: &str, now: i64) -> Result<i64, DbError> {
+        let (slow_mode_secs, last_message_at) = sqlx::query_as::<_, (i64, Option<i64>)>(
+            "SELECT r.slow_mode_secs, MAX(m.created_at) FROM \"rooms\" r
+            LEFT JOIN \"messages\" m ON m.room_id = r.id AND m.author_uuid = $2
+            WHERE r.id = $1 GROUP BY r.slow_mode_secs;")
+            .bind(room_id)
+            .bind(user_uuid.to_string())
+            .fetch_one(&self.pool).await?;
+        Ok(last_message_at.map_or(0, |last_message_at| (last_message_at + slow_mode_secs - now).max(0)))
+    }
+}
@@ -1,83 +1,395 @@
-use std::env;
+use std::{fmt, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::async_trait;
 use bcrypt::{DEFAULT_COST, hash_with_salt};
-use sqlx::any::AnyRow;
-use types::user::{RegisterUser, User, UserInfo};
+use sqlx::any::AnyKind;
+use tracing::instrument;
+use types::{page::Page, user::{RegisterUser, SortOrder, User, UserInfo, UserListQuery, UserSort}, username::username_key};
 use uuid::Uuid;
 
-use crate::pool;
+use crate::{db_error::DbError, pool::DbPool};
+
+// Error returned by user repositories, a missing user is told apart from a failed query
+#[derive(Debug)]
+pub enum UserRepositoryError {
+    NotFound,
+    PasswordHash(bcrypt::BcryptError),
+    Database(DbError)
+}
+
+impl fmt::Display for UserRepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserRepositoryError::NotFound => write!(f, "User not found"),
+            UserRepositoryError::PasswordHash(error) => write!(f, "Could not hash password: {}", error),
+            UserRepositoryError::Database(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for UserRepositoryError {}
+
+impl From<sqlx::Error> for UserRepositoryError {
+    fn from(error: sqlx::Error) -> Self {
+        match DbError::from(error) {
+            DbError::NotFound => UserRepositoryError::NotFound,
+            error => UserRepositoryError::Database(error)
+        }
+    }
+}
+
+// Storage of user accounts, passwords are hashed with the given salt before they are stored
+// and neither is recorded on spans. Soft deleted users are never returned by lookups, so
+// login, token creation and the websocket handshake all treat them as missing
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_uuid(&self, uuid: &str) -> Result<User, UserRepositoryError>;
+    // match normalized username or email
+    async fn find_by_username_or_email(&self, username_or_email: &str) -> Result<User, UserRepositoryError>;
+    async fn list(&self) -> Result<Vec<UserInfo>, UserRepositoryError>;
+    // one page of users matching the search and filters of the query
+    async fn page(&self, query: &UserListQuery) -> Result<Page<UserInfo>, UserRepositoryError>;
+    async fn insert(&self, register_user: RegisterUser, salt: [u8; 16]) -> Result<User, UserRepositoryError>;
+    // store every field of the user, pass is the new plain text password
+    async fn update(&self, user: User, salt: [u8; 16]) -> Result<User, UserRepositoryError>;
+    async fn set_admin(&self, uuid: &str, is_admin: bool) -> Result<(), UserRepositoryError>;
+    // soft delete, returns number of deleted rows, 0 if no active user had the uuid
+    async fn delete(&self, uuid: &str) -> Result<u64, UserRepositoryError>;
+    // undo a soft delete that was not erased yet, returns number of restored rows
+    async fn restore(&self, uuid: &str) -> Result<u64, UserRepositoryError>;
+    // anonymize users soft deleted at or before the unix timestamp, returns number of erased rows
+    async fn erase_deleted(&self, deleted_before: i64) -> Result<u64, UserRepositoryError>;
+}
+
+// pick the repository matching the database kind of the pool
+pub fn repository(pool: DbPool) -> Arc<dyn UserRepository> {
+    match pool.any_kind() {
+        AnyKind::Postgres => Arc::new(PostgresUserRepository::new(pool)),
+        AnyKind::Sqlite => Arc::new(SqliteUserRepository::new(pool))
+    }
+}
+
+pub struct SqliteUserRepository {
+    pool: DbPool
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn find_by_uuid(&self, uuid: &str) -> Result<User, UserRepositoryError> {
+        find_by_uuid(&self.pool, uuid).await
+    }
+    async fn find_by_username_or_email(&self, username_or_email: &str) -> Result<User, UserRepositoryError> {
+        find_by_username_or_email(&self.pool, username_or_email).await
+    }
+    async fn list(&self) -> Result<Vec<UserInfo>, UserRepositoryError> {
+        list(&self.pool).await
+    }
+    async fn page(&self, query: &UserListQuery) -> Result<Page<UserInfo>, UserRepositoryError> {
+        // LIKE is already case-insensitive for ASCII in SQLite
+        page(&self.pool, query, "LIKE").await
+    }
+    #[instrument(name = "sql.insert_user", skip_all)]
+    async fn insert(&self, register_user: RegisterUser, salt: [u8; 16]) -> Result<User, UserRepositoryError> {
+        // fetch every row so SQLite finishes the statement and commits before the connection is reused
+        insert_query(register_user, salt)?
+            .fetch_all(&self.pool).await?
+            .pop()
+            .ok_or(UserRepositoryError::NotFound)
+    }
+    #[instrument(name = "sql.update_user", skip_all, fields(uuid = %user.uuid))]
+    async fn update(&self, user: User, salt: [u8; 16]) -> Result<User, UserRepositoryError> {
+        // fetch every row so SQLite finishes the statement and commits before the connection is reused
+        update_query(user, salt)?
+            .fetch_all(&self.pool).await?
+            .pop()
+            .ok_or(UserRepositoryError::NotFound)
+    }
+    async fn set_admin(&self, uuid: &str, is_admin: bool) -> Result<(), UserRepositoryError> {
+        set_admin(&self.pool, uuid, is_admin).await
+    }
+    async fn delete(&self, uuid: &str) -> Result<u64, UserRepositoryError> {
+        delete(&self.pool, uuid).await
+    }
+    async fn restore(&self, uuid: &str) -> Result<u64, UserRepositoryError> {
+        restore(&self.pool, uuid).await
+    }
+    async fn erase_deleted(&self, deleted_before: i64) -> Result<u64, UserRepositoryError> {
+        erase_deleted(&self.pool, deleted_before).await
+    }
+}
+
+pub struct PostgresUserRepository {
+    pool: DbPool
+}
+
+impl PostgresUserRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn find_by_uuid(&self, uuid: &str) -> Result<User, UserRepositoryError> {
+        find_by_uuid(&self.pool, uuid).await
+    }
+    async fn find_by_username_or_email(&self, username_or_email: &str) -> Result<User, UserRepositoryError> {
+        find_by_username_or_email(&self.pool, username_or_email).await
+    }
+    async fn list(&self) -> Result<Vec<UserInfo>, UserRepositoryError> {
+        list(&self.pool).await
+    }
+    async fn page(&self, query: &UserListQuery) -> Result<Page<UserInfo>, UserRepositoryError> {
+        page(&self.pool, query, "ILIKE").await
+    }
+    #[instrument(name = "sql.insert_user", skip_all)]
+    async fn insert(&self, register_user: RegisterUser, salt: [u8; 16]) -> Result<User, UserRepositoryError> {
+        Ok(insert_query(register_user, salt)?.fetch_one(&self.pool).await?)
+    }
+    #[instrument(name = "sql.update_user", skip_all, fields(uuid = %user.uuid))]
+    async fn update(&self, user: User, salt: [u8; 16]) -> Result<User, UserRepositoryError> {
+        Ok(update_query(user, salt)?.fetch_one(&self.pool).await?)
+    }
+    async fn set_admin(&self, uuid: &str, is_admin: bool) -> Result<(), UserRepositoryError> {
+        set_admin(&self.pool, uuid, is_admin).await
+    }
+    async fn delete(&self, uuid: &str) -> Result<u64, UserRepositoryError> {
+        delete(&self.pool, uuid).await
+    }
+    async fn restore(&self, uuid: &str) -> Result<u64, UserRepositoryError> {
+        restore(&self.pool, uuid).await
+    }
+    async fn erase_deleted(&self, deleted_before: i64) -> Result<u64, UserRepositoryError> {
+        erase_deleted(&self.pool, deleted_before).await
+    }
+}
+
+type UserQuery = sqlx::query::QueryAs<'static, sqlx::Any, User, sqlx::any::AnyArguments<'static>>;
 
-pub async fn get_db_user_by_username_or_email(username_or_email: String) -> Result<User, sqlx::Error> {
-    // query for getting all data from users table where user row matches given user ID
-    sqlx::query_as::<_, User>(
-        "SELECT * FROM \"users\" WHERE username = $1 OR email = $1;")
-    .bind(username_or_email)
-    .fetch_one(&pool::get_pool()).await
+fn hash_password(pass: String, salt: [u8; 16]) -> Result<String, UserRepositoryError> {
+    hash_with_salt(pass, DEFAULT_COST, salt)
+        .map(|hash| hash.to_string())
+        .map_err(UserRepositoryError::PasswordHash)
 }
 
-pub async fn get_db_user_by_uuid(uuid: String) -> Result<User, sqlx::Error> {
-    sqlx::query_as::<_,User>(
-        "SELECT * FROM \"users\" WHERE uuid = $1;")
-        .bind(uuid)
-        .fetch_one(&pool::get_pool()).await
+// queries below are valid on both backends through the Any driver
+
+#[instrument(name = "sql.get_user_by_uuid", skip_all, fields(uuid = %uuid))]
+async fn find_by_uuid(pool: &DbPool, uuid: &str) -> Result<User, UserRepositoryError> {
+    Ok(sqlx::query_as::<_, User>(
+        "SELECT * FROM \"users\" WHERE uuid = $1 AND deleted_at IS NULL;")
+        .bind(uuid.to_string())
+        .fetch_one(pool).await?)
+}
+
+#[instrument(name = "sql.get_user_by_username_or_email", skip_all)]
+async fn find_by_username_or_email(pool: &DbPool, username_or_email: &str) -> Result<User, UserRepositoryError> {
+    // query for getting all data from users table where normalized username or email matches
+    Ok(sqlx::query_as::<_, User>(
+        "SELECT * FROM \"users\" WHERE (username_key = $1 OR email = $2) AND deleted_at IS NULL;")
+        .bind(username_key(username_or_email))
+        .bind(username_or_email.trim().to_string())
+        .fetch_one(pool).await?)
 }
 
-pub async fn get_all_users() -> Result<Vec<UserInfo>, sqlx::Error> {
-    sqlx::query_as::<_, UserInfo>("SELECT * FROM \"users\";")
-        .fetch_all(&pool::get_pool()).await
+#[instrument(name = "sql.get_all_users", skip_all)]
+async fn list(pool: &DbPool) -> Result<Vec<UserInfo>, UserRepositoryError> {
+    Ok(sqlx::query_as::<_, UserInfo>("SELECT * FROM \"users\" WHERE deleted_at IS NULL;")
+        .fetch_all(pool).await?)
 }
 
-pub async fn delete_user_by_uuid(uuid: String) -> Result<AnyRow, sqlx::Error> {
-    sqlx::query("DELETE FROM \"users\" WHERE uuid = $1;")
-        .bind(uuid)
-        .fetch_one(&pool::get_pool()).await
+// value bound to a placeholder of a dynamically built query
+enum Bind {
+    Text(String),
+    Bool(bool)
 }
 
-pub async fn insert_db_user(register_user: RegisterUser) -> Result<User, sqlx::Error> {
+// escape LIKE wildcards so the search term only matches literally
+fn like_pattern(search: &str) -> String {
+    let escaped = search.trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[instrument(name = "sql.get_user_page", skip_all, fields(page = query.page(), limit = query.limit()))]
+async fn page(pool: &DbPool, query: &UserListQuery, like: &str) -> Result<Page<UserInfo>, UserRepositoryError> {
+    // build WHERE clause from the set filters, values are always bound
+    let mut conditions = vec![String::from("erased_at IS NULL")];
+    conditions.push(String::from(match query.is_deleted {
+        Some(true) => "deleted_at IS NOT NULL",
+        Some(false) | None => "deleted_at IS NULL"
+    }));
+    let mut binds = Vec::new();
+    if let Some(search) = query.search.as_deref().filter(|search| !search.trim().is_empty()) {
+        let pattern = like_pattern(search);
+        conditions.push(format!("(username {like} ${} ESCAPE '\\' OR email {like} ${} ESCAPE '\\')", binds.len() + 1, binds.len() + 2));
+        binds.push(Bind::Text(pattern.clone()));
+        binds.push(Bind::Text(pattern));
+    }
+    for (column, value) in [("is_admin", query.is_admin), ("is_disabled", query.is_disabled), ("is_verified", query.is_verified)] {
+        if let Some(value) = value {
+            conditions.push(format!("{} = ${}", column, binds.len() + 1));
+            binds.push(Bind::Bool(value));
+        }
+    }
+    let where_clause = format!(" WHERE {}", conditions.join(" AND "));
+    // sort column comes from a fixed list, id keeps pages stable between equal values
+    let column = match query.sort {
+        UserSort::Created => "id",
+        UserSort::Username => "username_key",
+        UserSort::Email => "email",
+        UserSort::Admin => "is_admin"
+    };
+    let order = match query.order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC"
+    };
+
+    let count_sql = format!("SELECT COUNT(*) FROM \"users\"{};", where_clause);
+    let mut count_query = sqlx::query_as::<_, (i64,)>(&count_sql);
+    for bind in &binds {
+        count_query = match bind {
+            Bind::Text(value) => count_query.bind(value.clone()),
+            Bind::Bool(value) => count_query.bind(*value)
+        };
+    }
+    let (total,) = count_query.fetch_one(pool).await?;
+
+    let page_sql = format!(
+        "SELECT * FROM \"users\"{} ORDER BY {} {}, id {} LIMIT {} OFFSET {};",
+        where_clause, column, order, order, query.limit(), query.offset());
+    let mut page_query = sqlx::query_as::<_, UserInfo>(&page_sql);
+    for bind in binds {
+        page_query = match bind {
+            Bind::Text(value) => page_query.bind(value),
+            Bind::Bool(value) => page_query.bind(value)
+        };
+    }
+    let items = page_query.fetch_all(pool).await?;
+
+    Ok(Page {
+        items,
+        page: query.page(),
+        limit: query.limit(),
+        total: u64::try_from(total).unwrap_or_default()
+    })
+}
+
+fn insert_query(register_user: RegisterUser, salt: [u8; 16]) -> Result<UserQuery, UserRepositoryError> {
     // generate new user id
     let id = Uuid::new_v4();
-    // initialize salt str slice
-    let mut salt: [u8; 16] = [0;16];
-    // load 16 bytes from PASSWORD_SALT env variable to salt str slice
-    salt.copy_from_slice(&env::var("PASSWORD_SALT").unwrap().as_bytes()[0..16]);
+    let username_key = username_key(&register_user.username);
     // perform query to insert new user with hashed password and bind all payload object fields
-    sqlx::query_as::<_, User>(
-        "INSERT INTO \"users\" (uuid, username, pass, email, is_admin)
-        VALUES ($1, $2, $3, $4, $5)
+    Ok(sqlx::query_as::<_, User>(
+        "INSERT INTO \"users\" (uuid, username, pass, email, is_admin, username_key)
+        VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING *;")
         .bind(id.to_string())
         .bind(register_user.username)
-        // hash password with salt
-        .bind(hash_with_salt(
-            register_user.pass,
-            DEFAULT_COST,
-            salt
-        ).unwrap().to_string())
+        .bind(hash_password(register_user.pass, salt)?)
         .bind(register_user.email)
         .bind(false)
-        .fetch_one(&pool::get_pool()).await
+        .bind(username_key))
 }
 
-pub async fn update_db_user(user: User) -> Result<AnyRow, sqlx::Error> {
-    // initialize salt str slice
-    let mut salt: [u8; 16] = [0;16];
-    // load 16 bytes from PASSWORD_SALT env variable to salt str slice
-    salt.copy_from_slice(&env::var("PASSWORD_SALT").unwrap().as_bytes()[0..16]);
-    // perform query to insert new user with hashed password and bind all payload object fields
-    sqlx::query(
+fn update_query(user: User, salt: [u8; 16]) -> Result<UserQuery, UserRepositoryError> {
+    let username_key = username_key(&user.username);
+    // perform query to update user with hashed password and bind all fields
+    Ok(sqlx::query_as::<_, User>(
         "UPDATE \"users\"
-        SET uuid = $2, username = $3, pass = $4, email = $5, is_admin = $6
-        WHERE id = $1
+        SET uuid = $2, username = $3, pass = $4, email = $5, is_admin = $6, username_key = $7
+        WHERE id = $1 AND deleted_at IS NULL
         RETURNING *;")
         .bind(user.id)
         .bind(user.uuid)
         .bind(user.username)
-        // hash password with salt
-        .bind(hash_with_salt(
-            user.pass,
-            DEFAULT_COST,
-            salt
-        ).unwrap().to_string())
+        .bind(hash_password(user.pass, salt)?)
         .bind(user.email.to_string())
         .bind(user.is_admin)
-        .fetch_one(&pool::get_pool()).await
-}
\ No newline at end of file
+        .bind(username_key))
+}
+
+#[instrument(name = "sql.set_user_admin", skip_all, fields(uuid = %uuid, is_admin = is_admin))]
+async fn set_admin(pool: &DbPool, uuid: &str, is_admin: bool) -> Result<(), UserRepositoryError> {
+    // only touches is_admin, update would rehash the stored password
+    let result = sqlx::query("UPDATE \"users\" SET is_admin = $1 WHERE uuid = $2 AND deleted_at IS NULL;")
+        .bind(is_admin)
+        .bind(uuid.to_string())
+        .execute(pool).await?;
+    if result.rows_affected() == 0 {
+        return Err(UserRepositoryError::NotFound);
+    }
+    Ok(())
+}
+
+// current unix timestamp in seconds
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[instrument(name = "sql.delete_user_by_uuid", skip_all, fields(uuid = %uuid))]
+async fn delete(pool: &DbPool, uuid: &str) -> Result<u64, UserRepositoryError> {
+    // soft delete returns no rows, callers check rows affected to detect a missing user
+    let result = sqlx::query("UPDATE \"users\" SET deleted_at = $1 WHERE uuid = $2 AND deleted_at IS NULL;")
+        .bind(now())
+        .bind(uuid.to_string())
+        .execute(pool).await?;
+    Ok(result.rows_affected())
+}
+
+#[instrument(name = "sql.restore_user_by_uuid", skip_all, fields(uuid = %uuid))]
+async fn restore(pool: &DbPool, uuid: &str) -> Result<u64, UserRepositoryError> {
+    let result = sqlx::query("UPDATE \"users\" SET deleted_at = NULL WHERE uuid = $1 AND deleted_at IS NOT NULL AND erased_at IS NULL;")
+        .bind(uuid.to_string())
+        .execute(pool).await?;
+    Ok(result.rows_affected())
+}
+
+// chat data of an erased user, rooms they created stay for the other members
+const ERASE_CHAT_DATA: [&str; 6] = [
+    "DELETE FROM \"messages\" WHERE author_uuid = $1 OR recipient_uuid = $1;",
+    "DELETE FROM \"read_markers\" WHERE user_uuid = $1;",
+    "DELETE FROM \"room_members\" WHERE user_uuid = $1;",
+    "DELETE FROM \"chat_mutes\" WHERE user_uuid = $1;",
+    "DELETE FROM \"room_bans\" WHERE user_uuid = $1;",
+    "UPDATE \"rooms\" SET created_by = NULL WHERE created_by = $1;"
+];
+
+#[instrument(name = "sql.erase_deleted_users", skip_all, fields(deleted_before = deleted_before))]
+async fn erase_deleted(pool: &DbPool, deleted_before: i64) -> Result<u64, UserRepositoryError> {
+    let mut transaction = pool.begin().await?;
+    let erased = sqlx::query_as::<_, (String,)>(
+        "SELECT uuid FROM \"users\" WHERE deleted_at IS NOT NULL AND deleted_at <= $1 AND erased_at IS NULL;")
+        .bind(deleted_before)
+        .fetch_all(&mut transaction).await?;
+    for (uuid,) in &erased {
+        for statement in ERASE_CHAT_DATA {
+            sqlx::query(statement)
+                .bind(uuid.clone())
+                .execute(&mut transaction).await?;
+        }
+    }
+    // the row is kept so the uuid still resolves for anything referencing the user, every
+    // personal field is replaced. '~' cannot start a registered username so names never collide
+    let result = sqlx::query(
+        "UPDATE \"users\"
+        SET username = '~deleted-' || id, username_key = '~deleted-' || id, email = '~deleted-' || id || '@erased.invalid',
+            pass = '', is_admin = $1, is_verified = $1, erased_at = $2
+        WHERE deleted_at IS NOT NULL AND deleted_at <= $3 AND erased_at IS NULL;")
+        .bind(false)
+        .bind(now())
+        .bind(deleted_before)
+        .execute(&mut transaction).await?;
+    transaction.commit().await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,95 @@
+use std::{str::FromStr, time::Duration};
+
+use axum::{body::Body, extract::{MatchedPath, Request}, response::Response};
+use tower_http::{classify::{ServerErrorsAsFailures, SharedClassifier}, trace::TraceLayer};
+use tracing::{field::Empty, Span};
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+use types::problem::REQUEST_ID_HEADER;
+
+use crate::config::LogConfig;
+
+// filter used when log.filter and RUST_LOG are not set
+pub const DEFAULT_LOG_FILTER: &str = "info,server=debug,sqlx=warn";
+
+// output format of log lines
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, expected pretty or json", format))
+        }
+    }
+}
+
+// install global tracing subscriber with configured format and level filter
+pub fn init_tracing(config: &LogConfig) {
+    let filter = EnvFilter::try_new(&config.filter)
+        .unwrap_or_else(|error| {
+            eprintln!("Invalid log filter {}: {}", config.filter, error);
+            EnvFilter::new(DEFAULT_LOG_FILTER)
+        });
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match config.format {
+        LogFormat::Json => builder.json().flatten_event(true).try_init(),
+        LogFormat::Pretty => builder.pretty().try_init()
+    };
+    if let Err(error) = result {
+        eprintln!("Could not install tracing subscriber: {}", error);
+    }
+}
+
+type MakeRequestSpan = fn(&Request<Body>) -> Span;
+type RecordResponse = fn(&Response<Body>, Duration, &Span);
+
+// layer type produced by trace_layer
+pub type RequestTraceLayer = TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeRequestSpan, (), RecordResponse>;
+
+// layer creating one span per request with method, route, status, latency and user uuid
+pub fn trace_layer() -> RequestTraceLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(make_request_span as MakeRequestSpan)
+        .on_request(())
+        .on_response(record_response as RecordResponse)
+}
+
+fn make_request_span(request: &Request<Body>) -> Span {
+    // use matched route template so path parameters such as reset keys are never logged
+    let route = request.extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| String::from("<unmatched>"));
+    let request_id = request.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route = %route,
+        request_id = %request_id,
+        status = Empty,
+        latency_ms = Empty,
+        user = Empty
+    )
+}
+
+fn record_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    tracing::info!("finished request");
+}
+
+// attach user uuid from verified claims to the current request span
+pub fn record_user(uuid: &str) {
+    Span::current().record("user", uuid);
+}
@@ -0,0 +1,277 @@
+use std::{env, fs, net::SocketAddr, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
+
+use axum::{body::{to_bytes, Body, Bytes}, Router};
+use http::{header::{AUTHORIZATION, CONTENT_TYPE}, HeaderMap, Method, Request, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use tower::ServiceExt;
+use types::{auth::AuthToken, problem::ProblemDetails, user::{LoginUser, RegisterUser}, username::username_key};
+use uuid::Uuid;
+
+use crate::{build_app, config::Config, mail::{Email, MailError, Mailer}, migrations, pool, pubsub::PubSub, state::AppState};
+
+// Mailer keeping sent emails in memory so tests can read reset links
+#[derive(Default)]
+pub struct CapturingMailer {
+    sent: Mutex<Vec<Email>>,
+    unconfigured: AtomicBool
+}
+
+impl CapturingMailer {
+    // all emails sent so far
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+    // most recently sent email
+    pub fn last(&self) -> Option<Email> {
+        self.sent.lock().unwrap().last().cloned()
+    }
+    // act like a server without smtp settings
+    pub fn set_configured(&self, configured: bool) {
+        self.unconfigured.store(!configured, Ordering::Relaxed);
+    }
+}
+
+impl Mailer for CapturingMailer {
+    fn is_configured(&self) -> bool {
+        !self.unconfigured.load(Ordering::Relaxed)
+    }
+    fn send(&self, email: Email) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+// App built in-process against a temporary SQLite database with migrations applied
+pub struct TestApp {
+    pub state: AppState,
+    pub router: Router,
+    pub mailer: Arc<CapturingMailer>,
+    database_path: PathBuf
+}
+
+impl TestApp {
+    // create app with default test settings
+    pub async fn new() -> TestApp {
+        Self::with_config(|_| {}).await
+    }
+    // create app, letting the caller adjust the config before the app is built
+    pub async fn with_config<F>(configure: F) -> TestApp
+    where F: FnOnce(&mut Config) {
+        Self::build(configure, None).await
+    }
+    // create app sending chat events through the given backend, apps sharing it act as instances of one deployment
+    pub async fn with_pubsub(pubsub: Arc<dyn PubSub>) -> TestApp {
+        Self::build(|_| {}, Some(pubsub)).await
+    }
+    // like with_pubsub, letting the caller adjust the config as well
+    pub async fn with_pubsub_and_config<F>(pubsub: Arc<dyn PubSub>, configure: F) -> TestApp
+    where F: FnOnce(&mut Config) {
+        Self::build(configure, Some(pubsub)).await
+    }
+    async fn build<F>(configure: F, pubsub: Option<Arc<dyn PubSub>>) -> TestApp
+    where F: FnOnce(&mut Config) {
+        let database_path = env::temp_dir().join(format!("server-test-{}.db", Uuid::new_v4()));
+        let database_url = format!("sqlite://{}?mode=rwc", database_path.display());
+        let mut config = Config::from_toml(&test_config_toml(&database_url), |_| None)
+            .expect("test config must be valid");
+        configure(&mut config);
+        let pool = pool::create_pool(&config.database).await
+            .expect("could not create test database");
+        migrations::run(&pool).await
+            .expect("could not run migrations on test database");
+        let mailer = Arc::new(CapturingMailer::default());
+        let state = match pubsub {
+            Some(pubsub) => AppState::with_pubsub(pool, config, mailer.clone(), pubsub),
+            None => AppState::new(pool, config, mailer.clone())
+        };
+        let router = build_app(state.clone());
+        TestApp { state, router, mailer, database_path }
+    }
+    // send request through the router without a network listener
+    pub async fn request(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await
+            .expect("router is infallible");
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await
+            .expect("could not read response body");
+        TestResponse { status, headers, body }
+    }
+    // client without any tokens
+    pub fn client(&self) -> TestClient<'_> {
+        TestClient { app: self, requester_token: None }
+    }
+    // grant admin access to a user, there is no API for this
+    pub async fn promote(&self, username: &str) {
+        self.set_admin(username, true).await;
+    }
+    pub async fn demote(&self, username: &str) {
+        self.set_admin(username, false).await;
+    }
+    async fn set_admin(&self, username: &str, is_admin: bool) {
+        sqlx::query("UPDATE \"users\" SET is_admin = $1 WHERE username_key = $2;")
+            .bind(is_admin)
+            .bind(username_key(username))
+            .execute(&self.state.pool).await
+            .expect("could not change admin access of user");
+    }
+    // serve the app on an ephemeral local port, needed for websocket clients
+    pub async fn spawn(&self) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .expect("could not bind test listener");
+        let addr = listener.local_addr().expect("listener has an address");
+        let router = self.router.clone();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("test server failed");
+        });
+        addr
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = fs::remove_file(format!("{}{}", self.database_path.display(), suffix));
+        }
+    }
+}
+
+fn test_config_toml(database_url: &str) -> String {
+    format!(r#"
+[server]
+port = 0
+
+[database]
+url = "{database_url}"
+max_connections = 5
+
+[auth]
+token_secret = "test-secret"
+password_salt = "TESTSALTTESTSALT"
+token_lifetime = 60
+requester_token_lifetime = 600
+
+[company]
+name = "TestCompany"
+domain = "test.local"
+
+[log]
+filter = "warn"
+"#)
+}
+
+// Response with the body read into memory
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes
+}
+
+impl TestResponse {
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|error| panic!("could not parse body {:?}: {}", self.text(), error))
+    }
+    pub fn problem(&self) -> ProblemDetails {
+        self.json()
+    }
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+    // token from the Authorization response header
+    pub fn auth_token(&self) -> Option<String> {
+        self.headers.get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| AuthToken::from_string(value.to_string()).access_token)
+    }
+}
+
+// token sent with a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token {
+    None,
+    // long lived token from login or register
+    Requester,
+    // short lived token from /auth/request
+    Auth
+}
+
+// Client performing the two-step token dance: login or register stores the requester token,
+// requests needing AuthClaims fetch a fresh auth token from /auth/request first
+pub struct TestClient<'a> {
+    app: &'a TestApp,
+    requester_token: Option<String>
+}
+
+impl<'a> TestClient<'a> {
+    pub async fn register(&mut self, username: &str, email: &str, pass: &str) -> TestResponse {
+        let body = RegisterUser {
+            username: username.to_string(),
+            email: email.to_string(),
+            pass: pass.to_string()
+        };
+        let response = self.post_json("/auth/register", &body, Token::None).await;
+        if let Some(token) = response.auth_token() {
+            self.requester_token = Some(token);
+        }
+        response
+    }
+    pub async fn login(&mut self, username: &str, pass: &str) -> TestResponse {
+        let body = LoginUser {
+            username: username.to_string(),
+            pass: pass.to_string()
+        };
+        let response = self.post_json("/auth/login", &body, Token::None).await;
+        if let Some(token) = response.auth_token() {
+            self.requester_token = Some(token);
+        }
+        response
+    }
+    pub fn requester_token(&self) -> Option<&str> {
+        self.requester_token.as_deref()
+    }
+    // exchange the requester token for a short lived auth token
+    pub async fn auth_token(&self) -> Result<String, TestResponse> {
+        let response = self.send(Method::GET, "/auth/request", Body::empty(), None, Token::Requester).await;
+        match response.auth_token() {
+            Some(token) if response.status == StatusCode::CREATED => Ok(token),
+            _ => Err(response)
+        }
+    }
+    pub async fn get(&self, path: &str, token: Token) -> TestResponse {
+        self.send(Method::GET, path, Body::empty(), None, token).await
+    }
+    pub async fn post_json<T: Serialize>(&self, path: &str, body: &T, token: Token) -> TestResponse {
+        let body = serde_json::to_string(body).expect("body must serialize");
+        self.send(Method::POST, path, Body::from(body), Some("application/json"), token).await
+    }
+    pub async fn post_text(&self, path: &str, body: &str, token: Token) -> TestResponse {
+        self.send(Method::POST, path, Body::from(body.to_string()), Some("text/plain"), token).await
+    }
+    pub async fn delete_text(&self, path: &str, body: &str, token: Token) -> TestResponse {
+        self.send(Method::DELETE, path, Body::from(body.to_string()), Some("text/plain"), token).await
+    }
+    pub async fn delete_json<T: Serialize>(&self, path: &str, body: &T, token: Token) -> TestResponse {
+        let body = serde_json::to_string(body).expect("body must serialize");
+        self.send(Method::DELETE, path, Body::from(body), Some("application/json"), token).await
+    }
+    pub async fn send(&self, method: Method, path: &str, body: Body, content_type: Option<&str>, token: Token) -> TestResponse {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        let bearer = match token {
+            Token::None => None,
+            Token::Requester => self.requester_token.clone(),
+            Token::Auth => match Box::pin(self.auth_token()).await {
+                Ok(token) => Some(token),
+                Err(response) => return response
+            }
+        };
+        if let Some(bearer) = bearer {
+            builder = builder.header(AUTHORIZATION, AuthToken::new(bearer).to_string());
+        }
+        self.app.request(builder.body(body).expect("request must be valid")).await
+    }
+}
@@ -0,0 +1,96 @@
+use http::StatusCode;
+use serde_json::json;
+use server::testing::{TestApp, Token};
+use types::{auth::AuthErrorType, user::UserInfo};
+
+#[tokio::test]
+async fn register_returns_user_and_requester_token() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let response = client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let user: UserInfo = response.json();
+    assert_eq!(user.username, "ferris");
+    assert_eq!(user.email, "ferris@example.com");
+    assert!(!user.is_admin);
+    assert!(client.requester_token().is_some());
+}
+
+#[tokio::test]
+async fn register_rejects_username_differing_only_by_case() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    let response = app.client().register("FERRIS", "other@example.com", "crabby-pass").await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::UsernameTaken)));
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_is_rejected() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut client = app.client();
+    let response = client.login("ferris", "wrong-pass").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::WrongCredentials)));
+    assert!(client.requester_token().is_none());
+}
+
+#[tokio::test]
+async fn login_token_can_request_auth_token() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut client = app.client();
+    let response = client.login("Ferris", "crabby-pass").await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    assert!(client.auth_token().await.is_ok());
+    // auth tokens are accepted by routes requiring AuthClaims
+    let response = client.get("/auth/test", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn request_auth_token_requires_requester_token() {
+    let app = TestApp::new().await;
+    let response = app.client().get("/auth/request", Token::None).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::InvalidToken)));
+}
+
+#[tokio::test]
+async fn password_reset_with_emailed_key() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    let client = app.client();
+
+    let response = client.post_text("/auth/reset", "ferris@example.com", Token::None).await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let email = app.mailer.last().expect("reset email was sent");
+    assert_eq!(email.to, "ferris@example.com");
+    let reset_key = email.html
+        .split("key=").nth(1)
+        .and_then(|rest| rest.split('&').next())
+        .expect("reset link contains key")
+        .to_string();
+
+    let body = json!({"email_address": "ferris@example.com", "pass": "new-crabby-pass"});
+    let response = client.post_json(&format!("/auth/reset/{}", reset_key), &body, Token::None).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED);
+
+    let mut client = app.client();
+    assert_eq!(client.login("ferris", "crabby-pass").await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(client.login("ferris", "new-crabby-pass").await.status, StatusCode::CREATED);
+
+    // reset keys can only be used once
+    let response = client.post_json(&format!("/auth/reset/{}", reset_key), &body, Token::None).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::ResetLinkInvalid)));
+}
+
+#[tokio::test]
+async fn password_reset_for_unknown_email_sends_nothing() {
+    let app = TestApp::new().await;
+    let response = app.client().post_text("/auth/reset", "nobody@example.com", Token::None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(app.mailer.sent().is_empty());
+}
@@ -0,0 +1,137 @@
+use http::StatusCode;
+use server::{strategies::messages::MessageTarget, testing::{TestApp, Token}};
+use types::{chat::{ChatContact, ChatMessage, CreateRoom, ModerationAction, ModerationOverview, Room}, user::UserInfo};
+
+fn bodies(messages: &[ChatMessage]) -> Vec<&str> {
+    messages.iter().map(|message| message.body.as_str()).collect()
+}
+
+#[tokio::test]
+async fn history_pages_backwards_from_cursor() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let ferris = app.state.users.find_by_username_or_email("ferris").await.unwrap();
+    let general = app.state.rooms.find_by_name("general").await.unwrap();
+    for body in ["one", "two", "three"] {
+        app.state.messages.insert(&ferris, MessageTarget::Room(&general), body).await.unwrap();
+    }
+
+    let newest: Vec<ChatMessage> = client.get("/chat/history?limit=2", Token::Auth).await.json();
+    assert_eq!(bodies(&newest), ["two", "three"]);
+    assert_eq!(newest[0].author, "ferris");
+
+    let older: Vec<ChatMessage> = client.get(&format!("/chat/history?limit=2&before={}", newest[0].id), Token::Auth).await.json();
+    assert_eq!(bodies(&older), ["one"]);
+}
+
+#[tokio::test]
+async fn history_requires_token_and_valid_limit() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    assert_eq!(client.get("/chat/history", Token::None).await.status, StatusCode::FORBIDDEN);
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    assert_eq!(client.get("/chat/history?limit=0", Token::Auth).await.status, StatusCode::BAD_REQUEST);
+    assert_eq!(client.get("/chat/history", Token::Auth).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn private_rooms_are_only_visible_to_members() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+
+    let payload = CreateRoom { name: String::from("Crabs"), is_private: true };
+    let response = ferris.post_json("/chat/rooms", &payload, Token::Auth).await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let room: Room = response.json();
+    assert_eq!(room.name, "crabs");
+    assert_eq!(ferris.post_json("/chat/rooms", &payload, Token::Auth).await.status, StatusCode::BAD_REQUEST);
+
+    let names = |rooms: Vec<Room>| rooms.into_iter().map(|room| room.name).collect::<Vec<_>>();
+    assert_eq!(names(ferris.get("/chat/rooms", Token::Auth).await.json()), ["crabs", "general"]);
+    assert_eq!(names(corro.get("/chat/rooms", Token::Auth).await.json()), ["general"]);
+    assert_eq!(corro.get("/chat/history?room=crabs", Token::Auth).await.status, StatusCode::NOT_FOUND);
+    // only members can invite
+    assert_eq!(corro.post_text("/chat/rooms/crabs/members", &corro_info.uuid, Token::Auth).await.status, StatusCode::NOT_FOUND);
+
+    assert_eq!(ferris.post_text("/chat/rooms/crabs/members", &corro_info.uuid, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(names(corro.get("/chat/rooms", Token::Auth).await.json()), ["crabs", "general"]);
+    assert_eq!(corro.get("/chat/history?room=crabs", Token::Auth).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn direct_history_is_only_readable_by_participants() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    let ferris_info: UserInfo = ferris.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+    let mut gopher = app.client();
+    gopher.register("gopher", "gopher@example.com", "go-pass").await;
+
+    let author = app.state.users.find_by_uuid(&ferris_info.uuid).await.unwrap();
+    app.state.messages.insert(&author, MessageTarget::Direct(&corro_info.uuid), "psst").await.unwrap();
+
+    let messages: Vec<ChatMessage> = corro.get(&format!("/chat/history?with={}", ferris_info.uuid), Token::Auth).await.json();
+    assert_eq!(bodies(&messages), ["psst"]);
+    assert_eq!(messages[0].recipient_uuid.as_deref(), Some(corro_info.uuid.as_str()));
+    // a direct message never shows up in a room
+    let general: Vec<ChatMessage> = corro.get("/chat/history", Token::Auth).await.json();
+    assert!(general.is_empty());
+    let overheard: Vec<ChatMessage> = gopher.get(&format!("/chat/history?with={}", ferris_info.uuid), Token::Auth).await.json();
+    assert!(overheard.is_empty());
+
+    let contacts: Vec<ChatContact> = corro.get("/chat/contacts", Token::Auth).await.json();
+    assert_eq!(contacts, [ChatContact { uuid: ferris_info.uuid, username: String::from("ferris") }]);
+}
+
+#[tokio::test]
+async fn moderation_requires_admin() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let action = ModerationAction::Mute { user: ferris.uuid, seconds: 60 };
+    assert_eq!(client.post_json("/chat/moderation", &action, Token::Auth).await.status, StatusCode::FORBIDDEN);
+    assert_eq!(client.get("/chat/moderation", Token::Auth).await.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admin_mutes_bans_and_deletes() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+    let author = app.state.users.find_by_uuid(&corro_info.uuid).await.unwrap();
+    let general = app.state.rooms.find_by_name("general").await.unwrap();
+    let message = app.state.messages.insert(&author, MessageTarget::Room(&general), "spam").await.unwrap();
+
+    let mute = ModerationAction::Mute { user: corro_info.uuid.clone(), seconds: 600 };
+    assert_eq!(admin.post_json("/chat/moderation", &mute, Token::Auth).await.status, StatusCode::OK);
+    let ban = ModerationAction::Ban { room: String::from("general"), user: corro_info.uuid.clone() };
+    assert_eq!(admin.post_json("/chat/moderation", &ban, Token::Auth).await.status, StatusCode::OK);
+    let overview: ModerationOverview = admin.get("/chat/moderation", Token::Auth).await.json();
+    assert_eq!(overview.mutes.len(), 1);
+    assert_eq!(overview.mutes[0].username, "corro");
+    assert_eq!(overview.bans.len(), 1);
+    assert_eq!(overview.bans[0].room, "general");
+    assert_eq!(corro.get("/chat/history", Token::Auth).await.status, StatusCode::FORBIDDEN);
+
+    let delete = ModerationAction::DeleteMessage { message_id: message.id };
+    assert_eq!(admin.post_json("/chat/moderation", &delete, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(admin.post_json("/chat/moderation", &delete, Token::Auth).await.status, StatusCode::NOT_FOUND);
+    let history: Vec<ChatMessage> = admin.get("/chat/history", Token::Auth).await.json();
+    assert!(history.is_empty());
+
+    // zero seconds lifts the mute, unbanning restores access
+    let unmute = ModerationAction::Mute { user: corro_info.uuid.clone(), seconds: 0 };
+    assert_eq!(admin.post_json("/chat/moderation", &unmute, Token::Auth).await.status, StatusCode::OK);
+    let unban = ModerationAction::Unban { room: String::from("general"), user: corro_info.uuid };
+    assert_eq!(admin.post_json("/chat/moderation", &unban, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(admin.get("/chat/moderation", Token::Auth).await.json::<ModerationOverview>(), ModerationOverview::default());
+    assert_eq!(corro.get("/chat/history", Token::Auth).await.status, StatusCode::OK);
+}
@@ -0,0 +1,88 @@
+use http::StatusCode;
+use server::{cli::{Command, MigrateCommand, UserCommand}, manage::{self, ManageError}, testing::{TestApp, Token}};
+use types::user::RegisterUser;
+
+fn register_user(username: &str, email: &str, pass: &str) -> RegisterUser {
+    RegisterUser {
+        username: username.to_string(),
+        email: email.to_string(),
+        pass: pass.to_string()
+    }
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(Command::parse(Vec::<String>::new()).unwrap(), Command::Serve);
+    assert_eq!(Command::parse(["migrate", "status"]).unwrap(), Command::Migrate(MigrateCommand::Status));
+    assert_eq!(
+        Command::parse(["user", "create", "--admin", "ferris", "ferris@example.com"]).unwrap(),
+        Command::User(UserCommand::Create { username: String::from("ferris"), email: String::from("ferris@example.com"), is_admin: true })
+    );
+    assert_eq!(Command::parse(["user", "list"]).unwrap(), Command::User(UserCommand::List));
+}
+
+#[test]
+fn rejects_invalid_arguments() {
+    assert!(Command::parse(["launch"]).is_err());
+    assert!(Command::parse(["user", "create", "ferris"]).is_err());
+    assert!(Command::parse(["user", "promote", "ferris", "--admin"]).is_err());
+    assert!(Command::parse(["user", "delete", "ferris", "--force"]).is_err());
+}
+
+#[tokio::test]
+async fn created_admin_can_list_users() {
+    let app = TestApp::new().await;
+    let user = manage::create_user(app.state.users.as_ref(), &app.state.config, register_user("admin", "admin@example.com", "admin-pass"), true).await.unwrap();
+    // reserved names are allowed for operator created users
+    assert_eq!(user.username, "admin");
+    assert!(user.is_admin);
+
+    let mut client = app.client();
+    assert_eq!(client.login("admin", "admin-pass").await.status, StatusCode::CREATED);
+    assert_eq!(client.get("/user/all", Token::Auth).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn set_password_replaces_login_password() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    manage::set_password(app.state.users.as_ref(), &app.state.config, "ferris@example.com", String::from("new-crabby-pass")).await.unwrap();
+
+    let mut client = app.client();
+    assert_eq!(client.login("ferris", "crabby-pass").await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(client.login("ferris", "new-crabby-pass").await.status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn promote_keeps_password_and_grants_admin() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    assert!(manage::promote(app.state.users.as_ref(), "Ferris").await.unwrap().is_admin);
+
+    let mut client = app.client();
+    assert_eq!(client.login("ferris", "crabby-pass").await.status, StatusCode::CREATED);
+    assert_eq!(client.get("/user/all", Token::Auth).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn delete_and_list_users() {
+    let app = TestApp::new().await;
+    app.client().register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.client().register("corro", "corro@example.com", "unsafe-pass").await;
+
+    assert_eq!(manage::delete_user(app.state.users.as_ref(), "corro").await.unwrap().username, "corro");
+    let users = manage::list_users(app.state.users.as_ref()).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, "ferris");
+    assert!(matches!(manage::delete_user(app.state.users.as_ref(), "corro").await, Err(ManageError::UserNotFound(_))));
+}
+
+#[tokio::test]
+async fn create_user_validates_input() {
+    let app = TestApp::new().await;
+    let users = app.state.users.as_ref();
+    let config = &app.state.config;
+    assert!(matches!(manage::create_user(users, config, register_user("x", "x@example.com", "pass"), false).await, Err(ManageError::InvalidUsername(_))));
+    assert!(matches!(manage::create_user(users, config, register_user("ferris", "not-an-email", "pass"), false).await, Err(ManageError::InvalidEmail(_))));
+    assert!(matches!(manage::create_user(users, config, register_user("ferris", "ferris@example.com", ""), false).await, Err(ManageError::EmptyPassword)));
+}
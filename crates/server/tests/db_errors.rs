@@ -0,0 +1,87 @@
+use server::db_error::DbError;
+use sqlx::{any::AnyPoolOptions, AnyPool};
+use types::auth::AuthErrorType;
+
+// create in-memory SQLite pool with migrations applied, single connection keeps one database
+async fn sqlite_pool() -> AnyPool {
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:").await
+        .expect("could not open sqlite database");
+    sqlx::migrate!("../../migrations/sqlite")
+        .run(&pool).await
+        .expect("could not run sqlite migrations");
+    pool
+}
+
+async fn insert_user(pool: &AnyPool, uuid: &str, username: &str, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO \"users\" (uuid, username, pass, email, is_admin, username_key)
+        VALUES ($1, $2, $3, $4, $5, $6);")
+        .bind(uuid)
+        .bind(username)
+        .bind("")
+        .bind(email)
+        .bind(false)
+        .bind(username.to_lowercase())
+        .execute(pool).await
+        .map(|_| ())
+}
+
+#[tokio::test]
+async fn duplicate_username_maps_to_username_taken() {
+    let pool = sqlite_pool().await;
+    insert_user(&pool, "uuid-1", "ferris", "ferris@example.com").await.unwrap();
+    let error = insert_user(&pool, "uuid-2", "Ferris", "crab@example.com").await.unwrap_err();
+    let error = DbError::from(error);
+    assert!(matches!(error, DbError::UniqueViolation(Some(ref column)) if column == "username_key"));
+    assert!(matches!(error.auth_error_type(), AuthErrorType::UsernameTaken));
+}
+
+#[tokio::test]
+async fn duplicate_email_maps_to_email_taken() {
+    let pool = sqlite_pool().await;
+    insert_user(&pool, "uuid-1", "ferris", "ferris@example.com").await.unwrap();
+    let error = insert_user(&pool, "uuid-2", "crab", "ferris@example.com").await.unwrap_err();
+    let error = DbError::from(error);
+    assert!(matches!(error, DbError::UniqueViolation(Some(ref column)) if column == "email"));
+    assert!(matches!(error.auth_error_type(), AuthErrorType::EmailTaken));
+}
+
+#[tokio::test]
+async fn foreign_key_violation_is_classified() {
+    let pool = sqlite_pool().await;
+    sqlx::query("CREATE TABLE notes (user_uuid VARCHAR(36) REFERENCES users(uuid));")
+        .execute(&pool).await
+        .unwrap();
+    let error = sqlx::query("INSERT INTO notes (user_uuid) VALUES ($1);")
+        .bind("missing")
+        .execute(&pool).await
+        .unwrap_err();
+    assert!(matches!(DbError::from(error), DbError::ForeignKeyViolation));
+}
+
+#[tokio::test]
+async fn missing_row_maps_to_user_does_not_exist() {
+    let pool = sqlite_pool().await;
+    let error = sqlx::query("SELECT * FROM \"users\" WHERE uuid = $1;")
+        .bind("missing")
+        .fetch_one(&pool).await
+        .err()
+        .expect("expected missing row");
+    let error = DbError::from(error);
+    assert!(matches!(error, DbError::NotFound));
+    assert!(matches!(error.auth_error_type(), AuthErrorType::UserDoesNotExist));
+}
+
+#[tokio::test]
+async fn closed_pool_maps_to_connection_lost() {
+    let pool = sqlite_pool().await;
+    pool.close().await;
+    let error = sqlx::query("SELECT 1;")
+        .execute(&pool).await
+        .unwrap_err();
+    let error = DbError::from(error);
+    assert!(matches!(error, DbError::ConnectionLost));
+    assert!(matches!(error.auth_error_type(), AuthErrorType::ServiceUnavailable));
+}
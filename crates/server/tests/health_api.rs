@@ -0,0 +1,72 @@
+use http::StatusCode;
+use serde_json::Value;
+use server::{migrations, testing::{TestApp, Token}};
+
+#[tokio::test]
+async fn health_responds_ok() {
+    let app = TestApp::new().await;
+    assert_eq!(app.client().get("/health", Token::None).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn ready_reports_every_check() {
+    let app = TestApp::new().await;
+    let response = app.client().get("/ready", Token::None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let readiness: Value = response.json();
+    assert_eq!(readiness["ready"], true);
+    for check in ["database", "migrations", "mail"] {
+        assert_eq!(readiness[check]["ok"], true, "{check}");
+    }
+}
+
+#[tokio::test]
+async fn ready_fails_without_database() {
+    let app = TestApp::new().await;
+    app.state.pool.close().await;
+    let response = app.client().get("/ready", Token::None).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: Value = response.json();
+    assert_eq!(readiness["ready"], false);
+    assert_eq!(readiness["database"]["ok"], false);
+    assert!(readiness["database"]["detail"].is_string());
+    // health only tells the process is up
+    assert_eq!(app.client().get("/health", Token::None).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn ready_fails_with_pending_migrations() {
+    let app = TestApp::new().await;
+    migrations::revert_last(&app.state.pool).await.unwrap();
+    let response = app.client().get("/ready", Token::None).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: Value = response.json();
+    assert_eq!(readiness["database"]["ok"], true);
+    assert_eq!(readiness["migrations"]["ok"], false);
+}
+
+#[tokio::test]
+async fn ready_fails_without_mail_transport() {
+    let app = TestApp::new().await;
+    app.mailer.set_configured(false);
+    let response = app.client().get("/ready", Token::None).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: Value = response.json();
+    assert_eq!(readiness["ready"], false);
+    assert_eq!(readiness["database"]["ok"], true);
+    assert_eq!(readiness["mail"]["ok"], false);
+    assert_eq!(readiness["mail"]["detail"], "SMTP is not configured");
+}
+
+#[tokio::test]
+async fn version_reports_build_info() {
+    let app = TestApp::new().await;
+    let response = app.client().get("/version", Token::None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let build: Value = response.json();
+    assert_eq!(build["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!build["git_hash"].as_str().unwrap().is_empty());
+    let features: Vec<&str> = build["features"].as_array().unwrap().iter().map(|feature| feature.as_str().unwrap()).collect();
+    assert_eq!(features.contains(&"sqlite"), cfg!(feature = "sqlite"));
+    assert_eq!(features.contains(&"postgres"), cfg!(feature = "postgres"));
+}
@@ -0,0 +1,69 @@
+use axum::body::{to_bytes, Body};
+use http::{header::CONTENT_TYPE, Request, StatusCode};
+use server::{monitoring, testing::{TestApp, Token}};
+use tower::ServiceExt;
+use types::auth::AuthErrorType;
+
+#[tokio::test]
+async fn admin_gets_prometheus_text() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    // login is recorded before the scrape
+    admin.login("ferris", "crabby-pass").await;
+
+    let response = admin.get("/metrics", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.headers[CONTENT_TYPE].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+    let text = response.text();
+    assert!(text.contains(&format!("# TYPE {} counter", monitoring::HTTP_REQUESTS_TOTAL)));
+    assert!(text.contains(&format!("# TYPE {} histogram", monitoring::HTTP_REQUEST_DURATION_SECONDS)));
+    assert!(text.contains(&format!("{}{{result=\"success\"}}", monitoring::LOGIN_ATTEMPTS_TOTAL)));
+    assert!(text.contains(monitoring::DB_POOL_CONNECTIONS));
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_template() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let body = serde_json::json!({ "email_address": "ferris@example.com", "pass": "new-crabby-pass" });
+    let response = app.client().post_json("/auth/reset/not-a-real-reset-key", &body, Token::None).await;
+    assert_ne!(response.status, StatusCode::NOT_FOUND);
+
+    let text = admin.get("/metrics", Token::Auth).await.text();
+    assert!(text.contains("route=\"/auth/reset/:reset_key\""));
+    assert!(!text.contains("not-a-real-reset-key"));
+}
+
+#[tokio::test]
+async fn non_admin_cannot_read_metrics() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("corro", "corro@example.com", "unsafe-pass").await;
+
+    let response = client.get("/metrics", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::AccessDenied)));
+    // the requester token is not enough either
+    assert_ne!(client.get("/metrics", Token::Requester).await.status, StatusCode::OK);
+    assert_ne!(client.get("/metrics", Token::None).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn separate_bind_address_moves_metrics_off_the_app() {
+    let app = TestApp::with_config(|config| config.server.metrics_addr = Some("127.0.0.1:0".parse().unwrap())).await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    assert_eq!(admin.get("/metrics", Token::Auth).await.status, StatusCode::NOT_FOUND);
+
+    // the separate listener needs no token
+    let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+    let response = monitoring::standalone_router(app.state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8(body.to_vec()).unwrap().contains(monitoring::DB_POOL_CONNECTIONS));
+}
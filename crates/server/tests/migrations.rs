@@ -0,0 +1,52 @@
+use server::{migrations, testing::TestApp};
+use sqlx::any::AnyKind;
+
+#[tokio::test]
+async fn test_app_database_is_fully_migrated() {
+    let app = TestApp::new().await;
+    let mut connection = app.state.pool.acquire().await.unwrap();
+    let status = migrations::status(&mut connection).await.unwrap();
+    assert!(status.is_up_to_date());
+    assert_eq!(status.applied.len(), migrations::SQLITE_MIGRATOR.iter().filter(|m| !m.migration_type.is_down_migration()).count());
+}
+
+#[tokio::test]
+async fn revert_last_undoes_latest_migration_and_run_reapplies_it() {
+    let app = TestApp::new().await;
+    let pool = &app.state.pool;
+    let latest = {
+        let mut connection = pool.acquire().await.unwrap();
+        *migrations::status(&mut connection).await.unwrap().applied.iter().max().unwrap()
+    };
+
+    assert_eq!(migrations::revert_last(pool).await.unwrap(), Some(latest));
+    let mut connection = pool.acquire().await.unwrap();
+    let status = migrations::status(&mut connection).await.unwrap();
+    assert_eq!(status.pending, [latest]);
+    drop(connection);
+
+    migrations::run(pool).await.unwrap();
+    let mut connection = pool.acquire().await.unwrap();
+    assert!(migrations::status(&mut connection).await.unwrap().is_up_to_date());
+}
+
+#[tokio::test]
+async fn every_migration_has_a_description() {
+    for migration in migrations::SQLITE_MIGRATOR.iter() {
+        assert!(migrations::description(AnyKind::Sqlite, migration.version).is_some());
+    }
+}
+
+#[tokio::test]
+async fn run_backfills_username_keys_sqlite_cannot_fold() {
+    let app = TestApp::new().await;
+    let pool = &app.state.pool;
+    app.client().register("Ärger", "aerger@example.com", "crabby-pass").await;
+    app.client().register("Ferris", "ferris@example.com", "crabby-pass").await;
+    // what the NormalizedUsernames migration leaves behind on sqlite
+    sqlx::query("UPDATE users SET username_key = LOWER(TRIM(username));").execute(pool).await.unwrap();
+
+    migrations::run(pool).await.unwrap();
+    let keys: Vec<String> = sqlx::query_scalar("SELECT username_key FROM users ORDER BY id;").fetch_all(pool).await.unwrap();
+    assert_eq!(keys, ["ärger", "ferris"]);
+}
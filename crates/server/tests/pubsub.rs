@@ -0,0 +1,26 @@
+use server::pubsub::{ChatEvent, NotificationRelay};
+use tokio::sync::broadcast;
+
+#[test]
+fn lost_listener_connection_raises_lost_event() {
+    let (tx, mut rx) = broadcast::channel(16);
+    let mut relay = NotificationRelay::new(tx);
+
+    // try_recv returns no notification when the connection dropped, it reconnects on the next call
+    assert!(relay.relay(Ok(None)));
+    assert_eq!(rx.try_recv().unwrap(), ChatEvent::Lost);
+    // the listener keeps going after malformed payloads and lost connections
+    assert!(relay.relay(Ok(Some("not a part"))));
+    assert!(rx.try_recv().is_err());
+    assert!(relay.relay(Ok(None)));
+    assert_eq!(rx.try_recv().unwrap(), ChatEvent::Lost);
+}
+
+#[test]
+fn listener_errors_stop_the_relay() {
+    let (tx, mut rx) = broadcast::channel(16);
+    let mut relay = NotificationRelay::new(tx);
+
+    assert!(!relay.relay(Err(&sqlx::Error::PoolClosed)));
+    assert!(rx.try_recv().is_err());
+}
@@ -0,0 +1,77 @@
+use std::{io, sync::{Arc, Mutex}};
+
+use http::StatusCode;
+use serde_json::json;
+use server::testing::{TestApp, Token};
+use tracing_subscriber::{fmt::MakeWriter, EnvFilter};
+
+// log output kept in memory
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = CapturedLogs;
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn secrets_never_reach_the_logs() {
+    let logs = CapturedLogs::default();
+    // everything at every level, with the fields of the spans around each event
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new("trace"))
+        .with_writer(logs.clone())
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    assert_eq!(client.register("ferris", "ferris@example.com", "crabby-pass").await.status, StatusCode::CREATED);
+    assert_eq!(client.post_text("/auth/reset", "ferris@example.com", Token::None).await.status, StatusCode::CREATED);
+    let reset_key = app.mailer.last().expect("reset email was sent").html
+        .split("key=").nth(1)
+        .and_then(|rest| rest.split('&').next())
+        .expect("reset link contains key")
+        .to_string();
+    let body = json!({"email_address": "ferris@example.com", "pass": "new-crabby-pass"});
+    let response = client.post_json(&format!("/auth/reset/{}", reset_key), &body, Token::None).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED);
+    // a failed and a successful login
+    assert_eq!(client.login("ferris", "crabby-pass").await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(client.login("ferris", "new-crabby-pass").await.status, StatusCode::CREATED);
+    let requester_token = client.requester_token().expect("login sets a token").to_string();
+    let auth_token = client.auth_token().await.expect("auth token is issued");
+    assert_eq!(client.get("/auth/test", Token::Auth).await.status, StatusCode::OK);
+
+    let output = logs.text();
+    // the requests did get logged, by route template
+    assert!(output.contains("/auth/reset/:reset_key"));
+    assert!(output.contains("/auth/login"));
+    for secret in ["crabby-pass", "new-crabby-pass", reset_key.as_str(), requester_token.as_str(), auth_token.as_str()] {
+        assert!(!output.contains(secret), "{secret} found in logs");
+    }
+    // neither does the password hash
+    let hash: String = sqlx::query_scalar("SELECT pass FROM users;").fetch_one(&app.state.pool).await.unwrap();
+    assert!(!logs.text().contains(&hash));
+}
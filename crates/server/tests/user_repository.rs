@@ -0,0 +1,133 @@
+use axum::body::Body;
+use http::{header::AUTHORIZATION, Request, StatusCode};
+use server::{db_error::DbError, erasure, strategies::{messages::MessageTarget, users::{SqliteUserRepository, UserRepository, UserRepositoryError}}, testing::TestApp};
+use types::{auth::AuthToken, user::{RegisterUser, UserListQuery}};
+
+const SALT: [u8; 16] = *b"TESTSALTTESTSALT";
+
+fn register_user(username: &str, email: &str) -> RegisterUser {
+    RegisterUser {
+        username: username.to_string(),
+        email: email.to_string(),
+        pass: String::from("crabby-pass")
+    }
+}
+
+#[tokio::test]
+async fn missing_user_is_not_found() {
+    let app = TestApp::new().await;
+    let users = SqliteUserRepository::new(app.state.pool.clone());
+    assert!(matches!(users.find_by_uuid("missing").await, Err(UserRepositoryError::NotFound)));
+    assert!(matches!(users.find_by_username_or_email("nobody").await, Err(UserRepositoryError::NotFound)));
+    assert!(matches!(users.set_admin("missing", true).await, Err(UserRepositoryError::NotFound)));
+}
+
+#[tokio::test]
+async fn delete_returns_rows_affected() {
+    // deletes are soft, a deleted user counts as missing for a second delete
+    let app = TestApp::new().await;
+    let users = SqliteUserRepository::new(app.state.pool.clone());
+    let user = users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    assert_eq!(users.delete(&user.uuid).await.unwrap(), 1);
+    assert_eq!(users.delete(&user.uuid).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn duplicate_username_is_a_unique_violation() {
+    let app = TestApp::new().await;
+    let users = SqliteUserRepository::new(app.state.pool.clone());
+    users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    let result = users.insert(register_user("Ferris", "other@example.com"), SALT).await;
+    assert!(matches!(result, Err(UserRepositoryError::Database(DbError::UniqueViolation(_)))));
+}
+
+#[tokio::test]
+async fn update_is_visible_to_other_connections() {
+    let app = TestApp::new().await;
+    let users = SqliteUserRepository::new(app.state.pool.clone());
+    let mut user = users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    user.username = String::from("corro");
+    user.pass = String::from("new-pass");
+    let updated = users.update(user.clone(), SALT).await.unwrap();
+    assert_eq!(updated.username, "corro");
+    assert_eq!(users.find_by_uuid(&user.uuid).await.unwrap().username, "corro");
+}
+
+#[tokio::test]
+async fn database_failure_is_not_reported_as_missing_user() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let token = admin.auth_token().await.unwrap();
+    app.state.pool.close().await;
+
+    let users = SqliteUserRepository::new(app.state.pool.clone());
+    assert!(matches!(users.list().await, Err(UserRepositoryError::Database(_))));
+    // the admin token is still valid, listing fails on the database instead
+    let request = Request::builder()
+        .uri("/user/all")
+        .header(AUTHORIZATION, AuthToken::new(token).to_string())
+        .body(Body::empty())
+        .unwrap();
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn erasure_anonymizes_users_after_grace_period() {
+    let app = TestApp::with_config(|config| config.accounts.deletion_grace_period = 0).await;
+    let users = app.state.users.as_ref();
+    let ferris = users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    let corro = users.insert(register_user("corro", "corro@example.com"), SALT).await.unwrap();
+    users.delete(&ferris.uuid).await.unwrap();
+
+    assert_eq!(erasure::erase_expired(users, &app.state.config.accounts).await.unwrap(), 1);
+    // erased users cannot be restored or listed and their name and email are free again
+    assert_eq!(users.restore(&ferris.uuid).await.unwrap(), 0);
+    let deleted = users.page(&UserListQuery { is_deleted: Some(true), ..Default::default() }).await.unwrap();
+    assert_eq!(deleted.total, 0);
+    users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    assert_eq!(users.find_by_uuid(&corro.uuid).await.unwrap().username, "corro");
+    assert_eq!(erasure::erase_expired(users, &app.state.config.accounts).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn erasure_removes_chat_data() {
+    let app = TestApp::with_config(|config| config.accounts.deletion_grace_period = 0).await;
+    let state = &app.state;
+    let ferris = state.users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    let corro = state.users.insert(register_user("corro", "corro@example.com"), SALT).await.unwrap();
+    let room = state.rooms.create("crabs", true, &ferris.uuid).await.unwrap();
+    state.rooms.add_member(room.id, &corro.uuid).await.unwrap();
+    let message = state.messages.insert(&ferris, MessageTarget::Room(&room), "hello crabs").await.unwrap();
+    state.messages.insert(&ferris, MessageTarget::Direct(&corro.uuid), "hello corro").await.unwrap();
+    state.messages.insert(&corro, MessageTarget::Direct(&ferris.uuid), "hello ferris").await.unwrap();
+    let corro_message = state.messages.insert(&corro, MessageTarget::Room(&room), "hello ferris").await.unwrap();
+    state.rooms.mark_read(room.id, &ferris.uuid, message.id).await.unwrap();
+    state.rooms.ban(room.id, &ferris.uuid, &corro.uuid).await.unwrap();
+    state.mutes.mute(&ferris.uuid, i64::MAX, &corro.uuid).await.unwrap();
+    state.users.delete(&ferris.uuid).await.unwrap();
+
+    assert_eq!(erasure::erase_expired(state.users.as_ref(), &state.config.accounts).await.unwrap(), 1);
+    assert!(state.messages.by_author(&ferris.uuid).await.unwrap().is_empty());
+    // direct messages they received go as well, the room and messages of others stay
+    assert!(state.messages.contacts(&corro.uuid).await.unwrap().is_empty());
+    let history = state.messages.history(room.id, None, 10).await.unwrap();
+    assert_eq!(history.iter().map(|message| message.id).collect::<Vec<_>>(), [corro_message.id]);
+    assert!(!state.rooms.is_member(room.id, &ferris.uuid).await.unwrap());
+    assert!(state.rooms.is_member(room.id, &corro.uuid).await.unwrap());
+    assert!(state.rooms.read_markers_of(&ferris.uuid).await.unwrap().is_empty());
+    assert!(state.rooms.bans().await.unwrap().is_empty());
+    assert!(state.mutes.active(0).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn erasure_waits_for_grace_period() {
+    let app = TestApp::new().await;
+    let users = app.state.users.as_ref();
+    let ferris = users.insert(register_user("ferris", "ferris@example.com"), SALT).await.unwrap();
+    users.delete(&ferris.uuid).await.unwrap();
+    assert_eq!(erasure::erase_expired(users, &app.state.config.accounts).await.unwrap(), 0);
+    assert_eq!(users.restore(&ferris.uuid).await.unwrap(), 1);
+}
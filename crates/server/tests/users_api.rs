@@ -0,0 +1,251 @@
+use http::{header::CONTENT_DISPOSITION, StatusCode};
+use server::{strategies::messages::MessageTarget, testing::{TestApp, Token}};
+use types::{auth::AuthErrorType, chat::{ChatMessage, ChatMute, Room, RoomBan}, export::UserExport, page::Page, user::{DeleteAccount, UserInfo}};
+
+#[tokio::test]
+async fn user_info_returns_current_user() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let response = client.get("/user/info", Token::Requester).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let user: UserInfo = response.json();
+    assert_eq!(user.username, "ferris");
+}
+
+#[tokio::test]
+async fn user_info_requires_token() {
+    let app = TestApp::new().await;
+    let response = app.client().get("/user/info", Token::None).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn all_users_denied_for_non_admin() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let response = client.get("/user/all", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::AccessDenied)));
+}
+
+#[tokio::test]
+async fn all_users_listed_for_admin() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.client().register("corro", "corro@example.com", "unsafe-pass").await;
+    app.promote("ferris").await;
+    let response = admin.get("/user/all", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let users: Page<UserInfo> = response.json();
+    assert_eq!(users.total, 2);
+    assert_eq!(usernames(&users), ["ferris", "corro"]);
+}
+
+fn usernames(page: &Page<UserInfo>) -> Vec<&str> {
+    page.items.iter().map(|user| user.username.as_str()).collect()
+}
+
+#[tokio::test]
+async fn all_users_are_paged_with_total() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    for name in ["corro", "gopher", "duke"] {
+        app.client().register(name, &format!("{}@example.com", name), "other-pass").await;
+    }
+
+    let first: Page<UserInfo> = admin.get("/user/all?limit=3", Token::Auth).await.json();
+    assert_eq!((first.page, first.limit, first.total), (1, 3, 4));
+    assert_eq!(usernames(&first), ["ferris", "corro", "gopher"]);
+    assert!(first.has_next());
+
+    let second: Page<UserInfo> = admin.get("/user/all?limit=3&page=2", Token::Auth).await.json();
+    assert_eq!(usernames(&second), ["duke"]);
+    assert!(!second.has_next());
+}
+
+#[tokio::test]
+async fn all_users_search_filter_and_sort() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@rust.example", "crabby-pass").await;
+    app.promote("ferris").await;
+    app.client().register("corro", "corro@rust.example", "unsafe-pass").await;
+    app.client().register("gopher", "gopher@go.example", "other-pass").await;
+
+    // search matches username or email ignoring case, wildcards are literal
+    let page: Page<UserInfo> = admin.get("/user/all?search=RUST&sort=username", Token::Auth).await.json();
+    assert_eq!(usernames(&page), ["corro", "ferris"]);
+    let page: Page<UserInfo> = admin.get("/user/all?search=%25", Token::Auth).await.json();
+    assert_eq!(page.total, 0);
+
+    let page: Page<UserInfo> = admin.get("/user/all?is_admin=false&sort=username&order=desc", Token::Auth).await.json();
+    assert_eq!(usernames(&page), ["gopher", "corro"]);
+    let page: Page<UserInfo> = admin.get("/user/all?is_disabled=true", Token::Auth).await.json();
+    assert_eq!(page.total, 0);
+    let page: Page<UserInfo> = admin.get("/user/all?is_verified=false", Token::Auth).await.json();
+    assert_eq!(page.total, 3);
+}
+
+#[tokio::test]
+async fn all_users_rejects_invalid_paging() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    assert_eq!(admin.get("/user/all?page=0", Token::Auth).await.status, StatusCode::BAD_REQUEST);
+    assert_eq!(admin.get("/user/all?limit=1000", Token::Auth).await.status, StatusCode::BAD_REQUEST);
+    assert_eq!(admin.get("/user/all?sort=password", Token::Auth).await.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn requester_token_is_not_accepted_for_admin_routes() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let response = admin.get("/user/all", Token::Requester).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admin_can_delete_user() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let corro: UserInfo = app.client().register("corro", "corro@example.com", "unsafe-pass").await.json();
+
+    let response = admin.delete_text("/user", &corro.uuid, Token::Auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let users: Page<UserInfo> = admin.get("/user/all", Token::Auth).await.json();
+    assert!(users.items.iter().all(|user| user.uuid != corro.uuid));
+
+    // deleting a missing user reports it
+    let response = admin.delete_text("/user", &corro.uuid, Token::Auth).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn non_admin_cannot_delete_user() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let response = client.delete_text("/user", &ferris.uuid, Token::Auth).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(client.get("/user/info", Token::Requester).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleted_user_is_rejected_until_restored() {
+    let app = TestApp::new().await;
+    let mut admin = app.client();
+    admin.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+
+    assert_eq!(admin.delete_text("/user", &corro_info.uuid, Token::Auth).await.status, StatusCode::OK);
+    // the issued requester token can no longer be exchanged and login fails
+    let response = corro.auth_token().await.unwrap_err();
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::UserDoesNotExist)));
+    assert_eq!(app.client().login("corro", "unsafe-pass").await.status, StatusCode::NOT_FOUND);
+    // deleted users are only listed when asked for
+    let deleted: Page<UserInfo> = admin.get("/user/all?is_deleted=true", Token::Auth).await.json();
+    assert_eq!(usernames(&deleted), ["corro"]);
+    assert!(deleted.items[0].deleted_at.is_some());
+
+    assert_eq!(admin.post_text("/user/restore", &corro_info.uuid, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(app.client().login("corro", "unsafe-pass").await.status, StatusCode::CREATED);
+    assert!(corro.auth_token().await.is_ok());
+    // restoring an active user reports it as missing
+    assert_eq!(admin.post_text("/user/restore", &corro_info.uuid, Token::Auth).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn non_admin_cannot_restore_user() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let response = client.post_text("/user/restore", &ferris.uuid, Token::Auth).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn user_can_delete_own_account_with_password() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+
+    let wrong = DeleteAccount { pass: String::from("wrong-pass") };
+    let response = client.delete_json("/user/me", &wrong, Token::Auth).await;
+    assert!(matches!(response.problem().error_type, Some(AuthErrorType::WrongCredentials)));
+    assert_eq!(client.get("/user/info", Token::Requester).await.status, StatusCode::OK);
+
+    let confirm = DeleteAccount { pass: String::from("crabby-pass") };
+    assert_eq!(client.delete_json("/user/me", &confirm, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(app.client().login("ferris", "crabby-pass").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn user_can_export_own_data() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+
+    let response = client.get("/user/me/export", Token::Auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.headers[CONTENT_DISPOSITION].to_str().unwrap().starts_with("attachment"));
+    let export: UserExport = response.json();
+    let account: UserInfo = serde_json::from_value(export.sections["account"].clone()).unwrap();
+    assert_eq!(account, ferris);
+    // the password hash is never part of an export
+    assert!(!response.text().contains("pass"));
+}
+
+#[tokio::test]
+async fn export_contains_chat_data() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let corro: UserInfo = app.client().register("corro", "corro@example.com", "crabby-pass").await.json();
+    let state = &app.state;
+    let corro = state.users.find_by_uuid(&corro.uuid).await.unwrap();
+    let room = state.rooms.create("crabs", true, &ferris.uuid).await.unwrap();
+    let banned_from = state.rooms.create("lobsters", false, &corro.uuid).await.unwrap();
+    state.rooms.ban(banned_from.id, &ferris.uuid, &corro.uuid).await.unwrap();
+    state.mutes.mute(&ferris.uuid, 4_102_444_800, &corro.uuid).await.unwrap();
+    let message = state.messages.insert(&corro, MessageTarget::Direct(&ferris.uuid), "hello ferris").await.unwrap();
+
+    let export: UserExport = client.get("/user/me/export", Token::Auth).await.json();
+    let section = |name: &str| export.sections[name].clone();
+    assert_eq!(serde_json::from_value::<Vec<ChatMessage>>(section("received_messages")).unwrap(), [message]);
+    let rooms = vec![room];
+    assert_eq!(serde_json::from_value::<Vec<Room>>(section("memberships")).unwrap(), rooms);
+    assert_eq!(serde_json::from_value::<Vec<Room>>(section("owned_rooms")).unwrap(), rooms);
+    let mute: ChatMute = serde_json::from_value(section("mute")).unwrap();
+    assert_eq!(mute.muted_until, 4_102_444_800);
+    let bans: Vec<RoomBan> = serde_json::from_value(section("bans")).unwrap();
+    assert_eq!(bans.iter().map(|ban| ban.room.as_str()).collect::<Vec<_>>(), ["lobsters"]);
+}
+
+#[tokio::test]
+async fn exporters_cover_every_table_with_user_data() {
+    let app = TestApp::new().await;
+    // tables with a column pointing at a user, what a moderator did to others is not the user's data
+    let tables = sqlx::query_as::<_, (String,)>(
+        "SELECT DISTINCT m.name FROM sqlite_master m JOIN pragma_table_info(m.name) p
+        WHERE m.type = 'table' AND p.name IN ('uuid', 'user_uuid', 'author_uuid', 'recipient_uuid', 'created_by')
+        ORDER BY m.name;")
+        .fetch_all(&app.state.pool).await.unwrap();
+    let covered = app.state.exporters.tables();
+    for (table,) in &tables {
+        assert!(covered.contains(&table.as_str()), "no exporter for the {} table", table);
+    }
+    assert!(tables.len() >= 7);
+}
@@ -0,0 +1,768 @@
+use std::{net::SocketAddr, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use futures::{SinkExt, StreamExt};
+use http::StatusCode;
+use server::{pubsub::{ChatEvent, MemoryPubSub, PubSub}, strategies::{authentication::{AuthRequesterClaims, Claims}, messages::MessageTarget}, testing::{TestApp, Token}};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use types::{chat::{ChatContact, ChatErrorType, ModerationAction, ReadMarker, RoomResume, UnreadCount, ClientMessage, ServerMessage, CHAT_PROTOCOL_VERSION, DEFAULT_ROOM}, user::UserInfo};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(addr: SocketAddr) -> Socket {
+    let (socket, _) = connect_async(format!("ws://{}/ws", addr)).await
+        .expect("could not open websocket");
+    socket
+}
+
+async fn send(socket: &mut Socket, message: &ClientMessage) {
+    socket.send(Message::Text(serde_json::to_string(message).unwrap())).await.unwrap();
+}
+
+fn auth(token: &str) -> ClientMessage {
+    ClientMessage::Auth { version: CHAT_PROTOCOL_VERSION, token: token.to_string(), resume: Vec::new() }
+}
+
+// message to the default room
+fn chat(id: u64, body: &str) -> ClientMessage {
+    ClientMessage::Chat { id, room: DEFAULT_ROOM.to_string(), body: body.to_string() }
+}
+
+// next server frame, None if the socket closed or nothing arrived in time
+async fn next_frame(socket: &mut Socket) -> Option<ServerMessage> {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), socket.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => return Some(serde_json::from_str(&text).expect("server sent invalid frame")),
+            Ok(Some(Ok(Message::Ping(_) | Message::Pong(_)))) => continue,
+            _ => return None
+        }
+    }
+}
+
+// next frame that is not a presence change, those race with room frames of the same connection
+async fn next_message(socket: &mut Socket) -> Option<ServerMessage> {
+    loop {
+        match next_frame(socket).await {
+            Some(ServerMessage::Presence { .. }) => continue,
+            message => return message
+        }
+    }
+}
+
+// next presence change as username and online state
+async fn next_presence(socket: &mut Socket) -> Option<(String, bool)> {
+    loop {
+        match next_frame(socket).await {
+            Some(ServerMessage::Presence { user, online }) => return Some((user.username, online)),
+            Some(_) => continue,
+            None => return None
+        }
+    }
+}
+
+// next frame that is not an ack, the ack of an own message can arrive before or after its broadcast
+async fn next_broadcast(socket: &mut Socket) -> Option<ServerMessage> {
+    loop {
+        match next_message(socket).await {
+            Some(ServerMessage::Ack { .. }) => continue,
+            message => return message
+        }
+    }
+}
+
+// chat frame rendered as author and body
+fn line(message: Option<ServerMessage>) -> Option<String> {
+    match message {
+        Some(ServerMessage::Chat { message }) => Some(message.to_string()),
+        _ => None
+    }
+}
+
+fn joined(username: &str) -> Option<ServerMessage> {
+    joined_room(DEFAULT_ROOM, username)
+}
+
+fn joined_room(room: &str, username: &str) -> Option<ServerMessage> {
+    Some(ServerMessage::Join { room: room.to_string(), username: username.to_string() })
+}
+
+// authenticate and read the auth ack, returns the session expiry
+async fn join(socket: &mut Socket, token: &str) -> u64 {
+    send(socket, &auth(token)).await;
+    assert_eq!(next_message(socket).await, Some(ServerMessage::Ack { id: None, message_id: None }));
+    match next_message(socket).await {
+        Some(ServerMessage::Session { expires_at }) => expires_at,
+        frame => panic!("expected session frame, got {:?}", frame)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+// requester token of the user expiring at the given unix timestamp
+async fn requester_token(app: &TestApp, uuid: &str, expires_at: u64) -> String {
+    let mut claims = AuthRequesterClaims::new(&app.state, uuid.to_string()).await.unwrap();
+    claims.exp = expires_at;
+    claims.generate_token(&app.state).unwrap().access_token
+}
+
+#[tokio::test]
+async fn handshake_with_requester_token_joins_chat() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    join(&mut socket, client.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+
+    send(&mut socket, &chat(1, "hello")).await;
+    let mut frames = [next_message(&mut socket).await.unwrap(), next_message(&mut socket).await.unwrap()];
+    frames.sort_by_key(|frame| matches!(frame, ServerMessage::Chat { .. }));
+    let message_id = match &frames[1] {
+        ServerMessage::Chat { message } => {
+            assert_eq!(message.to_string(), "ferris: hello");
+            message.id
+        },
+        frame => panic!("expected chat frame, got {:?}", frame)
+    };
+    assert_eq!(frames[0], ServerMessage::Ack { id: Some(1), message_id: Some(message_id) });
+}
+
+#[tokio::test]
+async fn handshake_with_invalid_token_reports_error() {
+    let app = TestApp::new().await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    send(&mut socket, &auth("not-a-token")).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::InvalidToken)));
+    assert_eq!(next_message(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn handshake_rejects_other_protocol_versions_and_frames() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    let token = client.requester_token().unwrap().to_string();
+    send(&mut socket, &ClientMessage::Auth { version: CHAT_PROTOCOL_VERSION + 1, token, resume: Vec::new() }).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::UnsupportedVersion)));
+    assert_eq!(next_message(&mut socket).await, None);
+
+    // a raw token was the old handshake
+    let mut socket = connect(addr).await;
+    socket.send(Message::Text(client.requester_token().unwrap().to_string())).await.unwrap();
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::InvalidFrame)));
+}
+
+#[tokio::test]
+async fn handshake_times_out_without_auth_frame() {
+    let app = TestApp::with_config(|config| config.chat.handshake_timeout = 1).await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::HandshakeTimeout)));
+    assert_eq!(next_message(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn invalid_frames_after_handshake_are_answered_with_errors() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    join(&mut socket, client.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+
+    send(&mut socket, &chat(1, "  ")).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::EmptyMessage)));
+    socket.send(Message::Text(String::from("not json"))).await.unwrap();
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::InvalidFrame)));
+    // the connection stays usable
+    send(&mut socket, &ClientMessage::Ping).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Pong));
+}
+
+#[tokio::test]
+async fn messages_are_broadcast_to_other_users() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+
+    send(&mut corro_socket, &chat(1, "hi ferris")).await;
+    assert_eq!(line(next_message(&mut ferris_socket).await).as_deref(), Some("corro: hi ferris"));
+}
+
+#[tokio::test]
+async fn handshake_rejects_deleted_user() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    let ferris: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    app.state.users.delete(&ferris.uuid).await.unwrap();
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    send(&mut socket, &auth(client.requester_token().unwrap())).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::InvalidToken)));
+    assert_eq!(next_message(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn late_joiner_receives_chat_history() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    send(&mut ferris_socket, &chat(1, "anyone here?")).await;
+    assert_eq!(line(next_broadcast(&mut ferris_socket).await).as_deref(), Some("ferris: anyone here?"));
+
+    // stored lines are replayed before the join announcement
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(line(next_message(&mut corro_socket).await).as_deref(), Some("ferris: anyone here?"));
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+}
+
+#[tokio::test]
+async fn rooms_only_reach_their_members() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+
+    let room = String::from("rust");
+    let owner = app.state.users.find_by_username_or_email("ferris").await.unwrap();
+    app.state.rooms.create(&room, false, &owner.uuid).await.unwrap();
+    send(&mut ferris_socket, &ClientMessage::Join { room: room.clone() }).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined_room("rust", "ferris"));
+
+    // corro has not joined the room, so cannot post in it and does not see its messages
+    send(&mut corro_socket, &ClientMessage::Chat { id: 1, room: room.clone(), body: String::from("let me in") }).await;
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::NotInRoom)));
+    send(&mut ferris_socket, &ClientMessage::Chat { id: 1, room: room.clone(), body: String::from("crabs only") }).await;
+    assert_eq!(line(next_broadcast(&mut ferris_socket).await).as_deref(), Some("ferris: crabs only"));
+    send(&mut corro_socket, &ClientMessage::Ping).await;
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::Pong));
+
+    // private rooms of others look like missing rooms
+    app.state.rooms.create("secret", true, &owner.uuid).await.unwrap();
+    send(&mut corro_socket, &ClientMessage::Join { room: String::from("secret") }).await;
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::RoomNotFound)));
+}
+
+#[tokio::test]
+async fn direct_messages_reach_only_the_recipient() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+    let mut gopher = app.client();
+    gopher.register("gopher", "gopher@example.com", "go-pass").await;
+    let addr = app.spawn().await;
+
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+    let mut gopher_socket = connect(addr).await;
+    join(&mut gopher_socket, gopher.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut gopher_socket).await, joined("gopher"));
+    assert_eq!(next_message(&mut corro_socket).await, joined("gopher"));
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    assert_eq!(next_message(&mut corro_socket).await, joined("ferris"));
+    assert_eq!(next_message(&mut gopher_socket).await, joined("ferris"));
+
+    send(&mut ferris_socket, &ClientMessage::Direct { id: 1, to: corro_info.uuid.clone(), body: String::from("psst") }).await;
+    match next_message(&mut corro_socket).await {
+        Some(ServerMessage::Chat { message }) => {
+            assert_eq!(message.to_string(), "ferris: psst");
+            assert_eq!(message.room, None);
+            assert_eq!(message.recipient_uuid, Some(corro_info.uuid.clone()));
+        },
+        frame => panic!("expected direct message, got {:?}", frame)
+    }
+    // the sender gets a copy for their other tabs
+    assert_eq!(line(next_broadcast(&mut ferris_socket).await).as_deref(), Some("ferris: psst"));
+    send(&mut gopher_socket, &ClientMessage::Ping).await;
+    assert_eq!(next_message(&mut gopher_socket).await, Some(ServerMessage::Pong));
+
+    send(&mut ferris_socket, &ClientMessage::Direct { id: 2, to: String::from("nobody"), body: String::from("hello?") }).await;
+    assert_eq!(next_broadcast(&mut ferris_socket).await, Some(ServerMessage::error(ChatErrorType::UserNotFound)));
+}
+
+#[tokio::test]
+async fn instances_sharing_a_pubsub_share_rooms_and_presence() {
+    let pubsub: Arc<dyn PubSub> = Arc::new(MemoryPubSub::default());
+    let first = TestApp::with_pubsub(pubsub.clone()).await;
+    let second = TestApp::with_pubsub(pubsub).await;
+    let mut ferris = first.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = second.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let first_addr = first.spawn().await;
+    let second_addr = second.spawn().await;
+
+    let mut ferris_socket = connect(first_addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("ferris"), true)));
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(second_addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), true)));
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    assert_eq!(online.iter().map(|user| user.username.as_str()).collect::<Vec<_>>(), vec!["corro", "ferris"]);
+
+    send(&mut corro_socket, &chat(1, "hello from the other instance")).await;
+    assert_eq!(line(next_broadcast(&mut ferris_socket).await).as_deref(), Some("corro: hello from the other instance"));
+
+    corro_socket.close(None).await.unwrap();
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), false)));
+}
+
+#[tokio::test]
+async fn silent_instances_take_their_users_offline() {
+    let pubsub: Arc<dyn PubSub> = Arc::new(MemoryPubSub::default());
+    let app = TestApp::with_pubsub_and_config(pubsub.clone(), |config| config.chat.presence_interval = 1).await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("ferris"), true)));
+
+    // an instance that announces a user and then crashes
+    let corro = ChatContact { uuid: String::from("corro-uuid"), username: String::from("corro") };
+    pubsub.publish(&ChatEvent::Presence { instance: String::from("crashed"), user: corro, online: true }).await.unwrap();
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), true)));
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), false)));
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    assert_eq!(online.len(), 1);
+}
+
+#[tokio::test]
+async fn lost_events_resync_connections_and_presence() {
+    let pubsub: Arc<dyn PubSub> = Arc::new(MemoryPubSub::default());
+    let app = TestApp::with_pubsub(pubsub.clone()).await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    join(&mut socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+
+    let mut events = pubsub.subscribe();
+    pubsub.publish(&ChatEvent::Lost).await.unwrap();
+    // the other instances are asked for their snapshots again
+    let requested = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(ChatEvent::SnapshotRequest { .. }) = events.recv().await {
+                break;
+            }
+        }
+    }).await;
+    assert!(requested.is_ok());
+    send(&mut socket, &ClientMessage::Ping).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Pong));
+    assert!(matches!(next_message(&mut socket).await, Some(ServerMessage::Resync { room: None, .. })));
+}
+
+// frames up to and including the next chat line, room frames sent before it are all among them
+async fn frames_until_chat(socket: &mut Socket) -> Vec<ServerMessage> {
+    let mut frames = Vec::new();
+    loop {
+        match next_frame(socket).await {
+            Some(frame @ ServerMessage::Chat { .. }) => {
+                frames.push(frame);
+                return frames;
+            },
+            Some(frame) => frames.push(frame),
+            None => panic!("socket closed")
+        }
+    }
+}
+
+#[tokio::test]
+async fn presence_counts_every_connection_of_a_user() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("ferris"), true)));
+
+    let mut first_tab = connect(addr).await;
+    join(&mut first_tab, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut first_tab).await, joined("corro"));
+    let mut second_tab = connect(addr).await;
+    join(&mut second_tab, corro.requester_token().unwrap()).await;
+
+    // the second tab joins the room without announcing corro again
+    send(&mut ferris_socket, &chat(1, "hello corro")).await;
+    assert_eq!(line(next_broadcast(&mut second_tab).await).as_deref(), Some("ferris: hello corro"));
+    let frames = frames_until_chat(&mut ferris_socket).await;
+    assert_eq!(frames.iter().filter(|frame| Some(*frame) == joined("corro").as_ref()).count(), 1);
+    assert!(frames.iter().any(|frame| matches!(frame, ServerMessage::Presence { user, online: true } if user.username == "corro")));
+
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    let usernames: Vec<&str> = online.iter().map(|user| user.username.as_str()).collect();
+    assert_eq!(usernames, ["corro", "ferris"]);
+
+    // closing one tab keeps corro online and in the room
+    first_tab.close(None).await.unwrap();
+    send(&mut ferris_socket, &chat(2, "still there?")).await;
+    assert_eq!(line(next_broadcast(&mut second_tab).await).as_deref(), Some("ferris: still there?"));
+    for frame in frames_until_chat(&mut ferris_socket).await {
+        match frame {
+            ServerMessage::Presence { online, .. } => assert!(online, "corro went offline with a tab open"),
+            ServerMessage::Leave { username, .. } => assert_ne!(username, "corro", "corro left with a tab open"),
+            _ => {}
+        }
+    }
+
+    // the last tab takes corro out of the room and offline, announced once each
+    second_tab.close(None).await.unwrap();
+    let mut frames = Vec::new();
+    while !frames.iter().any(|frame| matches!(frame, ServerMessage::Presence { online: false, .. })) {
+        frames.push(next_frame(&mut ferris_socket).await.expect("socket closed"));
+    }
+    send(&mut ferris_socket, &chat(3, "bye")).await;
+    frames.extend(frames_until_chat(&mut ferris_socket).await);
+    let left = ServerMessage::Leave { room: DEFAULT_ROOM.to_string(), username: String::from("corro") };
+    assert_eq!(frames.iter().filter(|frame| **frame == left).count(), 1);
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    assert_eq!(online.len(), 1);
+}
+
+#[tokio::test]
+async fn typing_is_throttled_and_stops_with_the_message() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+
+    let typing = |typing: bool| Some(ServerMessage::Typing { room: DEFAULT_ROOM.to_string(), username: String::from("corro"), typing });
+    send(&mut corro_socket, &ClientMessage::Typing { room: DEFAULT_ROOM.to_string() }).await;
+    send(&mut corro_socket, &ClientMessage::Typing { room: DEFAULT_ROOM.to_string() }).await;
+    send(&mut corro_socket, &chat(1, "hi")).await;
+    assert_eq!(next_message(&mut ferris_socket).await, typing(true));
+    assert_eq!(next_message(&mut ferris_socket).await, typing(false));
+    assert_eq!(line(next_message(&mut ferris_socket).await).as_deref(), Some("corro: hi"));
+}
+
+#[tokio::test]
+async fn read_markers_send_receipts_and_count_unread() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+
+    send(&mut corro_socket, &chat(1, "first")).await;
+    let message_id = match next_message(&mut ferris_socket).await {
+        Some(ServerMessage::Chat { message }) => message.id,
+        frame => panic!("expected chat frame, got {:?}", frame)
+    };
+    send(&mut ferris_socket, &ClientMessage::Read { room: DEFAULT_ROOM.to_string(), message_id }).await;
+    let receipt = Some(ServerMessage::Read { marker: ReadMarker { room: DEFAULT_ROOM.to_string(), username: String::from("ferris"), message_id } });
+    assert_eq!(line(next_broadcast(&mut corro_socket).await).as_deref(), Some("corro: first"));
+    assert_eq!(next_broadcast(&mut corro_socket).await, receipt);
+    // the reader gets the receipt too, so their other tabs follow
+    assert_eq!(next_message(&mut ferris_socket).await, receipt);
+    // markers never move back and ignore messages of other rooms
+    send(&mut ferris_socket, &ClientMessage::Read { room: DEFAULT_ROOM.to_string(), message_id: message_id - 1 }).await;
+    send(&mut ferris_socket, &ClientMessage::Read { room: DEFAULT_ROOM.to_string(), message_id: message_id + 100 }).await;
+
+    send(&mut corro_socket, &chat(2, "second")).await;
+    assert_eq!(line(next_message(&mut ferris_socket).await).as_deref(), Some("corro: second"));
+    let unread: Vec<UnreadCount> = ferris.get("/chat/unread", Token::Auth).await.json();
+    assert_eq!(unread, [UnreadCount { room: DEFAULT_ROOM.to_string(), unread: 1 }]);
+    // corro never read anything, so has no room to count in
+    let unread: Vec<UnreadCount> = corro.get("/chat/unread", Token::Auth).await.json();
+    assert!(unread.is_empty());
+
+    // joining replays the markers after the history
+    let mut late_socket = connect(addr).await;
+    join(&mut late_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(line(next_message(&mut late_socket).await).as_deref(), Some("corro: first"));
+    assert_eq!(line(next_message(&mut late_socket).await).as_deref(), Some("corro: second"));
+    assert_eq!(next_message(&mut late_socket).await, receipt);
+}
+
+#[tokio::test]
+async fn moderators_mute_ban_and_delete_over_the_socket() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+    assert_eq!(next_message(&mut ferris_socket).await, joined("corro"));
+
+    // only admins moderate
+    let mute = ModerationAction::Mute { user: corro_info.uuid.clone(), seconds: 600 };
+    send(&mut corro_socket, &ClientMessage::Moderate { id: 1, action: mute.clone() }).await;
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::Forbidden)));
+
+    send(&mut corro_socket, &chat(2, "spam")).await;
+    assert_eq!(line(next_broadcast(&mut corro_socket).await).as_deref(), Some("corro: spam"));
+    let message_id = match next_message(&mut ferris_socket).await {
+        Some(ServerMessage::Chat { message }) => message.id,
+        frame => panic!("expected chat frame, got {:?}", frame)
+    };
+    send(&mut ferris_socket, &ClientMessage::Moderate { id: 1, action: ModerationAction::DeleteMessage { message_id } }).await;
+    assert_eq!(next_broadcast(&mut ferris_socket).await, Some(ServerMessage::Deleted { message_id }));
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::Deleted { message_id }));
+
+    send(&mut ferris_socket, &ClientMessage::Moderate { id: 2, action: mute }).await;
+    assert!(matches!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::Muted { until: Some(_) })));
+    send(&mut corro_socket, &chat(3, "let me talk")).await;
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::Muted)));
+
+    let ban = ModerationAction::Ban { room: DEFAULT_ROOM.to_string(), user: corro_info.uuid.clone() };
+    send(&mut ferris_socket, &ClientMessage::Moderate { id: 3, action: ban }).await;
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::Banned { room: DEFAULT_ROOM.to_string() }));
+    assert_eq!(next_broadcast(&mut ferris_socket).await, Some(ServerMessage::Leave { room: DEFAULT_ROOM.to_string(), username: String::from("corro") }));
+    send(&mut corro_socket, &ClientMessage::Join { room: DEFAULT_ROOM.to_string() }).await;
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::Banned)));
+
+    // a demoted admin cannot moderate over the socket they opened as admin
+    app.demote("ferris").await;
+    let unban = ModerationAction::Unban { room: DEFAULT_ROOM.to_string(), user: corro_info.uuid.clone() };
+    send(&mut ferris_socket, &ClientMessage::Moderate { id: 4, action: unban }).await;
+    assert_eq!(next_broadcast(&mut ferris_socket).await, Some(ServerMessage::error(ChatErrorType::Forbidden)));
+}
+
+#[tokio::test]
+async fn slow_mode_spaces_out_messages_of_non_admins() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+
+    let slow_mode = ModerationAction::SlowMode { room: DEFAULT_ROOM.to_string(), seconds: 60 };
+    assert_eq!(ferris.post_json("/chat/moderation", &slow_mode, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::SlowMode { room: DEFAULT_ROOM.to_string(), seconds: 60 }));
+
+    send(&mut corro_socket, &chat(1, "first")).await;
+    assert_eq!(line(next_broadcast(&mut corro_socket).await).as_deref(), Some("corro: first"));
+    send(&mut corro_socket, &chat(2, "second")).await;
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::SlowMode)));
+
+    // the role counts as it is now, not as it was when the socket opened
+    app.promote("corro").await;
+    send(&mut corro_socket, &chat(3, "promoted")).await;
+    assert_eq!(line(next_broadcast(&mut corro_socket).await).as_deref(), Some("corro: promoted"));
+    app.demote("corro").await;
+    send(&mut corro_socket, &chat(4, "demoted")).await;
+    assert_eq!(next_broadcast(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::SlowMode)));
+}
+
+#[tokio::test]
+async fn sessions_expire_unless_refreshed() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    let ferris_info: UserInfo = ferris.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+    // both tokens are minted up front, so their expiries do not depend on how long the steps take
+    let now = unix_now();
+    let first_token = requester_token(&app, &ferris_info.uuid, now + 2).await;
+    let refresh_token = requester_token(&app, &ferris_info.uuid, now + 6).await;
+
+    let mut socket = connect(addr).await;
+    assert_eq!(join(&mut socket, &first_token).await, now + 2);
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+
+    // tokens of other users do not extend the session
+    send(&mut socket, &ClientMessage::Refresh { token: corro.requester_token().unwrap().to_string() }).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::InvalidToken)));
+
+    send(&mut socket, &ClientMessage::Refresh { token: refresh_token }).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Session { expires_at: now + 6 }));
+
+    // still open after the first token expired
+    tokio::time::sleep(Duration::from_secs((now + 3).saturating_sub(unix_now()))).await;
+    send(&mut socket, &ClientMessage::Ping).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Pong));
+
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::error(ChatErrorType::TokenExpired)));
+    assert_eq!(next_message(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn deleting_a_user_closes_their_sockets() {
+    let app = TestApp::new().await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    app.promote("ferris").await;
+    let mut corro = app.client();
+    let corro_info: UserInfo = corro.register("corro", "corro@example.com", "unsafe-pass").await.json();
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut ferris_socket).await, joined("ferris"));
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut corro_socket).await, joined("corro"));
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), true)));
+
+    assert_eq!(ferris.delete_text("/user", &corro_info.uuid, Token::Auth).await.status, StatusCode::OK);
+    assert_eq!(next_message(&mut corro_socket).await, Some(ServerMessage::error(ChatErrorType::SessionRevoked)));
+    assert_eq!(next_message(&mut corro_socket).await, None);
+    assert_eq!(next_presence(&mut ferris_socket).await, Some((String::from("corro"), false)));
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    assert_eq!(online.iter().map(|user| user.username.as_str()).collect::<Vec<_>>(), vec!["ferris"]);
+}
+
+#[tokio::test]
+async fn silent_connections_miss_their_heartbeat() {
+    let app = TestApp::with_config(|config| {
+        config.chat.ping_interval = 1;
+        config.chat.pong_timeout = 1;
+    }).await;
+    let mut ferris = app.client();
+    ferris.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let mut corro = app.client();
+    corro.register("corro", "corro@example.com", "unsafe-pass").await;
+    let addr = app.spawn().await;
+
+    let mut ferris_socket = connect(addr).await;
+    join(&mut ferris_socket, ferris.requester_token().unwrap()).await;
+    let mut corro_socket = connect(addr).await;
+    join(&mut corro_socket, corro.requester_token().unwrap()).await;
+
+    // reading answers the pings, corro stops reading and never answers
+    let reading = tokio::time::timeout(Duration::from_millis(3500), async {
+        while next_frame(&mut ferris_socket).await.is_some() {}
+    }).await;
+    assert!(reading.is_err(), "ferris was disconnected");
+    let online: Vec<ChatContact> = ferris.get("/chat/online", Token::Auth).await.json();
+    assert_eq!(online.iter().map(|user| user.username.as_str()).collect::<Vec<_>>(), vec!["ferris"]);
+}
+
+#[tokio::test]
+async fn oversized_frames_close_the_connection() {
+    let app = TestApp::new().await;
+    let mut client = app.client();
+    client.register("ferris", "ferris@example.com", "crabby-pass").await;
+    let addr = app.spawn().await;
+
+    let mut socket = connect(addr).await;
+    join(&mut socket, client.requester_token().unwrap()).await;
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+    let body = "a".repeat(app.state.config.chat.max_frame_bytes);
+    send(&mut socket, &chat(1, &body)).await;
+    assert_eq!(next_message(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn reconnecting_clients_resume_after_their_newest_message() {
+    let app = TestApp::with_config(|config| config.chat.history_limit = 2).await;
+    let mut client = app.client();
+    let ferris_info: UserInfo = client.register("ferris", "ferris@example.com", "crabby-pass").await.json();
+    let ferris = app.state.users.find_by_uuid(&ferris_info.uuid).await.unwrap();
+    let general = app.state.rooms.find_by_name(DEFAULT_ROOM).await.unwrap();
+    let seen = app.state.messages.insert(&ferris, MessageTarget::Room(&general), "seen").await.unwrap();
+    app.state.messages.insert(&ferris, MessageTarget::Room(&general), "missed one").await.unwrap();
+    app.state.messages.insert(&ferris, MessageTarget::Room(&general), "missed two").await.unwrap();
+    let addr = app.spawn().await;
+
+    let resume = |after: i64| ClientMessage::Auth {
+        version: CHAT_PROTOCOL_VERSION,
+        token: client.requester_token().unwrap().to_string(),
+        resume: vec![RoomResume { room: DEFAULT_ROOM.to_string(), after: Some(after) }]
+    };
+    let mut socket = connect(addr).await;
+    send(&mut socket, &resume(seen.id)).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Ack { id: None, message_id: None }));
+    assert!(matches!(next_message(&mut socket).await, Some(ServerMessage::Session { .. })));
+    assert_eq!(line(next_message(&mut socket).await).as_deref(), Some("ferris: missed one"));
+    assert_eq!(line(next_message(&mut socket).await).as_deref(), Some("ferris: missed two"));
+    assert_eq!(next_message(&mut socket).await, joined("ferris"));
+
+    // more missed messages than the history limit cannot be replayed
+    let mut socket = connect(addr).await;
+    send(&mut socket, &resume(seen.id - 1)).await;
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Ack { id: None, message_id: None }));
+    assert!(matches!(next_message(&mut socket).await, Some(ServerMessage::Session { .. })));
+    assert_eq!(next_message(&mut socket).await, Some(ServerMessage::Resync { room: Some(DEFAULT_ROOM.to_string()), skipped: 3 }));
+}
@@ -1,12 +1,25 @@
+use std::fmt;
+
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+use crate::user::REDACTED;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct AuthToken {
     pub access_token: String,
     pub token_type: String
 }
 
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthToken")
+            .field("access_token", &REDACTED)
+            .field("token_type", &self.token_type)
+            .finish()
+    }
+}
+
 impl AuthToken {
     pub fn new(access_token: String) -> Self {
         Self {
@@ -64,6 +77,11 @@ impl AuthError {
             AuthErrorType::InvalidEmail => (StatusCode::BAD_REQUEST, String::from("Email address is invalid")),
             AuthErrorType::ResetLinkInvalid => (StatusCode::BAD_REQUEST, String::from("Reset link is invalid")),
             AuthErrorType::PasswordDoesNotMatch => (StatusCode::BAD_REQUEST, String::from("Password does not match")),
+            AuthErrorType::InvalidUsername => (StatusCode::BAD_REQUEST, String::from("Username is invalid")),
+            AuthErrorType::UsernameTaken => (StatusCode::CONFLICT, String::from("Username taken")),
+            AuthErrorType::EmailTaken => (StatusCode::CONFLICT, String::from("Email taken")),
+            AuthErrorType::ServiceUnavailable => (StatusCode::SERVICE_UNAVAILABLE, String::from("Service unavailable")),
+            AuthErrorType::NotFound => (StatusCode::NOT_FOUND, String::from("Resource not found")),
         };
         Self {
             status,
@@ -73,6 +91,10 @@ impl AuthError {
             }
         }
     }
+    pub fn with_message(mut self, message: String) -> Self {
+        self.body.message = message;
+        self
+    }
     pub fn body(&self) -> AuthErrorBody {
         self.body.to_owned()
     }
@@ -94,7 +116,12 @@ pub enum AuthErrorType {
     MissingFields,
     InvalidEmail,
     ResetLinkInvalid,
-    PasswordDoesNotMatch
+    PasswordDoesNotMatch,
+    InvalidUsername,
+    UsernameTaken,
+    EmailTaken,
+    ServiceUnavailable,
+    NotFound
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
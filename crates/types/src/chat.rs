@@ -0,0 +1,288 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// number of messages replayed after the websocket handshake unless configured otherwise
+pub const CHAT_HISTORY_DEFAULT_LIMIT: u32 = 50;
+// largest page GET /chat/history returns
+pub const CHAT_HISTORY_MAX_LIMIT: u32 = 200;
+// public room every connection joins after the handshake
+pub const DEFAULT_ROOM: &str = "general";
+// longest room name, matches VARCHAR(32) column
+pub const ROOM_NAME_MAX_LENGTH: usize = 32;
+
+// trim and lowercase a room name, None if it is empty, too long or has characters other than
+// ASCII letters, digits, '-' and '_'
+pub fn normalize_room_name(name: &str) -> Option<String> {
+    let name = name.trim().to_ascii_lowercase();
+    let valid = !name.is_empty()
+        && name.len() <= ROOM_NAME_MAX_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(name)
+}
+
+// Chat room, private rooms are only visible to and joinable by their members
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Room {
+    pub id: i64,
+    pub name: String,
+    pub is_private: bool,
+    // seconds between two messages of a user, 0 when slow mode is off
+    pub slow_mode_secs: i64
+}
+
+// body of POST /chat/rooms, the creator becomes the first member
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct CreateRoom {
+    pub name: String,
+    pub is_private: bool
+}
+
+// user direct messages were exchanged with, also used for online users
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChatContact {
+    pub uuid: String,
+    pub username: String
+}
+
+// Moderation command of an admin, sent over the websocket or to POST /chat/moderation
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModerationAction {
+    // room message or direct message, removed for everyone
+    DeleteMessage { message_id: i64 },
+    // user is the uuid, 0 seconds lifts the mute
+    Mute { user: String, seconds: u64 },
+    // the user leaves the room and cannot join or read it again until unbanned
+    Ban { room: String, user: String },
+    Unban { room: String, user: String },
+    // 0 seconds turns slow mode off, admins are never slowed down
+    SlowMode { room: String, seconds: u32 }
+}
+
+// active mute listed by GET /chat/moderation
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChatMute {
+    pub uuid: String,
+    pub username: String,
+    // unix timestamp in seconds
+    pub muted_until: i64
+}
+
+// room ban listed by GET /chat/moderation
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct RoomBan {
+    pub room: String,
+    pub uuid: String,
+    pub username: String
+}
+
+// active mutes and bans, returned by GET /chat/moderation
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct ModerationOverview {
+    pub mutes: Vec<ChatMute>,
+    pub bans: Vec<RoomBan>
+}
+
+// newest message of a room a user has read
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ReadMarker {
+    pub room: String,
+    pub username: String,
+    pub message_id: i64
+}
+
+// messages of other users after the read marker of a room, returned by GET /chat/unread
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct UnreadCount {
+    pub room: String,
+    pub unread: i64
+}
+
+// Persisted chat line together with the current username of its author
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChatMessage {
+    // increasing id, used as the cursor when scrolling back
+    pub id: i64,
+    pub author_uuid: String,
+    pub author: String,
+    // set for room messages
+    pub room: Option<String>,
+    // set for direct messages
+    pub recipient_uuid: Option<String>,
+    pub body: String,
+    // unix timestamp in seconds
+    pub created_at: i64
+}
+
+impl fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.author, self.body)
+    }
+}
+
+// query parameters of GET /chat/history, unset before returns the newest messages
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct ChatHistoryQuery {
+    // room to read, defaults to the default room
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    // uuid of the other user, reads direct messages instead of a room
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with: Option<String>,
+    // only messages with a smaller id are returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>
+}
+
+// typing frames of a connection closer together than this are ignored
+pub const TYPING_THROTTLE_SECS: u64 = 2;
+// typing stops when no typing frame was accepted for this long
+pub const TYPING_TIMEOUT_SECS: u64 = 5;
+
+// version of the websocket protocol, a client sending another version is rejected
+pub const CHAT_PROTOCOL_VERSION: u32 = 3;
+
+// room of a previous connection, the messages after the newest one the client has are replayed
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RoomResume {
+    pub room: String,
+    // None replays the newest messages like a new join
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<i64>
+}
+
+// Frame sent by chat clients as JSON, tagged by its type
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    // must be the first frame, token is the requester token
+    // a reconnecting client lists its rooms in resume, they are joined instead of the default room
+    Auth {
+        version: u32,
+        token: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        resume: Vec<RoomResume>
+    },
+    // newer requester token of the same user, extends the session to its expiry
+    Refresh { token: String },
+    // start receiving a room, its history is replayed first
+    Join { room: String },
+    Leave { room: String },
+    // id is picked by the client and echoed in the ack, the room must be joined
+    Chat { id: u64, room: String, body: String },
+    // message to one user by uuid, delivered to every connection of both users
+    Direct { id: u64, to: String, body: String },
+    // the user is typing in a joined room, repeat while typing, extra frames are throttled
+    Typing { room: String },
+    // every message of a joined room up to message_id was read, markers never move back
+    Read { room: String, message_id: i64 },
+    // admins only, acknowledged with the id like chat frames
+    Moderate { id: u64, action: ModerationAction },
+    Ping
+}
+
+// Frame sent by the chat server as JSON, tagged by its type
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    // replayed history and live messages
+    Chat { message: ChatMessage },
+    Join { room: String, username: String },
+    Leave { room: String, username: String },
+    // sent to every connection when a user opens their first or closes their last connection
+    Presence { user: ChatContact, online: bool },
+    // typing stopped when the user sent a message, left or stopped sending typing frames
+    Typing { room: String, username: String, typing: bool },
+    // read receipt, the read markers of a room are also sent after its history when joining
+    Read { marker: ReadMarker },
+    // a moderator removed the message
+    Deleted { message_id: i64 },
+    // sent to the room when its slow mode changes
+    SlowMode { room: String, seconds: u32 },
+    // sent to the muted user, None when the mute was lifted
+    Muted { until: Option<i64> },
+    // sent to the banned user, who no longer receives the room
+    Banned { room: String },
+    // the connection fell behind and skipped frames of the room, or direct frames when room is None,
+    // the client reloads the history instead of trusting what it has
+    Resync { room: Option<String>, skipped: u64 },
+    Error { error: ChatErrorType, message: String },
+    // sent after the auth ack and every accepted refresh, the socket is closed at expires_at
+    // unless a refreshed token arrives first, unix timestamp in seconds
+    Session { expires_at: u64 },
+    // auth succeeded when id is None, otherwise the chat frame with the id was stored as message_id
+    Ack { id: Option<u64>, message_id: Option<i64> },
+    Pong
+}
+
+impl ServerMessage {
+    pub fn error(error: ChatErrorType) -> Self {
+        ServerMessage::Error { error, message: error.to_string() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatErrorType {
+    UnsupportedVersion,
+    InvalidToken,
+    // frame was not valid JSON or not allowed at this point
+    InvalidFrame,
+    EmptyMessage,
+    // room does not exist or is private and the user is no member
+    RoomNotFound,
+    // chat or leave frame for a room that was not joined
+    NotInRoom,
+    // recipient of a direct message or moderated user does not exist
+    UserNotFound,
+    MessageNotFound,
+    // moderation command of a user who is no admin
+    Forbidden,
+    Muted,
+    // the user is banned from the room
+    Banned,
+    // the slow mode of the room does not allow another message yet
+    SlowMode,
+    // message could not be stored and was not delivered
+    Unavailable,
+    // the token expired without a refresh, sent before the socket is closed
+    TokenExpired,
+    // the account was deleted or disabled, sent before the socket is closed
+    SessionRevoked,
+    // no auth frame arrived in time, sent before the socket is closed
+    HandshakeTimeout
+}
+
+impl fmt::Display for ChatErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatErrorType::UnsupportedVersion => write!(f, "Unsupported protocol version, expected {}", CHAT_PROTOCOL_VERSION),
+            ChatErrorType::InvalidToken => write!(f, "Invalid or expired token"),
+            ChatErrorType::InvalidFrame => write!(f, "Invalid frame"),
+            ChatErrorType::EmptyMessage => write!(f, "Message cannot be empty"),
+            ChatErrorType::RoomNotFound => write!(f, "Room not found"),
+            ChatErrorType::NotInRoom => write!(f, "Join the room first"),
+            ChatErrorType::UserNotFound => write!(f, "User not found"),
+            ChatErrorType::MessageNotFound => write!(f, "Message not found"),
+            ChatErrorType::Forbidden => write!(f, "Only admins can moderate the chat"),
+            ChatErrorType::Muted => write!(f, "You are muted"),
+            ChatErrorType::Banned => write!(f, "You are banned from this room"),
+            ChatErrorType::SlowMode => write!(f, "Slow mode is on, wait before sending another message"),
+            ChatErrorType::Unavailable => write!(f, "Message could not be delivered, try again"),
+            ChatErrorType::TokenExpired => write!(f, "Session expired, log in again"),
+            ChatErrorType::SessionRevoked => write!(f, "Session was ended"),
+            ChatErrorType::HandshakeTimeout => write!(f, "Timed out waiting for authentication")
+        }
+    }
+}
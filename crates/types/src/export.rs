@@ -0,0 +1,12 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+// Everything the server holds about a user, returned by GET /user/me/export
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct UserExport {
+    // unix timestamp the export was built at
+    pub exported_at: i64,
+    // one section per exporter keyed by its name, e.g. "account"
+    pub sections: BTreeMap<String, serde_json::Value>
+}
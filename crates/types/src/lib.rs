@@ -1,2 +1,7 @@
 pub mod user;
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod username;
+pub mod problem;
+pub mod page;
+pub mod export;
+pub mod chat;
\ No newline at end of file
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+// One page of a list together with the total number of matching items
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // 1 based page number
+    pub page: u32,
+    pub limit: u32,
+    // number of items matching the query across all pages
+    pub total: u64
+}
+
+impl<T> Page<T> {
+    // number of pages needed for total items, at least 1 so an empty list still has a page
+    pub fn page_count(&self) -> u32 {
+        if self.limit == 0 {
+            return 1;
+        }
+        let pages = self.total.div_ceil(u64::from(self.limit)).max(1);
+        u32::try_from(pages).unwrap_or(u32::MAX)
+    }
+    pub fn has_previous(&self) -> bool {
+        self.page > 1
+    }
+    pub fn has_next(&self) -> bool {
+        self.page < self.page_count()
+    }
+}
+
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            page: 1,
+            limit: 0,
+            total: 0
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthErrorType;
+
+// media type for RFC 7807 error responses
+pub const PROBLEM_JSON: &str = "application/problem+json";
+// header carrying the request ID on every response
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// RFC 7807 problem details body returned for every error response
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<AuthErrorType>
+}
@@ -6,14 +6,34 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "sqlx")]
 use sqlx::{any::AnyRow, FromRow, Row};
 
-#[derive(Clone, Debug, Serialize)]
+// placeholder written instead of secrets in Debug and Display output
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Clone, Serialize)]
 pub struct User {
     pub id: i32,
     pub uuid: String,
     pub username: String,
     pub pass: String,
     pub email: EmailAddress,
-    pub is_admin: bool
+    pub is_admin: bool,
+    pub is_disabled: bool,
+    pub is_verified: bool
+}
+
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("uuid", &self.uuid)
+            .field("username", &self.username)
+            .field("pass", &REDACTED)
+            .field("email", &self.email)
+            .field("is_admin", &self.is_admin)
+            .field("is_disabled", &self.is_disabled)
+            .field("is_verified", &self.is_verified)
+            .finish()
+    }
 }
 
 #[cfg(feature = "sqlx")]
@@ -33,14 +53,16 @@ impl <'r> FromRow<'r, AnyRow> for User {
             }
         };
         let is_admin: bool = row.try_get("is_admin")?;
+        let is_disabled: bool = row.try_get("is_disabled")?;
+        let is_verified: bool = row.try_get("is_verified")?;
 
         Ok(Self {
-            id, uuid, username, pass, email, is_admin
+            id, uuid, username, pass, email, is_admin, is_disabled, is_verified
         })
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct RegisterUser {
     pub username: String,
     pub pass: String,
@@ -49,7 +71,17 @@ pub struct RegisterUser {
 
 impl fmt::Display for RegisterUser {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Username: {}\nPass: {}\nEmail: {}", self.username, self.pass, self.email)
+        write!(f, "Username: {}\nPass: {}\nEmail: {}", self.username, REDACTED, self.email)
+    }
+}
+
+impl fmt::Debug for RegisterUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisterUser")
+            .field("username", &self.username)
+            .field("pass", &REDACTED)
+            .field("email", &self.email)
+            .finish()
     }
 }
 
@@ -66,7 +98,7 @@ impl RegisterUser {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct LoginUser {
     pub username: String,
     pub pass: String
@@ -74,7 +106,16 @@ pub struct LoginUser {
 
 impl fmt::Display for LoginUser {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Username: {}\nPass: {}", self.username, self.pass)
+        write!(f, "Username: {}\nPass: {}", self.username, REDACTED)
+    }
+}
+
+impl fmt::Debug for LoginUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginUser")
+            .field("username", &self.username)
+            .field("pass", &REDACTED)
+            .finish()
     }
 }
 
@@ -90,12 +131,21 @@ impl LoginUser {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ResetUser {
     pub email_address: EmailAddress,
     pub pass: String
 }
 
+impl fmt::Debug for ResetUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResetUser")
+            .field("email_address", &self.email_address)
+            .field("pass", &REDACTED)
+            .finish()
+    }
+}
+
 impl ResetUser {
     pub fn new(&self) -> ResetUser {
         Self {
@@ -114,18 +164,40 @@ impl ResetUser {
     }
 }
 
+// body of DELETE /user/me, the password is re-entered to confirm the deletion
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct DeleteAccount {
+    pub pass: String
+}
+
+impl fmt::Debug for DeleteAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeleteAccount")
+            .field("pass", &REDACTED)
+            .finish()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
 pub struct UserInfo {
     pub uuid: String,
     pub username: String,
     pub email: String,
-    pub is_admin: bool
+    pub is_admin: bool,
+    #[serde(default)]
+    pub is_disabled: bool,
+    #[serde(default)]
+    pub is_verified: bool,
+    // unix timestamp of soft deletion, the account can be restored until it is erased
+    #[serde(default)]
+    pub deleted_at: Option<i64>
 }
 
 impl fmt::Display for UserInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "UUID: {}\nUsername: {}\nEmail: {}\nIs Admin: {}", self.uuid, self.username, self.email, self.is_admin)
+        write!(f, "UUID: {}\nUsername: {}\nEmail: {}\nIs Admin: {}\nIs Disabled: {}\nIs Verified: {}",
+            self.uuid, self.username, self.email, self.is_admin, self.is_disabled, self.is_verified)
     }
 }
 
@@ -135,7 +207,11 @@ impl UserInfo {
             uuid: user.uuid,
             username: user.username,
             email: user.email.to_string(),
-            is_admin: user.is_admin
+            is_admin: user.is_admin,
+            is_disabled: user.is_disabled,
+            is_verified: user.is_verified,
+            // users are only loaded while they are not deleted
+            deleted_at: None
         }
     }
     pub fn new() -> Self {
@@ -143,7 +219,73 @@ impl UserInfo {
             uuid: String::new(),
             username: String::new(),
             email: String::new(),
-            is_admin: false
+            is_admin: false,
+            is_disabled: false,
+            is_verified: false,
+            deleted_at: None
         }
     }
-}
\ No newline at end of file
+}
+
+// default number of users per page of the admin user list
+pub const USER_PAGE_DEFAULT_LIMIT: u32 = 25;
+// largest page of the admin user list a client may request
+pub const USER_PAGE_MAX_LIMIT: u32 = 100;
+
+// column the admin user list is sorted by
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UserSort {
+    // registration order
+    #[default]
+    Created,
+    Username,
+    Email,
+    Admin
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc
+}
+
+// query parameters of GET /user/all, unset filters match every user
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct UserListQuery {
+    // 1 based page number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    // case-insensitive substring of username or email
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_admin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_disabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_verified: Option<bool>,
+    // unlike the other filters unset hides soft deleted users, erased users are never listed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_deleted: Option<bool>,
+    pub sort: UserSort,
+    pub order: SortOrder
+}
+
+impl UserListQuery {
+    pub fn page(&self) -> u32 {
+        self.page.unwrap_or(1)
+    }
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(USER_PAGE_DEFAULT_LIMIT)
+    }
+    // rows skipped before the requested page
+    pub fn offset(&self) -> u64 {
+        u64::from(self.page().saturating_sub(1)) * u64::from(self.limit())
+    }
+}
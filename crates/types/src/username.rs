@@ -0,0 +1,80 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+// shortest username accepted at registration
+pub const USERNAME_MIN_LENGTH: usize = 3;
+// longest username accepted at registration, matches VARCHAR(24) column
+pub const USERNAME_MAX_LENGTH: usize = 24;
+// names that cannot be registered unless overridden by the server configuration
+pub const DEFAULT_RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "root",
+    "system",
+    "support",
+    "moderator",
+    "null",
+    "undefined",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UsernameError {
+    TooShort,
+    TooLong,
+    InvalidCharacters,
+    InvalidStart,
+    Reserved
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::TooShort => write!(f, "Username must be at least {} characters", USERNAME_MIN_LENGTH),
+            UsernameError::TooLong => write!(f, "Username must be at most {} characters", USERNAME_MAX_LENGTH),
+            UsernameError::InvalidCharacters => write!(f, "Username may only contain letters, numbers, '_', '-' and '.'"),
+            UsernameError::InvalidStart => write!(f, "Username must start with a letter or number"),
+            UsernameError::Reserved => write!(f, "Username is reserved"),
+        }
+    }
+}
+
+// trim surrounding whitespace and apply Unicode NFKC normalization
+pub fn normalize_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+// key used for case-insensitive username comparisons and uniqueness
+pub fn username_key(username: &str) -> String {
+    normalize_username(username).to_lowercase()
+}
+
+// check whether a username matches any name in the reserved list, ignoring case
+pub fn is_reserved_username<S: AsRef<str>>(username: &str, reserved: &[S]) -> bool {
+    let key = username_key(username);
+    reserved.iter().any(|name| username_key(name.as_ref()) == key)
+}
+
+// validate a username against the registration rules and return its normalized form
+pub fn validate_username<S: AsRef<str>>(username: &str, reserved: &[S]) -> Result<String, UsernameError> {
+    let normalized = normalize_username(username);
+    let length = normalized.chars().count();
+    if length < USERNAME_MIN_LENGTH {
+        return Err(UsernameError::TooShort);
+    }
+    // lowercasing can grow the name, e.g. 'İ' becomes two characters, and the key has to fit the column as well
+    if length > USERNAME_MAX_LENGTH || username_key(&normalized).chars().count() > USERNAME_MAX_LENGTH {
+        return Err(UsernameError::TooLong);
+    }
+    if !normalized.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.') {
+        return Err(UsernameError::InvalidCharacters);
+    }
+    if !normalized.chars().next().is_some_and(char::is_alphanumeric) {
+        return Err(UsernameError::InvalidStart);
+    }
+    if is_reserved_username(&normalized, reserved) {
+        return Err(UsernameError::Reserved);
+    }
+    Ok(normalized)
+}
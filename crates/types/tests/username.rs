@@ -0,0 +1,59 @@
+use types::username::{is_reserved_username, username_key, validate_username, UsernameError, DEFAULT_RESERVED_USERNAMES, USERNAME_MAX_LENGTH};
+
+#[test]
+fn valid_usernames_are_trimmed() {
+    assert_eq!(validate_username("  ferris  ", DEFAULT_RESERVED_USERNAMES), Ok(String::from("ferris")));
+    assert_eq!(validate_username("Ferris_the.crab-1", DEFAULT_RESERVED_USERNAMES), Ok(String::from("Ferris_the.crab-1")));
+    assert_eq!(validate_username("Ärger", DEFAULT_RESERVED_USERNAMES), Ok(String::from("Ärger")));
+}
+
+#[test]
+fn length_is_limited() {
+    assert_eq!(validate_username("ab", DEFAULT_RESERVED_USERNAMES), Err(UsernameError::TooShort));
+    assert_eq!(validate_username("   ab   ", DEFAULT_RESERVED_USERNAMES), Err(UsernameError::TooShort));
+    let longest = "f".repeat(USERNAME_MAX_LENGTH);
+    assert!(validate_username(&longest, DEFAULT_RESERVED_USERNAMES).is_ok());
+    assert_eq!(validate_username(&format!("{longest}f"), DEFAULT_RESERVED_USERNAMES), Err(UsernameError::TooLong));
+}
+
+#[test]
+fn key_growing_past_the_column_is_too_long() {
+    // 'İ' lowercases to two characters
+    let username = format!("İ{}", "f".repeat(USERNAME_MAX_LENGTH - 1));
+    assert_eq!(username.chars().count(), USERNAME_MAX_LENGTH);
+    assert_eq!(username_key(&username).chars().count(), USERNAME_MAX_LENGTH + 1);
+    assert_eq!(validate_username(&username, DEFAULT_RESERVED_USERNAMES), Err(UsernameError::TooLong));
+    assert!(validate_username(&username[..username.len() - 1], DEFAULT_RESERVED_USERNAMES).is_ok());
+}
+
+#[test]
+fn characters_are_restricted() {
+    for username in ["fer ris", "ferris!", "fer@ris", "ferris🦀"] {
+        assert_eq!(validate_username(username, DEFAULT_RESERVED_USERNAMES), Err(UsernameError::InvalidCharacters), "{username}");
+    }
+    for username in ["_ferris", "-ferris", ".ferris"] {
+        assert_eq!(validate_username(username, DEFAULT_RESERVED_USERNAMES), Err(UsernameError::InvalidStart), "{username}");
+    }
+}
+
+#[test]
+fn usernames_are_nfkc_normalized() {
+    // fullwidth letters and ligatures fold to their plain forms
+    assert_eq!(validate_username("ｆｅｒｒｉｓ", DEFAULT_RESERVED_USERNAMES), Ok(String::from("ferris")));
+    assert_eq!(validate_username("ﬁsh", DEFAULT_RESERVED_USERNAMES), Ok(String::from("fish")));
+    // composed and decomposed forms share a key
+    assert_eq!(username_key("A\u{308}rger"), username_key("Ärger"));
+    assert_eq!(username_key(" FERRIS "), "ferris");
+}
+
+#[test]
+fn reserved_names_are_rejected_in_any_form() {
+    for username in ["admin", "ADMIN", "Root", "ａｄｍｉｎ"] {
+        assert_eq!(validate_username(username, DEFAULT_RESERVED_USERNAMES), Err(UsernameError::Reserved), "{username}");
+    }
+    // the configured list replaces the defaults
+    assert!(validate_username("admin", &["crab"]).is_ok());
+    assert_eq!(validate_username("CRAB", &["crab"]), Err(UsernameError::Reserved));
+    assert!(is_reserved_username("ferris", &["Ferris"]));
+    assert!(!is_reserved_username("ferris", &[] as &[&str]));
+}
@@ -0,0 +1,6 @@
+-- Add normalized lookup column for case-insensitive usernames
+ALTER TABLE "users" ADD COLUMN username_key VARCHAR(24);
+-- Backfill existing rows, fails if two usernames collide once normalized
+UPDATE "users" SET username_key = LOWER(NORMALIZE(TRIM(username), NFKC));
+ALTER TABLE "users" ALTER COLUMN username_key SET NOT NULL;
+CREATE UNIQUE INDEX users_username_key_idx ON "users" (username_key);
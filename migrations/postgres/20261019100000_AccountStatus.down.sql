@@ -0,0 +1,3 @@
+-- Remove account status flags
+ALTER TABLE "users" DROP COLUMN is_verified;
+ALTER TABLE "users" DROP COLUMN is_disabled;
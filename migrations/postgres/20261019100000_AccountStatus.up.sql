@@ -0,0 +1,3 @@
+-- Add account status flags used to filter the admin user list
+ALTER TABLE "users" ADD COLUMN is_disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE "users" ADD COLUMN is_verified BOOLEAN NOT NULL DEFAULT FALSE;
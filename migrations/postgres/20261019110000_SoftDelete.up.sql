@@ -0,0 +1,3 @@
+-- Unix timestamps of soft deletion and of permanent erasure after the grace period
+ALTER TABLE "users" ADD COLUMN deleted_at BIGINT;
+ALTER TABLE "users" ADD COLUMN erased_at BIGINT;
@@ -0,0 +1,3 @@
+-- Remove chat history
+DROP INDEX messages_author_uuid_idx;
+DROP TABLE "messages";
@@ -0,0 +1,8 @@
+-- Remove rooms and direct messages, room messages stay in the single chat
+DELETE FROM "messages" WHERE recipient_uuid IS NOT NULL;
+DROP INDEX messages_recipient_uuid_idx;
+DROP INDEX messages_room_id_idx;
+ALTER TABLE "messages" DROP COLUMN recipient_uuid;
+ALTER TABLE "messages" DROP COLUMN room_id;
+DROP TABLE "room_members";
+DROP TABLE "rooms";
@@ -0,0 +1,8 @@
+-- Newest message of a room each user has read
+CREATE TABLE "read_markers" (
+    user_uuid VARCHAR(36) NOT NULL,
+    room_id BIGINT NOT NULL REFERENCES "rooms" (id),
+    message_id BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (user_uuid, room_id)
+);
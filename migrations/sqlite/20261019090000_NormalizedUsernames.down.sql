@@ -0,0 +1,3 @@
+-- Remove normalized lookup column
+DROP INDEX users_username_key_idx;
+ALTER TABLE "users" DROP COLUMN username_key;
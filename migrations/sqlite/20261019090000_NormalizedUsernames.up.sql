@@ -0,0 +1,5 @@
+-- Add normalized lookup column for case-insensitive usernames
+ALTER TABLE "users" ADD COLUMN username_key VARCHAR(24);
+-- Backfill existing rows, SQLite has no NFKC so only ASCII case folding is applied here
+UPDATE "users" SET username_key = LOWER(TRIM(username));
+CREATE UNIQUE INDEX users_username_key_idx ON "users" (username_key);
@@ -0,0 +1,3 @@
+-- Remove soft deletion timestamps, soft deleted users become active again
+ALTER TABLE "users" DROP COLUMN erased_at;
+ALTER TABLE "users" DROP COLUMN deleted_at;
@@ -0,0 +1,8 @@
+-- Chat lines kept for replay and history, users are never hard deleted so author_uuid always resolves
+CREATE TABLE "messages" (
+    id INTEGER PRIMARY KEY,
+    author_uuid VARCHAR(36) NOT NULL,
+    body TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+CREATE INDEX messages_author_uuid_idx ON "messages" (author_uuid);
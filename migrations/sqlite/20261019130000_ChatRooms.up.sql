@@ -0,0 +1,20 @@
+-- Named chat rooms, private rooms are only visible to their members
+CREATE TABLE "rooms" (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(32) NOT NULL UNIQUE,
+    is_private BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by VARCHAR(36),
+    created_at BIGINT NOT NULL
+);
+CREATE TABLE "room_members" (
+    room_id INTEGER NOT NULL REFERENCES "rooms" (id),
+    user_uuid VARCHAR(36) NOT NULL,
+    PRIMARY KEY (room_id, user_uuid)
+);
+INSERT INTO "rooms" (name, is_private, created_at) VALUES ('general', FALSE, 0);
+-- Messages go to a room or, for direct messages, to a recipient. Existing messages move to general
+ALTER TABLE "messages" ADD COLUMN room_id INTEGER;
+ALTER TABLE "messages" ADD COLUMN recipient_uuid VARCHAR(36);
+UPDATE "messages" SET room_id = (SELECT id FROM "rooms" WHERE name = 'general');
+CREATE INDEX messages_room_id_idx ON "messages" (room_id, id);
+CREATE INDEX messages_recipient_uuid_idx ON "messages" (recipient_uuid);
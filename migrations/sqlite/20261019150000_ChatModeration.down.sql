@@ -0,0 +1,3 @@
+ALTER TABLE "rooms" DROP COLUMN slow_mode_secs;
+DROP TABLE "room_bans";
+DROP TABLE "chat_mutes";
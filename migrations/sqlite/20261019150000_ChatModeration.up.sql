@@ -0,0 +1,17 @@
+-- Users who cannot send chat messages until muted_until
+CREATE TABLE "chat_mutes" (
+    user_uuid VARCHAR(36) PRIMARY KEY,
+    muted_until BIGINT NOT NULL,
+    muted_by VARCHAR(36) NOT NULL,
+    created_at BIGINT NOT NULL
+);
+-- Users who cannot join or read a room
+CREATE TABLE "room_bans" (
+    room_id INTEGER NOT NULL REFERENCES "rooms" (id),
+    user_uuid VARCHAR(36) NOT NULL,
+    banned_by VARCHAR(36) NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (room_id, user_uuid)
+);
+-- Seconds a user has to wait between two messages in the room, 0 disables slow mode
+ALTER TABLE "rooms" ADD COLUMN slow_mode_secs BIGINT NOT NULL DEFAULT 0;